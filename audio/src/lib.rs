@@ -6,18 +6,47 @@ pub mod voice;
 pub mod upd;
 pub mod playback;
 
+pub use upd::CodecOptions;
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crossbeam_channel::bounded;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 const FRAME_MS: usize = 20;
 const BUFFER_FRAMES: usize = 4;
 const VOLUME_GAIN: f32 = 1.5;
 
+/// Silences the microphone without stopping the streams; clones share
+/// the same switch
+#[derive(Clone, Default)]
+pub struct MuteSwitch(Arc<AtomicBool>);
+
+impl MuteSwitch {
+    pub fn set(&self, muted: bool) {
+        self.0.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Devices and codec settings; the defaults are what `start` uses
+#[derive(Debug, Clone, Default)]
+pub struct VoiceOptions {
+    /// Input device by name; None picks "pulse" or "default"
+    pub input_device: Option<String>,
+    /// Output device by name; None uses the system default
+    pub output_device: Option<String>,
+    pub codec: CodecOptions,
+}
+
 pub struct VoiceSession {
     _input_stream: cpal::Stream,
     _output_stream: cpal::Stream,
+    mute: MuteSwitch,
 }
 
 impl VoiceSession {
@@ -29,19 +58,41 @@ impl VoiceSession {
         sender_id: u32,
         send_addr: &str,
         recv_bind: &str,
+    ) -> anyhow::Result<Self> {
+        Self::start_with(sender_id, send_addr, recv_bind, &VoiceOptions::default())
+    }
+
+    /// Like `start`, with the devices and codec settings in `options`
+    pub fn start_with(
+        sender_id: u32,
+        send_addr: &str,
+        recv_bind: &str,
+        options: &VoiceOptions,
     ) -> anyhow::Result<Self> {
         let host = cpal::default_host();
 
-        let input = host
-            .input_devices()?
-            .find(|d| {
-                let n = d.name().unwrap_or_default().to_lowercase();
-                n == "pulse" || n == "default"
-            })
-            .ok_or_else(|| anyhow::anyhow!("No input device"))?;
+        let input = match &options.input_device {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.name().is_ok_and(|n| &n == name))
+                .ok_or_else(|| anyhow::anyhow!("No input device named {}", name))?,
+            None => host
+                .input_devices()?
+                .find(|d| {
+                    let n = d.name().unwrap_or_default().to_lowercase();
+                    n == "pulse" || n == "default"
+                })
+                .ok_or_else(|| anyhow::anyhow!("No input device"))?,
+        };
 
-        let output = host.default_output_device()
-            .ok_or_else(|| anyhow::anyhow!("No output device"))?;
+        let output = match &options.output_device {
+            Some(name) => host
+                .output_devices()?
+                .find(|d| d.name().is_ok_and(|n| &n == name))
+                .ok_or_else(|| anyhow::anyhow!("No output device named {}", name))?,
+            None => host.default_output_device()
+                .ok_or_else(|| anyhow::anyhow!("No output device"))?,
+        };
 
         let input_cfg = input.default_input_config()?;
         let output_cfg = output.default_output_config()?;
@@ -60,6 +111,7 @@ impl VoiceSession {
             send_addr,
             recv_bind,
             tx_play.clone(),
+            &options.codec,
         )?;
 
         // Input stream config
@@ -76,11 +128,18 @@ impl VoiceSession {
         };
 
         // Build input stream
+        let mute = MuteSwitch::default();
+        let capture_mute = mute.clone();
         let mut capture_acc: Vec<f32> = Vec::new();
         let input_stream = input
             .build_input_stream(
                 &input_config,
                 move |data: &[f32], _| {
+                    // Muted: send nothing at all
+                    if capture_mute.is_muted() {
+                        capture_acc.clear();
+                        return;
+                    }
                     for frame in data.chunks(channels) {
                         let sum: f32 = frame.iter().sum();
                         let sample = (sum / channels as f32) * VOLUME_GAIN;
@@ -143,10 +202,51 @@ impl VoiceSession {
         Ok(VoiceSession {
             _input_stream: input_stream,
             _output_stream: output_stream,
+            mute,
         })
     }
+
+    pub fn mute_switch(&self) -> MuteSwitch {
+        self.mute.clone()
+    }
+}
+
+/// Audio devices by name, as `VoiceOptions` takes them
+#[derive(Debug, Clone, Default)]
+pub struct DeviceList {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// What `start` picks when no device is configured
+    pub default_input: Option<String>,
+    pub default_output: Option<String>,
+}
+
+pub fn list_devices() -> anyhow::Result<DeviceList> {
+    let host = cpal::default_host();
+    let inputs: Vec<String> = host.input_devices()?.filter_map(|d| d.name().ok()).collect();
+    let outputs: Vec<String> = host.output_devices()?.filter_map(|d| d.name().ok()).collect();
+    let default_input = inputs
+        .iter()
+        .find(|n| {
+            let n = n.to_lowercase();
+            n == "pulse" || n == "default"
+        })
+        .cloned();
+    let default_output = host.default_output_device().and_then(|d| d.name().ok());
+    Ok(DeviceList {
+        inputs,
+        outputs,
+        default_input,
+        default_output,
+    })
+}
+
+/// Whether an encoder with these settings can be created
+pub fn check_codec(codec: &CodecOptions) -> anyhow::Result<()> {
+    upd::new_encoder(codec)?;
+    Ok(())
 }
 
 fn err_fn(err: cpal::StreamError) {
-    eprintln!("[VOICE] Stream error: {}", err);
+    tracing::error!(error = %err, "audio stream error");
 }
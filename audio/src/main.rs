@@ -68,6 +68,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         send_addr,
         recv_bind,
         tx_play.clone(),
+        &upd::CodecOptions::default(),
     )?;
 
     /* ================= INPUT STREAM (CAPTURE & SEND) ================= */
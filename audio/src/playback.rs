@@ -13,10 +13,13 @@ pub fn start_playback(rx:Receiver<Vec<f32>>){
     let cfg=output.default_output_config()
         .expect("Failed to get output config");
 
-    println!("Playback device:{}",output.name().unwrap());
-    println!("Playback format:{:?}",cfg.sample_format());
-    println!("PLayback rate: {}",cfg.sample_rate().0);
-    println!("Playback chans :{}",cfg.channels());
+    tracing::debug!(
+        device=%output.name().unwrap_or_default(),
+        format=?cfg.sample_format(),
+        rate=cfg.sample_rate().0,
+        channels=cfg.channels(),
+        "playback device"
+    );
 
 
     let stream_cfg=cpal::StreamConfig{
@@ -54,7 +57,7 @@ pub fn start_playback(rx:Receiver<Vec<f32>>){
     };
 
     stream.play().unwrap();
-    println!("Playback running.Press Enter to stop.");
+    tracing::info!("playback running, press Enter to stop");
     let _=std::io::stdin().read_line(&mut String::new());
 }
 
@@ -128,6 +131,6 @@ fn write_output_i16(
 }
 
 fn err_fn(err: cpal::StreamError) {
-    eprintln!("Playback error: {}", err);
+    tracing::error!(error = %err, "playback stream error");
 }
 
@@ -3,11 +3,22 @@ use std::thread;
 use std::time::Duration;
 
 use crossbeam_channel::Sender;
-use opus::{Application, Channels, Encoder, Decoder};
+use opus::{Application, Bitrate, Channels, Encoder, Decoder};
 
 use crate::buffer::JitterBuffer;
 use crate::voice::VoicePacket;
 
+/// Opus encoder settings; the defaults leave the encoder's own
+#[derive(Debug, Clone, Default)]
+pub struct CodecOptions {
+    /// Bitrate in bits per second; None lets the encoder choose
+    pub bitrate: Option<i32>,
+    /// Encoder complexity, 0 to 10
+    pub complexity: Option<i32>,
+    /// In-band forward error correction
+    pub fec: bool,
+}
+
 //startup UDP networking:
 //send audio frame
 //receives packets
@@ -18,6 +29,7 @@ pub fn start_udp(
     sender_addr:&str,
     recv_bind:&str,
     playback_tx:Sender<Vec<f32>>,
+    codec:&CodecOptions,
 )->anyhow::Result<UdpHandle>{
     let send_socket=UdpSocket::bind("0.0.0.0:0")?;
     send_socket.connect(sender_addr)?;
@@ -73,12 +85,7 @@ pub fn start_udp(
         }
     });
 
-    // Create Opus encoder (48kHz for CD-quality audio)
-    let encoder = Encoder::new(
-        48_000,
-        Channels::Mono,
-        Application::Voip,
-    )?;
+    let encoder = new_encoder(codec)?;
 
     Ok(UdpHandle{
         sender_id,
@@ -90,6 +97,23 @@ pub fn start_udp(
 
 
 
+//Create Opus encoder (48kHz for CD-quality audio) with the codec settings
+pub fn new_encoder(codec:&CodecOptions)->anyhow::Result<Encoder>{
+    let mut encoder = Encoder::new(
+        48_000,
+        Channels::Mono,
+        Application::Voip,
+    )?;
+    if let Some(bitrate) = codec.bitrate {
+        encoder.set_bitrate(Bitrate::Bits(bitrate))?;
+    }
+    if let Some(complexity) = codec.complexity {
+        encoder.set_complexity(complexity)?;
+    }
+    encoder.set_inband_fec(codec.fec)?;
+    Ok(encoder)
+}
+
 //Handle used by audio capture to send frame 
 pub struct UdpHandle{
     sender_id:u32,
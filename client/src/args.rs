@@ -0,0 +1,154 @@
+//! Command-line arguments. `client --help` and `client completions`
+//! are generated from these definitions.
+
+use std::path::PathBuf;
+
+use clap::{ArgAction, Args as ClapArgs, Parser, Subcommand};
+use clap_complete::Shell;
+
+use client::export::{ExportFormat, ExportOptions};
+use client::history::HistoryScope;
+
+#[derive(Debug, Parser)]
+#[command(name = "client", version, about = "End-to-end encrypted voice and text chat")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Signaling server, host:port
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub server: Option<String>,
+
+    /// Identity key file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub identity: Option<String>,
+
+    /// Display name for this session and the next ones
+    #[arg(long, global = true, value_name = "NAME")]
+    pub nick: Option<String>,
+
+    /// Chat screen
+    #[arg(long, global = true, value_name = "MODE", value_parser = ["auto", "tui", "line"])]
+    pub ui: Option<String>,
+
+    /// Chat without opening audio devices
+    #[arg(long, global = true)]
+    pub no_voice: bool,
+
+    /// Log more about what the client is doing; -vv and -vvv for more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Config file [default: ~/.voicechat/config.toml]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create a room and wait for a peer to join
+    Create {
+        /// Events as JSON lines on stdout, requests on stdin; no audio
+        #[arg(long)]
+        json: bool,
+    },
+    /// Join a room by its code
+    Join {
+        /// Room code, e.g. ABCD-EFGH
+        code: String,
+        /// Events as JSON lines on stdout, requests on stdin; no audio
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show your identity key and fingerprint
+    Identity,
+    /// List the peers you have talked to
+    Peers,
+    /// List audio input and output devices
+    Devices,
+    /// Check the settings, identity, server, audio devices and ports
+    Doctor,
+    /// Export the local chat history
+    Export(ExportArgs),
+    /// Show or edit the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print a shell completion script
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ExportArgs {
+    /// jsonl, md or txt
+    #[arg(short, long, default_value = "txt", value_parser = parse_format)]
+    format: ExportFormat,
+    /// Leave out identity keys
+    #[arg(long)]
+    redact: bool,
+    /// Write here instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Only the history with this peer key
+    #[arg(long, value_name = "KEY", conflicts_with = "room")]
+    peer: Option<String>,
+    /// Only the history of this room
+    #[arg(long, value_name = "CODE")]
+    room: Option<String>,
+}
+
+fn parse_format(s: &str) -> Result<ExportFormat, String> {
+    s.parse()
+}
+
+impl From<ExportArgs> for ExportOptions {
+    fn from(args: ExportArgs) -> Self {
+        let scope = match (args.peer, args.room) {
+            (Some(key), _) => Some((HistoryScope::Peer, key)),
+            (None, Some(code)) => Some((HistoryScope::Room, code)),
+            (None, None) => None,
+        };
+        ExportOptions {
+            format: args.format,
+            redact: args.redact,
+            output: args.output,
+            scope,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print every setting and where its value came from
+    Show,
+    /// Change a setting in the config file
+    Set { key: String, value: String },
+    /// Remove a setting from the config file
+    Unset { key: String },
+}
+
+impl Args {
+    /// Flags that override settings, as (key, value) pairs for the config
+    pub fn setting_flags(&self) -> Vec<(&'static str, String)> {
+        let level = match self.verbose {
+            0 => None,
+            1 => Some("info".to_string()),
+            2 => Some("debug".to_string()),
+            _ => Some("trace".to_string()),
+        };
+        let flags = [
+            ("server", &self.server),
+            ("identity", &self.identity),
+            ("nickname", &self.nick),
+            ("ui", &self.ui),
+            ("log.level", &level),
+        ];
+        flags
+            .into_iter()
+            .filter_map(|(key, value)| value.clone().map(|value| (key, value)))
+            .collect()
+    }
+}
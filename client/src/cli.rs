@@ -1,137 +1,668 @@
-use std::io::{self, Write};
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{self, TryRecvError};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
 use std::time::Duration;
 
-use vc_core::net::secure_stream::SecureStream;
-use vc_core::protocol::chat::{ChatMessage, ChatText};
+use vc_core::protocol::chat::{short_id, MessageId};
+use vc_core::protocol::expiry::{format_ttl, parse_ttl};
+use vc_core::protocol::nick::FINGERPRINT_SEPARATOR;
+use vc_core::protocol::presence::PresenceStatus;
+#[cfg(unix)]
+use serde_json::{json, Value};
 
-pub fn input_loop(stream: SecureStream, sender_id: String) -> anyhow::Result<()> {
-    eprintln!("[INFO] Chat ready! Type /msg <text> to send messages");
+use client::commands::{Args, Command, CommandError, CommandResult, Flow, Registry};
+#[cfg(unix)]
+use client::control::{self, Call, ControlServer};
+use client::conversation::{Conversation, DeliveryStatus};
+use client::export::{self, hex_id, parse_hex_id, ExportFormat};
+use client::history::{self, TranscriptEntry};
+use client::hooks::{HookConfig, Hooks};
+use client::input::{expand_home, Completer, InputHistory};
+use client::peers::load_own_nick;
+use client::session::{describe_timer, Event, Session};
+use client::status::describe;
 
-    // Split stream into Arc<Mutex<>> for sharing between threads
-    let stream = Arc::new(Mutex::new(stream));
-    let recv_stream = Arc::clone(&stream);
+use crate::json::JsonUi;
+use crate::tui::{Tui, TuiInfo};
+use crate::ui::{Input, LineUi, Ui, UiMode};
 
-    // Channel to signal receiver to check for messages
-    let (shutdown_tx, shutdown_rx) = mpsc::channel();
+/// Everything a command handler can reach
+struct Chat {
+    commands: Registry<Chat>,
+    ui: Arc<dyn Ui>,
+    session: Session,
+    hooks: Arc<Hooks>,
+}
+
+/// Run the chat until the user leaves or the session ends
+pub fn input_loop(session: Session, mode: UiMode, hooks: Vec<HookConfig>) -> anyhow::Result<()> {
+    let events = session.events();
+    let commands = command_registry();
+    let completer = Completer::new(commands.names(), session.status());
+    let (input_tx, input_rx) = mpsc::channel();
+    let ui = start_ui(
+        mode,
+        TuiInfo {
+            room_code: session.room_code().to_string(),
+            peer_fingerprint: session.peer_fingerprint(),
+            status: session.status(),
+            link: session.link(),
+            mute: session.mute_switch(),
+        },
+        input_tx.clone(),
+        &session.identity().secret_key_bytes(),
+        completer,
+    );
+
+    let hooks = Arc::new(Hooks::new(hooks, session.room_code()));
+    let status = session.status();
+    let conversation = session.conversation();
+    let handle = {
+        let ui = Arc::clone(&ui);
+        let hooks = Arc::clone(&hooks);
+        move |event: Event| {
+            hooks.fire(&event, status.lock().unwrap().peer_name());
+            show(&*ui, &conversation, event);
+        }
+    };
+    // PeerJoined comes first, before the history catch-up below
+    if let Ok(event) = events.recv() {
+        handle(event);
+    }
+    let event_ui = Arc::clone(&ui);
+    let pump = thread::spawn(move || {
+        for event in events {
+            handle(event);
+        }
+        event_ui.refresh();
+    });
+
+    let read_receipts = read_receipts_enabled();
+    let link = session.link();
+    let chat = Chat {
+        commands,
+        ui,
+        session,
+        hooks: Arc::clone(&hooks),
+    };
+    #[cfg(unix)]
+    let _control = start_control(&*chat.ui, input_tx);
+    #[cfg(not(unix))]
+    drop(input_tx);
+
+    // Catch up on earlier conversations when rejoining
+    if chat.session.conversation().lock().unwrap().history().is_some() {
+        show_history(&chat, None, history::lines_from_env());
+    }
 
-    // Spawn receiver thread
-    let receiver_handle = thread::spawn(move || {
-        loop {
-            // Try to receive without blocking on the channel first
-            match shutdown_rx.try_recv() {
-                Ok(_) | Err(TryRecvError::Disconnected) => {
-                    eprintln!("[RECV] Shutting down receiver");
+    chat.ui.refresh();
+    let mut told_disconnected = false;
+    loop {
+        let input = match input_rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(Input::Line(line)) => line,
+            Ok(Input::Typing(active)) => {
+                chat.session.set_typing(active);
+                continue;
+            }
+            #[cfg(unix)]
+            Ok(Input::Control(request)) => {
+                let (result, flow) = match handle_control(&chat, request.call) {
+                    Ok((result, flow)) => (Ok(result), flow),
+                    Err(e) => (Err(e.to_string()), Flow::Continue),
+                };
+                request.reply.send(result).ok();
+                if flow == Flow::Exit {
                     break;
                 }
-                Err(TryRecvError::Empty) => {
-                    // No shutdown signal, continue
-                }
+                chat.ui.refresh();
+                continue;
             }
-
-            // Acquire lock, do the recv, then immediately release
-            let recv_result = {
-                let mut s = recv_stream.lock().unwrap();
-                s.recv()
-            };
-            // Lock is released here!
-
-            match recv_result {
-                Ok(data) => {
-                    eprintln!("[RECV] Received {} bytes", data.len());
-                    if let Ok(msg) = bincode::deserialize::<ChatMessage>(&data) {
-                        match msg {
-                            ChatMessage::Text(txt) => {
-                                println!("\n[{}]: {}", txt.sender_id, txt.body);
-                                print!("> ");
-                                io::stdout().flush().ok();
-                            }
-                            ChatMessage::System(sys) => {
-                                println!("\n[SYSTEM]: {}", sys.body);
-                                print!("> ");
-                                io::stdout().flush().ok();
-                            }
-                        }
-                    } else {
-                        eprintln!("[RECV] Failed to deserialize message");
-                    }
-                }
-                Err(e) => {
-                    // Check if it's a timeout or WouldBlock error (no data available)
-                    let e_str = format!("{:?}", e);
-                    if e_str.contains("WouldBlock") || e_str.contains("TimedOut") {
-                        // No data available, just continue without delay
-                        continue;
-                    }
-                    
-                    // Handle other errors but keep receiver alive
-                    eprintln!("[RECV] Error: {:?}", e);
-                    
-                    // Only exit on fatal connection errors, not transient ones
-                    if e_str.contains("ConnectionReset") || e_str.contains("BrokenPipe") {
-                        eprintln!("[RECV] Fatal connection error, exiting receiver");
-                        break;
-                    }
-                    
-                    // For UnexpectedEof and other errors, just retry after a delay
-                    eprintln!("[RECV] Retrying after error...");
-                    thread::sleep(Duration::from_millis(200));
+            Err(RecvTimeoutError::Timeout) => {
+                if link.is_connected() {
                     continue;
                 }
+                // Line mode leaves right away; a full-screen UI stays
+                // up so the last messages can still be read
+                if !chat.ui.waits_for_exit() {
+                    break;
+                }
+                if !told_disconnected {
+                    told_disconnected = true;
+                    chat.ui.notify("[SYSTEM]: Session over, press Ctrl-C to leave");
+                }
+                continue;
             }
+            // Input closed: leave like /exit
+            Err(RecvTimeoutError::Disconnected) => "/exit".to_string(),
+        };
+
+        // Any input means the user has seen what was printed so far
+        if read_receipts {
+            chat.session.mark_read();
         }
-    });
 
-    // Input loop  
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
+        match chat.commands.dispatch(&chat, &input) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Exit) => break,
+            Err(e) => chat.ui.error(&e.to_string()),
+        }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
+        chat.ui.refresh();
+    }
 
-        if input == "/exit" {
-            shutdown_tx.send(()).ok();
-            break;
+    let Chat { ui, session, .. } = chat;
+    session.close();
+    ui.shutdown();
+    // Let call_ended and peer_left hooks run before the process exits;
+    // events keep coming while the receiver is still resuming
+    if !link.is_connected() {
+        pump.join().ok();
+    }
+    hooks.wait();
+    Ok(())
+}
+
+/// Hand an event to the UI with the line text frontends display
+fn show(ui: &dyn Ui, conversation: &Mutex<Conversation>, event: Event) {
+    let line = match &event {
+        Event::PeerJoined { room, peer, .. } => format!(
+            "Secure session with {} in room {}. Type a message and press Enter, or /help for commands",
+            peer, room
+        ),
+        Event::PeerLeft { reason } => format!("[SYSTEM]: Session closed ({})", reason),
+        Event::Message { id, sender, body, .. } => {
+            let conv = conversation.lock().unwrap();
+            match parse_hex_id(id).and_then(|id| conv.get(id)) {
+                Some(entry) => conv.render(entry),
+                // Already gone again, e.g. a message with a short timer
+                None => format!("{}: {}", sender, body),
+            }
+        }
+        Event::Updated { text, .. } => text.clone(),
+        Event::Receipt { ids, status } => {
+            let marker = match *status {
+                "read" => DeliveryStatus::Read,
+                _ => DeliveryStatus::Delivered,
+            }
+            .marker();
+            let lines: Vec<String> = ids
+                .iter()
+                .filter_map(|id| parse_hex_id(id))
+                .map(|id| format!("[{}] {}", short_id(id), marker))
+                .collect();
+            lines.join("\n")
+        }
+        // An empty line redraws the prompt with the indicator
+        Event::Typing { .. } => String::new(),
+        Event::CallStarted => "[SYSTEM]: Voice call started".to_string(),
+        Event::CallEnded => "[SYSTEM]: Voice call ended".to_string(),
+        // Transfer progress keeps its own tag
+        Event::System { text } if text.starts_with("[FILE]") => text.clone(),
+        Event::System { text } => format!("[SYSTEM]: {}", text),
+        Event::RoomCreated { room } => format!("Room Code: {}", room),
+        Event::Output { text } => text.clone(),
+        Event::Error { message } => message.clone(),
+    };
+    ui.event(event, &line);
+}
+
+/// The control socket, if VOICECHAT_CONTROL_SOCKET asks for one
+#[cfg(unix)]
+fn start_control(ui: &dyn Ui, input: mpsc::Sender<Input>) -> Option<ControlServer> {
+    let path = control::path_from_env()?;
+    match ControlServer::start(path, input) {
+        Ok(server) => {
+            ui.notify(&format!("[SYSTEM]: Control socket at {}", server.path().display()));
+            Some(server)
+        }
+        Err(e) => {
+            ui.error(&format!("Cannot open the control socket: {}", e));
+            None
         }
+    }
+}
 
-        if let Some(text) = input.strip_prefix("/msg ") {
-            eprintln!("[SEND] Sending message: {}", text);
-            // Acquire lock just for sending
-            let result = {
-                let mut s = stream.lock().unwrap();
-                send_chat_messgae(&mut s, sender_id.clone(), text.to_string())
+/// Carry out a control socket call; its result goes back as JSON
+#[cfg(unix)]
+fn handle_control(s: &Chat, call: Call) -> Result<(Value, Flow), CommandError> {
+    let result = match call {
+        Call::Send { text, reply_to } => {
+            let id = match reply_to {
+                Some(to) => s.session.reply(resolve_id(s, &to)?, &text)?,
+                None => s.session.send_text(&text)?,
             };
-            // Lock released here!
-            
-            match result {
-                Ok(_) => eprintln!("[SEND] Message sent successfully"),
-                Err(e) => eprintln!("[SEND] ERROR: {:?}", e),
-            }
+            notify_entry(s, id);
+            json!({ "id": hex_id(id) })
+        }
+        Call::Mute | Call::Unmute => {
+            let muted = matches!(call, Call::Mute);
+            s.session.set_muted(muted)?;
+            s.ui.notify(if muted { "[SYSTEM]: Microphone muted" } else { "[SYSTEM]: Microphone on" });
+            json!({ "muted": muted })
+        }
+        Call::Status => {
+            let link = s.session.link();
+            let status = s.session.status();
+            let status = status.lock().unwrap();
+            json!({
+                "room": s.session.room_code(),
+                "peer": s.session.peer_name(),
+                "fingerprint": s.session.peer_fingerprint(),
+                "connected": link.is_connected(),
+                "resuming": link.is_resuming(),
+                "presence": describe(status.mine()),
+                "muted": s.session.mute_switch().map(|mute| mute.is_muted()),
+                "rtt_ms": link.rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            })
+        }
+        Call::Participants => {
+            let status = s.session.status();
+            let status = status.lock().unwrap();
+            json!([
+                { "name": status.my_name(), "presence": describe(status.mine()), "me": true },
+                {
+                    "name": status.peer_name(),
+                    "presence": describe(status.peer()),
+                    "typing": status.peer_typing(),
+                    "me": false,
+                },
+            ])
+        }
+        Call::Leave => return Ok((json!({}), Flow::Exit)),
+    };
+    Ok((result, Flow::Continue))
+}
+
+/// The frontend for `mode`; line mode when the TUI cannot start
+fn start_ui(
+    mode: UiMode,
+    info: TuiInfo,
+    input: mpsc::Sender<Input>,
+    identity_secret: &[u8; 32],
+    completer: Completer,
+) -> Arc<dyn Ui> {
+    if mode == UiMode::Json {
+        return Arc::new(JsonUi::start(input));
+    }
+    let status = Arc::clone(&info.status);
+    if mode == UiMode::Tui {
+        let history = InputHistory::open(identity_secret);
+        match Tui::start(info, input.clone(), history, completer.clone()) {
+            Ok(tui) => return Arc::new(tui),
+            Err(e) => tracing::warn!("cannot start the full-screen UI ({}), using line mode", e),
+        }
+    }
+    let history = InputHistory::open(identity_secret);
+    Arc::new(LineUi::start(status, input, history, completer))
+}
+
+fn command_registry() -> Registry<Chat> {
+    let mut commands = Registry::new();
+    let mut add = |name, aliases, args, help, handler| {
+        commands.register(Command {
+            name,
+            aliases,
+            args,
+            help,
+            handler,
+        })
+    };
+
+    add("help", &["?"], "[command]", "List commands, or show how to use one", cmd_help);
+    add("msg", &["m"], "<text>", "Send a message", cmd_msg);
+    add("reply", &["r"], "<id> <text>", "Reply to a message", cmd_reply);
+    add("edit", &[], "<id> <text>", "Edit one of your messages", cmd_edit);
+    add("delete", &["del"], "<id>", "Delete one of your messages", cmd_delete);
+    add("react", &[], "<id> <emoji>", "Add a reaction, or remove it if already added", cmd_react);
+    add("thread", &[], "<id>", "Show a message with all its replies", cmd_thread);
+    add("ttl", &[], "[duration|off]", "Show or set the disappearing-message timer", cmd_ttl);
+    add("status", &[], "<online|away|busy|dnd> [text]", "Set your presence", cmd_status);
+    add("away", &[], "[text]", "Set your presence to away", cmd_away);
+    add("busy", &[], "[text]", "Set your presence to busy", cmd_busy);
+    add("dnd", &[], "[text]", "Set your presence to do not disturb", cmd_dnd);
+    add("back", &["online"], "", "Set your presence back to online", cmd_back);
+    add("send", &[], "<path>", "Offer a file to the peer", cmd_send);
+    add("accept", &[], "[id]", "Accept a file offer", cmd_accept);
+    add("reject", &[], "[id]", "Decline a file offer", cmd_reject);
+    add("transfers", &[], "", "List file transfers and their progress", cmd_transfers);
+    add("nick", &[], "[name]", "Show or set your display name", cmd_nick);
+    add("alias", &[], "[name]", "Show the peer under your own name for them", cmd_alias);
+    add("whois", &[], "", "Show the peer's key, fingerprint and names", cmd_whois);
+    add("history", &[], "[count]", "Show the last messages from the local history", cmd_history);
+    add("search", &[], "<text>", "Search the local history", cmd_search);
+    add("export", &[], "[jsonl|md|txt] [--redact] [path]", "Save this session's transcript", cmd_export);
+    add("rtt", &[], "", "Show round-trip time statistics", cmd_rtt);
+    add("mute", &[], "", "Stop sending your microphone", cmd_mute);
+    add("unmute", &[], "", "Send your microphone again", cmd_unmute);
+    add("exit", &["quit", "q"], "", "Leave the room", cmd_exit);
+
+    commands.set_default("msg");
+    commands
+}
+
+fn cmd_help(s: &Chat, args: &mut Args) -> CommandResult {
+    let Some(name) = args.word() else {
+        s.ui.print(&s.commands.help());
+        return Ok(Flow::Continue);
+    };
+    args.finish()?;
+    let name = name.trim_start_matches('/');
+    let command = s
+        .commands
+        .find(name)
+        .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+    s.ui.print(&format!("{}\n  {}", command.usage(), command.help));
+    if !command.aliases.is_empty() {
+        let aliases: Vec<String> = command.aliases.iter().map(|a| format!("/{}", a)).collect();
+        s.ui.print(&format!("  Also: {}", aliases.join(", ")));
+    }
+    Ok(Flow::Continue)
+}
+
+fn cmd_exit(_: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    Ok(Flow::Exit)
+}
+
+fn cmd_msg(s: &Chat, args: &mut Args) -> CommandResult {
+    let id = s.session.send_text(args.required_rest()?)?;
+    print_entry(s, id);
+    Ok(Flow::Continue)
+}
+
+fn cmd_reply(s: &Chat, args: &mut Args) -> CommandResult {
+    let to = resolve_id(s, args.required()?)?;
+    let id = s.session.reply(to, args.required_rest()?)?;
+    print_entry(s, id);
+    Ok(Flow::Continue)
+}
+
+fn cmd_edit(s: &Chat, args: &mut Args) -> CommandResult {
+    let id = resolve_id(s, args.required()?)?;
+    s.session.edit(id, args.required_rest()?)?;
+    print_entry(s, id);
+    Ok(Flow::Continue)
+}
+
+fn cmd_delete(s: &Chat, args: &mut Args) -> CommandResult {
+    let id = resolve_id(s, args.required()?)?;
+    args.finish()?;
+    s.session.delete(id)?;
+    print_entry(s, id);
+    Ok(Flow::Continue)
+}
+
+fn cmd_react(s: &Chat, args: &mut Args) -> CommandResult {
+    let id = resolve_id(s, args.required()?)?;
+    s.session.react(id, args.required_rest()?)?;
+    print_entry(s, id);
+    Ok(Flow::Continue)
+}
+
+fn cmd_thread(s: &Chat, args: &mut Args) -> CommandResult {
+    let id = resolve_id(s, args.required()?)?;
+    args.finish()?;
+    let conversation = s.session.conversation();
+    let conv = conversation.lock().unwrap();
+    for entry in conv.thread(id) {
+        s.ui.print(&conv.render(entry));
+    }
+    Ok(Flow::Continue)
+}
+
+/// `/ttl` shows the disappearing-message timer, `/ttl <duration|off>`
+/// changes it for both sides
+fn cmd_ttl(s: &Chat, args: &mut Args) -> CommandResult {
+    let Some(arg) = args.word() else {
+        match s.session.conversation().lock().unwrap().ttl() {
+            Some(ttl) => s.ui.print(&format!("Messages disappear after {}", format_ttl(ttl))),
+            None => s.ui.print("Disappearing messages are off, use /ttl <duration> e.g. /ttl 5m"),
+        }
+        return Ok(Flow::Continue);
+    };
+    args.finish()?;
+
+    let ttl = parse_ttl(arg).map_err(CommandError::Invalid)?;
+    if s.session.set_ttl(ttl)? {
+        s.ui.print(&format!("[SYSTEM]: You {}", describe_timer(ttl.map(|ttl| ttl.as_secs()))));
+    } else {
+        s.ui.print("Timer unchanged");
+    }
+    Ok(Flow::Continue)
+}
+
+fn cmd_status(s: &Chat, args: &mut Args) -> CommandResult {
+    let status = args.parse::<PresenceStatus>()?.ok_or_else(|| args.usage_error())?;
+    set_presence(s, status, args.rest())
+}
+
+fn cmd_away(s: &Chat, args: &mut Args) -> CommandResult {
+    set_presence(s, PresenceStatus::Away, args.rest())
+}
+
+fn cmd_busy(s: &Chat, args: &mut Args) -> CommandResult {
+    set_presence(s, PresenceStatus::Busy, args.rest())
+}
+
+fn cmd_dnd(s: &Chat, args: &mut Args) -> CommandResult {
+    set_presence(s, PresenceStatus::DoNotDisturb, args.rest())
+}
+
+fn cmd_back(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    set_presence(s, PresenceStatus::Online, "")
+}
+
+fn cmd_send(s: &Chat, args: &mut Args) -> CommandResult {
+    let line = s.session.send_file(expand_home(args.required_rest()?))?;
+    s.ui.print(&line);
+    Ok(Flow::Continue)
+}
+
+fn cmd_accept(s: &Chat, args: &mut Args) -> CommandResult {
+    let line = s.session.accept_file(args.rest())?;
+    s.ui.print(&line);
+    Ok(Flow::Continue)
+}
+
+fn cmd_reject(s: &Chat, args: &mut Args) -> CommandResult {
+    let line = s.session.reject_file(args.rest())?;
+    s.ui.print(&line);
+    Ok(Flow::Continue)
+}
+
+fn cmd_transfers(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    let lines = s.session.transfers().lock().unwrap().list();
+    if lines.is_empty() {
+        s.ui.print("No file transfers");
+    }
+    for line in lines {
+        s.ui.print(&line);
+    }
+    Ok(Flow::Continue)
+}
+
+fn cmd_nick(s: &Chat, args: &mut Args) -> CommandResult {
+    let name = args.rest();
+    if name.is_empty() {
+        match load_own_nick() {
+            Some(nick) => s.ui.print(&format!("Your display name is {}", nick)),
+            None => s.ui.print("No display name set, use /nick <name>"),
+        }
+        return Ok(Flow::Continue);
+    }
+
+    let shown = s.session.set_nick(name)?;
+    if let Some(nick) = shown.split(FINGERPRINT_SEPARATOR).next() {
+        s.hooks.set_nick(nick);
+    }
+    s.ui.print(&format!("You are now {}", shown));
+    Ok(Flow::Continue)
+}
+
+fn cmd_alias(s: &Chat, args: &mut Args) -> CommandResult {
+    let alias = Some(args.rest()).filter(|alias| !alias.is_empty());
+    let shown = s.session.set_alias(alias)?;
+    s.ui.print(&format!("Peer is now shown as {}", shown));
+    Ok(Flow::Continue)
+}
+
+fn cmd_whois(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    let peer_id = s.session.peer_id();
+    let known_peers = s.session.known_peers();
+    let known = known_peers.lock().unwrap();
+    let peer = known.get(peer_id).cloned().unwrap_or_default();
+    s.ui.print(&format!("Peer:        {}", known.display(peer_id)));
+    s.ui.print(&format!("Key:         {}", peer_id));
+    s.ui.print(&format!("Fingerprint: {}", s.session.peer_fingerprint()));
+    s.ui.print(&format!("Announced:   {}", peer.nick.as_deref().unwrap_or("(none)")));
+    s.ui.print(&format!("Your alias:  {}", peer.alias.as_deref().unwrap_or("(none)")));
+    Ok(Flow::Continue)
+}
+
+fn cmd_history(s: &Chat, args: &mut Args) -> CommandResult {
+    let count = args.parse::<usize>()?.unwrap_or_else(history::lines_from_env);
+    args.finish()?;
+    show_history(s, None, count);
+    Ok(Flow::Continue)
+}
+
+fn cmd_search(s: &Chat, args: &mut Args) -> CommandResult {
+    show_history(s, Some(args.required_rest()?), usize::MAX);
+    Ok(Flow::Continue)
+}
+
+fn cmd_export(s: &Chat, args: &mut Args) -> CommandResult {
+    let mut format = ExportFormat::Text;
+    let mut redact = false;
+    let mut path = None;
+
+    while let Some(arg) = args.word() {
+        if arg == "--redact" {
+            redact = true;
+        } else if let Ok(f) = arg.parse::<ExportFormat>() {
+            format = f;
+        } else if path.is_none() {
+            path = Some(expand_home(arg));
         } else {
-            println!("Usage: /msg <text>");
+            return Err(args.usage_error());
         }
     }
 
-    // Wait for receiver to finish
-    receiver_handle.join().ok();
-    Ok(())
+    let path = path.unwrap_or_else(|| export::default_path(format));
+    let entries = s.session.conversation().lock().unwrap().transcript();
+    std::fs::write(&path, export::render(&entries, format, redact))
+        .map_err(|e| CommandError::Invalid(format!("Cannot write {}: {}", path.display(), e)))?;
+    s.ui.print(&format!("Exported {} entries to {}", entries.len(), path.display()));
+    Ok(Flow::Continue)
 }
 
-fn send_chat_messgae(
-    stream: &mut SecureStream,
-    sender_id: String,
-    body: String,
-) -> anyhow::Result<()> {
-    eprintln!("[send_chat_messgae] Creating message...");
-    let msg = ChatMessage::Text(ChatText { sender_id, body });
-    eprintln!("[send_chat_messgae] Serializing...");
-    let data = bincode::serialize(&msg)?;
-    eprintln!("[send_chat_messgae] Serialized to {} bytes, calling stream.send()...", data.len());
-    stream.send(&data).map_err(|e| anyhow::anyhow!("Failed to send: {:?}", e))?;
-    eprintln!("[send_chat_messgae] Done!");
-    Ok(())
+fn cmd_rtt(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    match s.session.rtt_stats() {
+        Some(rtt) => s.ui.print(&format!(
+            "RTT {:.1} ms (smoothed {:.1} ms, jitter {:.1} ms, min {:.1} ms, {} samples)",
+            rtt.latest.as_secs_f64() * 1000.0,
+            rtt.smoothed.as_secs_f64() * 1000.0,
+            rtt.jitter.as_secs_f64() * 1000.0,
+            rtt.min.as_secs_f64() * 1000.0,
+            rtt.samples,
+        )),
+        None => s.ui.print("No RTT samples yet"),
+    }
+    Ok(Flow::Continue)
+}
+
+fn cmd_mute(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    s.session.set_muted(true)?;
+    s.ui.print("Microphone muted");
+    Ok(Flow::Continue)
+}
+
+fn cmd_unmute(s: &Chat, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    s.session.set_muted(false)?;
+    s.ui.print("Microphone on");
+    Ok(Flow::Continue)
+}
+
+/// Print the last `count` logged messages, optionally only those
+/// containing `filter`
+fn show_history(s: &Chat, filter: Option<&str>, count: usize) {
+    let conversation = s.session.conversation();
+    let conv = conversation.lock().unwrap();
+    let Some(history) = conv.history() else {
+        s.ui.print("History is off, set VOICECHAT_HISTORY=peer or room to keep it");
+        return;
+    };
+
+    let entries = match history.load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            s.ui.print(&format!("Cannot read history: {}", e));
+            return;
+        }
+    };
+    let shown: Vec<&TranscriptEntry> = match filter {
+        Some(text) => history::search(&entries, text),
+        None => entries.iter().collect(),
+    };
+
+    if shown.is_empty() {
+        if filter.is_some() {
+            s.ui.print("No messages found");
+        }
+        return;
+    }
+    let start = shown.len().saturating_sub(count);
+    s.ui.print(&format!("--- {} of {} message(s) from history ---", shown.len() - start, shown.len()));
+    for entry in &shown[start..] {
+        s.ui.print(&history::format_entry(entry));
+    }
+    s.ui.print("---");
+}
+
+fn set_presence(s: &Chat, status: PresenceStatus, text: &str) -> CommandResult {
+    let text = (!text.is_empty()).then_some(text);
+    let presence = s.session.set_presence(status, text)?;
+    s.ui.print(&format!("You are now {}", describe(&presence)));
+    Ok(Flow::Continue)
+}
+
+/// Read receipts are on unless VOICECHAT_READ_RECEIPTS is off/false/0
+fn read_receipts_enabled() -> bool {
+    match std::env::var("VOICECHAT_READ_RECEIPTS") {
+        Ok(v) => !matches!(v.to_lowercase().as_str(), "off" | "false" | "0" | "no"),
+        Err(_) => true,
+    }
+}
+
+fn resolve_id(s: &Chat, short: &str) -> Result<MessageId, CommandError> {
+    s.session
+        .conversation()
+        .lock()
+        .unwrap()
+        .resolve(short)
+        .map_err(CommandError::invalid)
+}
+
+/// Like print_entry, for entries the user did not type here
+#[cfg(unix)]
+fn notify_entry(s: &Chat, id: MessageId) {
+    let conversation = s.session.conversation();
+    let conv = conversation.lock().unwrap();
+    if let Some(entry) = conv.get(id) {
+        s.ui.notify(&conv.render(entry));
+    }
+}
+
+fn print_entry(s: &Chat, id: MessageId) {
+    let conversation = s.session.conversation();
+    let conv = conversation.lock().unwrap();
+    if let Some(entry) = conv.get(id) {
+        s.ui.print(&conv.render(entry));
+    }
 }
@@ -0,0 +1,232 @@
+//! Slash commands shared by the frontends: arguments, dispatch and help
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::session::SessionError;
+
+/// What the input loop does after a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// Wrong or missing arguments, holds the command's usage line
+    Usage(String),
+    /// Arguments were fine but the command failed
+    Invalid(String),
+    Unknown(String),
+}
+
+impl CommandError {
+    pub fn invalid(e: impl fmt::Display) -> Self {
+        CommandError::Invalid(e.to_string())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Usage(usage) => write!(f, "Usage: {}", usage),
+            CommandError::Invalid(msg) => write!(f, "{}", msg),
+            CommandError::Unknown(name) => write!(f, "Unknown command /{}, type /help for a list", name),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<SessionError> for CommandError {
+    fn from(e: SessionError) -> Self {
+        CommandError::invalid(e)
+    }
+}
+
+pub type CommandResult = Result<Flow, CommandError>;
+
+/// The text after a command name, consumed word by word
+pub struct Args<'a> {
+    rest: &'a str,
+    usage: String,
+}
+
+impl<'a> Args<'a> {
+    fn new(rest: &'a str, usage: String) -> Self {
+        Self {
+            rest: rest.trim(),
+            usage,
+        }
+    }
+
+    /// Next whitespace-separated word, if any
+    pub fn word(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (word, rest) = self.rest.split_once(char::is_whitespace).unwrap_or((self.rest, ""));
+        self.rest = rest.trim_start();
+        Some(word)
+    }
+
+    pub fn required(&mut self) -> Result<&'a str, CommandError> {
+        self.word().ok_or_else(|| self.usage_error())
+    }
+
+    /// Everything left, e.g. a message body
+    pub fn rest(&mut self) -> &'a str {
+        std::mem::take(&mut self.rest)
+    }
+
+    pub fn required_rest(&mut self) -> Result<&'a str, CommandError> {
+        match self.rest() {
+            "" => Err(self.usage_error()),
+            rest => Ok(rest),
+        }
+    }
+
+    /// Next word parsed as `T`; None when there are no more words
+    pub fn parse<T>(&mut self) -> Result<Option<T>, CommandError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.word()
+            .map(|word| word.parse().map_err(CommandError::invalid))
+            .transpose()
+    }
+
+    /// Fails on leftover arguments
+    pub fn finish(&self) -> Result<(), CommandError> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(self.usage_error())
+        }
+    }
+
+    pub fn usage_error(&self) -> CommandError {
+        CommandError::Usage(self.usage.clone())
+    }
+}
+
+pub type Handler<C> = fn(&C, &mut Args) -> CommandResult;
+
+/// A slash command; `ctx` is whatever state the frontend hands to
+/// its handlers
+pub struct Command<C> {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Arguments as shown in help, e.g. "<id> <text>"
+    pub args: &'static str,
+    pub help: &'static str,
+    pub handler: Handler<C>,
+}
+
+impl<C> Command<C> {
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.args)
+        }
+    }
+}
+
+/// Slash commands by name and alias. Input without a leading slash
+/// goes to the default command; "//" sends a literal slash.
+pub struct Registry<C> {
+    commands: Vec<Command<C>>,
+    default: Option<&'static str>,
+}
+
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Panics on a name or alias that is already taken, which is a bug
+    /// in the frontend and not something a user can cause
+    pub fn register(&mut self, command: Command<C>) {
+        for name in std::iter::once(&command.name).chain(command.aliases) {
+            assert!(self.find(name).is_none(), "command /{} registered twice", name);
+        }
+        self.commands.push(command);
+    }
+
+    /// Command that receives plain text
+    pub fn set_default(&mut self, name: &'static str) {
+        assert!(self.find(name).is_some(), "default command /{} is not registered", name);
+        self.default = Some(name);
+    }
+
+    /// Command names without aliases, for completion
+    pub fn names(&self) -> Vec<&'static str> {
+        self.commands.iter().map(|c| c.name).collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Command<C>> {
+        self.commands
+            .iter()
+            .find(|c| c.name == name || c.aliases.contains(&name))
+    }
+
+    pub fn dispatch(&self, ctx: &C, input: &str) -> CommandResult {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(Flow::Continue);
+        }
+
+        let (command, rest) = match input.strip_prefix('/') {
+            Some(line) if !line.starts_with('/') => {
+                let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                let command = self
+                    .find(&name.to_lowercase())
+                    .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+                (command, rest)
+            }
+            // "//text" sends "/text"
+            Some(line) => (self.default_command()?, line),
+            None => (self.default_command()?, input),
+        };
+
+        let mut args = Args::new(rest, command.usage());
+        (command.handler)(ctx, &mut args)
+    }
+
+    /// One line per command, generated from the registrations
+    pub fn help(&self) -> String {
+        let width = self.commands.iter().map(|c| c.usage().chars().count()).max().unwrap_or(0);
+        let mut out = String::from("Commands:");
+        for command in &self.commands {
+            let usage = command.usage();
+            let pad = width - usage.chars().count();
+            out.push_str(&format!("\n  {}{}  {}", usage, " ".repeat(pad), command.help));
+            if !command.aliases.is_empty() {
+                let aliases: Vec<String> = command.aliases.iter().map(|a| format!("/{}", a)).collect();
+                out.push_str(&format!(" (also {})", aliases.join(", ")));
+            }
+        }
+        if let Some(default) = self.default {
+            out.push_str(&format!("\nText without a leading / is sent like /{}.", default));
+        }
+        out
+    }
+
+    fn default_command(&self) -> Result<&Command<C>, CommandError> {
+        self.default
+            .and_then(|name| self.find(name))
+            .ok_or_else(|| CommandError::Invalid("Commands start with /, type /help for a list".to_string()))
+    }
+}
@@ -0,0 +1,477 @@
+//! The client config file, ~/.voicechat/config.toml. Each setting comes
+//! from the first of: a command-line flag, an environment variable, the
+//! file, the built-in default.
+//!
+//!   server = "chat.example.org:9000"
+//!   nickname = "alice"
+//!
+//!   [codec]
+//!   bitrate = 24000
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use audio::{CodecOptions, VoiceOptions};
+
+use crate::hooks::HookConfig;
+use crate::logging;
+use crate::session::VoicePorts;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Signaling server, host:port
+    pub server: String,
+    /// Identity key file, created on first run
+    pub identity: String,
+    /// Display name; replaces the one saved by /nick
+    pub nickname: Option<String>,
+    pub ui: UiPreference,
+    pub audio: AudioConfig,
+    pub voice: PortConfig,
+    pub codec: CodecConfig,
+    pub log: LogConfig,
+    /// Commands run on session events
+    pub hooks: Vec<HookConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server: "127.0.0.1:9000".to_string(),
+            identity: "~/.voicechat/identity.key".to_string(),
+            nickname: None,
+            ui: UiPreference::Auto,
+            audio: AudioConfig::default(),
+            voice: PortConfig::default(),
+            codec: CodecConfig::default(),
+            log: LogConfig::default(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UiPreference {
+    /// The TUI when stdin and stdout are terminals, else line mode
+    Auto,
+    Tui,
+    Line,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    /// Device name as the audio system reports it
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+}
+
+/// UDP ports for voice; each side listens on the one for its role
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortConfig {
+    pub client_port: u16,
+    pub host_port: u16,
+}
+
+impl Default for PortConfig {
+    fn default() -> Self {
+        let ports = VoicePorts::default();
+        Self {
+            client_port: ports.client,
+            host_port: ports.host,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodecConfig {
+    /// Opus bitrate in bits per second; unset lets the encoder choose
+    pub bitrate: Option<i32>,
+    /// Opus encoder complexity, 0 to 10
+    pub complexity: Option<i32>,
+    /// Forward error correction, for lossy networks
+    pub fec: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Filter in RUST_LOG syntax, e.g. "warn,vc_core=debug"
+    pub level: String,
+    pub format: LogFormat,
+    /// Logs go here instead of stderr
+    pub file: Option<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "warn".to_string(),
+            format: LogFormat::Text,
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// A setting `config set` can change, and the environment variable that
+/// overrides it
+struct Setting {
+    key: &'static str,
+    env: &'static str,
+    kind: Kind,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    /// One of a few lowercase words
+    Choice,
+    Number,
+    Flag,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { key: "server", env: "SERVER_ADDR", kind: Kind::Text },
+    Setting { key: "identity", env: "VOICECHAT_IDENTITY", kind: Kind::Text },
+    Setting { key: "nickname", env: "VOICECHAT_NICK", kind: Kind::Text },
+    Setting { key: "ui", env: "VOICECHAT_UI", kind: Kind::Choice },
+    Setting { key: "audio.input_device", env: "VOICECHAT_INPUT_DEVICE", kind: Kind::Text },
+    Setting { key: "audio.output_device", env: "VOICECHAT_OUTPUT_DEVICE", kind: Kind::Text },
+    Setting { key: "voice.client_port", env: "VOICECHAT_CLIENT_PORT", kind: Kind::Number },
+    Setting { key: "voice.host_port", env: "VOICECHAT_HOST_PORT", kind: Kind::Number },
+    Setting { key: "codec.bitrate", env: "VOICECHAT_BITRATE", kind: Kind::Number },
+    Setting { key: "codec.complexity", env: "VOICECHAT_COMPLEXITY", kind: Kind::Number },
+    Setting { key: "codec.fec", env: "VOICECHAT_FEC", kind: Kind::Flag },
+    Setting { key: "log.level", env: "VOICECHAT_LOG", kind: Kind::Text },
+    Setting { key: "log.format", env: "VOICECHAT_LOG_FORMAT", kind: Kind::Choice },
+    Setting { key: "log.file", env: "VOICECHAT_LOG_FILE", kind: Kind::Text },
+];
+
+impl Kind {
+    fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            Kind::Text => Ok(Value::String(text.to_string())),
+            Kind::Choice => Ok(Value::String(text.to_lowercase())),
+            Kind::Number => text
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| format!("expected a number, got '{}'", text)),
+            Kind::Flag => match text.to_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => Ok(Value::Boolean(true)),
+                "false" | "off" | "no" | "0" => Ok(Value::Boolean(false)),
+                _ => Err(format!("expected true or false, got '{}'", text)),
+            },
+        }
+    }
+}
+
+fn setting(key: &str) -> Result<&'static Setting, ConfigError> {
+    SETTINGS
+        .iter()
+        .find(|s| s.key == key)
+        .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read or written
+    Io(PathBuf, io::Error),
+    /// Not valid TOML, or a key that does not exist or has the wrong type
+    Syntax(String),
+    /// A value out of range, or an override that does not parse
+    Invalid(String),
+    /// `config set` with a key that is not a setting
+    UnknownKey(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Syntax(message) | ConfigError::Invalid(message) => write!(f, "{}", message),
+            ConfigError::UnknownKey(key) => {
+                let keys: Vec<&str> = SETTINGS.iter().map(|s| s.key).collect();
+                write!(f, "unknown setting '{}', known settings: {}", key, keys.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn config_path() -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(".voicechat");
+    path.push("config.toml");
+    path
+}
+
+/// Where a value came from, for `config show`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Default,
+    File,
+    Env(&'static str),
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "file"),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+/// The file, environment and flags before they are merged
+struct Layers {
+    file: Table,
+    env: Table,
+    flags: Table,
+}
+
+impl Layers {
+    /// `flags` are (key, value) pairs from the command line
+    fn read(path: &Path, flags: &[(&str, String)]) -> Result<Self, ConfigError> {
+        let file = match fs::read_to_string(path) {
+            Ok(text) => {
+                // Checked on its own so errors point at the file
+                Config::parse(&text).map_err(|e| ConfigError::Syntax(format!("{}: {}", path.display(), e)))?;
+                text.parse::<Table>().map_err(|e| ConfigError::Syntax(e.to_string()))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Table::new(),
+            Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+        };
+
+        let mut env = Table::new();
+        for setting in SETTINGS {
+            let Some(text) = env::var(setting.env).ok().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            let value = setting
+                .kind
+                .parse(&text)
+                .map_err(|e| ConfigError::Invalid(format!("{}: {}", setting.env, e)))?;
+            insert(&mut env, setting.key, value);
+        }
+
+        let mut table = Table::new();
+        for (key, text) in flags {
+            let value = setting(key)?
+                .kind
+                .parse(text)
+                .map_err(|e| ConfigError::Invalid(format!("{}: {}", key, e)))?;
+            insert(&mut table, key, value);
+        }
+
+        Ok(Self { file, env, flags: table })
+    }
+
+    fn config(&self) -> Result<Config, ConfigError> {
+        let mut merged = self.file.clone();
+        merge(&mut merged, self.env.clone());
+        merge(&mut merged, self.flags.clone());
+        let config: Config = Value::Table(merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Invalid(e.message().to_string()))?;
+        config.validate().map_err(ConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    fn source(&self, key: &'static str) -> Source {
+        if lookup(&self.flags, key).is_some() {
+            Source::Flag
+        } else if lookup(&self.env, key).is_some() {
+            Source::Env(setting(key).map(|s| s.env).unwrap_or_default())
+        } else if lookup(&self.file, key).is_some() {
+            Source::File
+        } else {
+            Source::Default
+        }
+    }
+}
+
+/// Set a dotted key like "codec.bitrate", creating the section
+fn insert(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, name)) => {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(section) = section {
+                section.insert(name.to_string(), value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((section, name)) => table.get(section)?.as_table()?.get(name),
+        None => table.get(key),
+    }
+}
+
+/// Values in `over` win; sections are merged key by key
+fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+impl Config {
+    /// The settings from `path`, the environment and `flags`, which are
+    /// (key, value) pairs from the command line. A missing file is fine.
+    pub fn load(path: &Path, flags: &[(&str, String)]) -> Result<Self, ConfigError> {
+        Layers::read(path, flags)?.config()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|e| ConfigError::Syntax(e.message().to_string()))?;
+        config.validate().map_err(ConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.voice.client_port == self.voice.host_port {
+            return Err("voice.client_port and voice.host_port must differ".to_string());
+        }
+        if let Some(bitrate) = self.codec.bitrate
+            && !(500..=512_000).contains(&bitrate)
+        {
+            return Err(format!("codec.bitrate must be 500 to 512000, got {}", bitrate));
+        }
+        if let Some(complexity) = self.codec.complexity
+            && !(0..=10).contains(&complexity)
+        {
+            return Err(format!("codec.complexity must be 0 to 10, got {}", complexity));
+        }
+        logging::filter(&self.log.level).map_err(|e| format!("log.level: {}", e))?;
+        Ok(())
+    }
+
+    pub fn voice_ports(&self) -> VoicePorts {
+        VoicePorts {
+            client: self.voice.client_port,
+            host: self.voice.host_port,
+        }
+    }
+
+    pub fn voice_options(&self) -> VoiceOptions {
+        VoiceOptions {
+            input_device: self.audio.input_device.clone(),
+            output_device: self.audio.output_device.clone(),
+            codec: CodecOptions {
+                bitrate: self.codec.bitrate,
+                complexity: self.codec.complexity,
+                fec: self.codec.fec,
+            },
+        }
+    }
+}
+
+/// `client config show`: every setting with its value and where that
+/// came from
+pub fn show(path: &Path, flags: &[(&str, String)]) -> Result<String, ConfigError> {
+    let layers = Layers::read(path, flags)?;
+    let config = layers.config()?;
+    let values = Value::try_from(&config).map_err(|e| ConfigError::Invalid(e.to_string()))?;
+    let values = values.as_table().cloned().unwrap_or_default();
+
+    let mut out = format!("# {}\n", path.display());
+    for setting in SETTINGS {
+        let source = layers.source(setting.key);
+        match lookup(&values, setting.key) {
+            Some(value) => out.push_str(&format!("{} = {}  # {}\n", setting.key, value, source)),
+            None => out.push_str(&format!("# {} is not set\n", setting.key)),
+        }
+    }
+    if !config.hooks.is_empty() {
+        #[derive(Serialize)]
+        struct Hooks<'a> {
+            hooks: &'a [HookConfig],
+        }
+        let hooks = Hooks { hooks: &config.hooks };
+        out.push('\n');
+        out.push_str(&toml::to_string(&hooks).map_err(|e| ConfigError::Invalid(e.to_string()))?);
+    }
+    Ok(out)
+}
+
+/// `client config set` and `unset`: change one setting in the file,
+/// keeping its comments and layout; None removes it. The result is
+/// checked before it is written.
+pub fn set(path: &Path, key: &str, value: Option<&str>) -> Result<(), ConfigError> {
+    let setting = setting(key)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| ConfigError::Syntax(format!("{}: {}", path.display(), e)))?;
+
+    let (section, name) = match key.split_once('.') {
+        Some((section, name)) => (Some(section), name),
+        None => (None, key),
+    };
+    let table = match section {
+        Some(section) => doc
+            .entry(section)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| ConfigError::Syntax(format!("{}: {} is not a table", path.display(), section)))?,
+        None => doc.as_table_mut() as &mut dyn toml_edit::TableLike,
+    };
+    match value {
+        Some(value) => {
+            let value = match setting.kind.parse(value).map_err(|e| ConfigError::Invalid(format!("{}: {}", key, e)))? {
+                Value::String(s) => toml_edit::value(s),
+                Value::Integer(n) => toml_edit::value(n),
+                Value::Boolean(b) => toml_edit::value(b),
+                other => toml_edit::value(other.to_string()),
+            };
+            table.insert(name, value);
+        }
+        None => {
+            table.remove(name);
+        }
+    }
+
+    let text = doc.to_string();
+    Config::parse(&text)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| ConfigError::Io(parent.to_path_buf(), e))?;
+    }
+    fs::write(path, text).map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+}
@@ -0,0 +1,227 @@
+//! Control socket: other local tools drive a running client over a
+//! Unix domain socket, one JSON-RPC 2.0 request per line.
+//!
+//!   {"jsonrpc":"2.0","id":1,"method":"send","params":{"text":"hi"}}
+//!   {"jsonrpc":"2.0","id":1,"result":{"id":"3fa9c1d2e4b5a697"}}
+//!
+//! Methods: send, mute, unmute, status, participants, leave. Only the
+//! owner can connect: the socket is created with mode 0600.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::input::expand_home;
+
+/// How long a request may wait for the chat loop to answer
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The call was understood but failed, e.g. voice is off
+const CALL_FAILED: i64 = -32000;
+
+/// What a control client asked for
+#[derive(Debug)]
+pub enum Call {
+    Send { text: String, reply_to: Option<String> },
+    Mute,
+    Unmute,
+    Status,
+    Participants,
+    Leave,
+}
+
+/// A call for the chat loop, which answers on `reply`
+pub struct ControlRequest {
+    pub call: Call,
+    pub reply: Sender<Result<Value, String>>,
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct SendParams {
+    text: String,
+    #[serde(default)]
+    reply_to: Option<String>,
+}
+
+impl Call {
+    /// A call from a JSON-RPC method name and its params
+    pub fn parse(method: &str, params: Value) -> Result<Self, (i64, String)> {
+        let invalid = |e: serde_json::Error| (INVALID_PARAMS, format!("Invalid params: {}", e));
+        match method {
+            "send" => {
+                let params: SendParams = serde_json::from_value(params).map_err(invalid)?;
+                Ok(Call::Send {
+                    text: params.text,
+                    reply_to: params.reply_to,
+                })
+            }
+            "mute" => Ok(Call::Mute),
+            "unmute" => Ok(Call::Unmute),
+            "status" => Ok(Call::Status),
+            "participants" => Ok(Call::Participants),
+            "leave" => Ok(Call::Leave),
+            other => Err((METHOD_NOT_FOUND, format!("Method not found: {}", other))),
+        }
+    }
+}
+
+/// VOICECHAT_CONTROL_SOCKET: a socket path, or "on" for
+/// ~/.voicechat/control.sock. Unset means no socket.
+pub fn path_from_env() -> Option<PathBuf> {
+    match std::env::var("VOICECHAT_CONTROL_SOCKET").ok()?.as_str() {
+        "" | "off" => None,
+        "on" => {
+            let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+            path.push(".voicechat");
+            path.push("control.sock");
+            Some(path)
+        }
+        path => Some(expand_home(path)),
+    }
+}
+
+/// The listening socket; removed again when dropped
+pub struct ControlServer {
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Listen at `path` and pass each call to the chat loop through `input`
+    pub fn start<T>(path: PathBuf, input: Sender<T>) -> io::Result<Self>
+    where
+        T: From<ControlRequest> + Send + 'static,
+    {
+        let listener = bind_private(&path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let input = input.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, &input) {
+                        tracing::warn!(error = %e, "control connection failed");
+                    }
+                });
+            }
+        });
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Bind with a umask that keeps everyone else out from the start,
+/// rather than fixing the mode after others could have connected
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::symlink_metadata(path) {
+        // Left over from a client that did not exit cleanly
+        Ok(meta) if meta.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is used by another running client", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(_) => {}
+    }
+
+    // SAFETY: umask only swaps the process file mode mask
+    let old_mask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    // SAFETY: as above, putting the previous mask back
+    unsafe { libc::umask(old_mask) };
+    let listener = listener?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Answer requests on one connection until the client hangs up
+fn serve<T: From<ControlRequest>>(stream: UnixStream, input: &Sender<T>) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = answer(&line, input);
+        writeln!(out, "{}", response)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn answer<T: From<ControlRequest>>(line: &str, input: &Sender<T>) -> Value {
+    let request: RpcRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return error(Value::Null, PARSE_ERROR, format!("Parse error: {}", e)),
+    };
+    if request.jsonrpc != "2.0" {
+        return error(request.id, INVALID_REQUEST, "Invalid request: jsonrpc must be \"2.0\"".to_string());
+    }
+    let call = match Call::parse(&request.method, request.params) {
+        Ok(call) => call,
+        Err((code, message)) => return error(request.id, code, message),
+    };
+
+    let (reply, result) = mpsc::channel();
+    if input.send(ControlRequest { call, reply }.into()).is_err() {
+        return error(request.id, CALL_FAILED, "The session is over".to_string());
+    }
+    match result.recv_timeout(REPLY_TIMEOUT) {
+        Ok(Ok(result)) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Ok(Err(message)) => error(request.id, CALL_FAILED, message),
+        Err(_) => error(request.id, CALL_FAILED, "No answer from the session".to_string()),
+    }
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use crate::history::{History, TranscriptEntry};
+use vc_core::protocol::chat::{
+    now_millis, short_id, ChatDelete, ChatEdit, ChatReaction, ChatReceipt, ChatText, ChatTimer,
+    MessageId, ReceiptStatus,
+};
+use vc_core::protocol::expiry::{self, format_remaining};
+
+/// Longest quote of a parent message shown above a reply
+const SNIPPET_LEN: usize = 40;
+
+/// Progress of one of our own messages, only ever moves forward
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeliveryStatus {
+    Sent,
+    Delivered,
+    Read,
+}
+
+impl DeliveryStatus {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "✓ sent",
+            DeliveryStatus::Delivered => "✓✓ delivered",
+            DeliveryStatus::Read => "✓✓ read",
+        }
+    }
+}
+
+impl From<ReceiptStatus> for DeliveryStatus {
+    fn from(status: ReceiptStatus) -> Self {
+        match status {
+            ReceiptStatus::Delivered => DeliveryStatus::Delivered,
+            ReceiptStatus::Read => DeliveryStatus::Read,
+        }
+    }
+}
+
+/// One chat message as currently displayed
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: MessageId,
+    pub sender_id: String,
+    pub body: String,
+    pub sent_at: u64,
+    pub reply_to: Option<MessageId>,
+    /// Only set on our own messages
+    pub status: Option<DeliveryStatus>,
+    pub edited: bool,
+    pub deleted: bool,
+    /// emoji -> ids of everyone who reacted with it
+    pub reactions: BTreeMap<String, Vec<String>>,
+    /// Disappearing messages: local time, in milliseconds since the
+    /// epoch, when the message is dropped
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ConversationError {
+    UnknownMessage(String),
+    AmbiguousId(String),
+    NotYourMessage,
+    Deleted,
+}
+
+impl std::fmt::Display for ConversationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversationError::UnknownMessage(id) => write!(f, "no message with id {}", id),
+            ConversationError::AmbiguousId(id) => write!(f, "id {} matches several messages", id),
+            ConversationError::NotYourMessage => write!(f, "only the author can change a message"),
+            ConversationError::Deleted => write!(f, "message was deleted"),
+        }
+    }
+}
+
+impl std::error::Error for ConversationError {}
+
+/// Messages of the current chat, indexed by id for replies, edits,
+/// deletes and reactions
+pub struct Conversation {
+    my_id: String,
+    entries: Vec<Entry>,
+    index: HashMap<MessageId, usize>,
+    /// parent id -> ids of its direct replies, in arrival order
+    replies: HashMap<MessageId, Vec<MessageId>>,
+    /// Peer messages not yet acknowledged as read
+    unread: Vec<MessageId>,
+    /// sender_id -> name shown in place of the key
+    names: HashMap<String, String>,
+    /// Where messages are persisted, when history is enabled
+    history: Option<History>,
+    /// (time, text) of session events like resumes and name changes
+    events: Vec<(u64, String)>,
+    /// Timer applied to messages we send, and when it was last changed
+    ttl: Option<Duration>,
+    ttl_set_at: u64,
+    /// Ids of messages that already disappeared, so a retransmit
+    /// does not bring them back
+    expired: HashSet<MessageId>,
+}
+
+impl Conversation {
+    pub fn new(my_id: String) -> Self {
+        Self {
+            my_id,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            replies: HashMap::new(),
+            unread: Vec::new(),
+            names: HashMap::new(),
+            history: None,
+            events: Vec::new(),
+            ttl: None,
+            ttl_set_at: 0,
+            expired: HashSet::new(),
+        }
+    }
+
+    /// Record a message; false if this id was already seen
+    pub fn add_text(&mut self, text: &ChatText) -> bool {
+        if self.index.contains_key(&text.id) || self.expired.contains(&text.id) {
+            return false;
+        }
+        let mine = text.sender_id == self.my_id;
+        if !mine {
+            self.unread.push(text.id);
+        }
+        // Counted from when we saw the message, our clock may differ
+        // from the sender's
+        let expires_at = text
+            .expires_in
+            .map(|secs| expiry::expires_at(if mine { text.sent_at } else { now_millis() }, secs));
+
+        self.index.insert(text.id, self.entries.len());
+        if let Some(parent) = text.reply_to {
+            self.replies.entry(parent).or_default().push(text.id);
+        }
+        self.entries.push(Entry {
+            id: text.id,
+            sender_id: text.sender_id.clone(),
+            body: text.body.clone(),
+            sent_at: text.sent_at,
+            reply_to: text.reply_to,
+            status: mine.then_some(DeliveryStatus::Sent),
+            edited: false,
+            deleted: false,
+            reactions: BTreeMap::new(),
+            expires_at,
+        });
+        if let Some(history) = &self.history {
+            history.record_text(text, &self.name_of(&text.sender_id), expires_at);
+        }
+        true
+    }
+
+    /// Timer for messages we send from now on
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Apply a timer change unless a newer one is already in place;
+    /// true if the timer changed
+    pub fn set_ttl(&mut self, timer: &ChatTimer) -> bool {
+        if timer.set_at < self.ttl_set_at {
+            return false;
+        }
+        self.ttl_set_at = timer.set_at;
+        let ttl = timer.ttl_secs.map(Duration::from_secs);
+        let changed = ttl != self.ttl;
+        self.ttl = ttl;
+        changed
+    }
+
+    /// A new outgoing message carrying the current timer
+    pub fn new_text(&self, body: &str, reply_to: Option<MessageId>) -> ChatText {
+        let mut text = ChatText::new(self.my_id.clone(), body.to_string(), reply_to);
+        text.expires_in = self.ttl.map(|ttl| ttl.as_secs());
+        text
+    }
+
+    /// Drop messages whose timer ran out, here and in the history log;
+    /// returns how many disappeared
+    pub fn expire(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        let expired = &mut self.expired;
+        self.entries.retain(|e| match e.expires_at {
+            Some(at) if at <= now => {
+                expired.insert(e.id);
+                false
+            }
+            _ => true,
+        });
+        let removed = before - self.entries.len();
+        if removed == 0 {
+            return 0;
+        }
+
+        self.index = self.entries.iter().enumerate().map(|(i, e)| (e.id, i)).collect();
+        self.replies.clear();
+        for e in &self.entries {
+            if let Some(parent) = e.reply_to {
+                self.replies.entry(parent).or_default().push(e.id);
+            }
+        }
+        if let Some(history) = &self.history
+            && let Err(e) = history.purge_expired()
+        {
+            tracing::warn!(error = %e, "could not remove expired messages");
+        }
+        removed
+    }
+
+    pub fn set_history(&mut self, history: Option<History>) {
+        self.history = history;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Note a session event for the transcript and history
+    pub fn add_system(&mut self, body: &str) {
+        let at = now_millis();
+        if let Some(history) = &self.history {
+            history.record_system(at, body);
+        }
+        self.events.push((at, body.to_string()));
+    }
+
+    /// Messages and session events in time order
+    pub fn transcript(&self) -> Vec<TranscriptEntry> {
+        let mut out: Vec<TranscriptEntry> = self
+            .entries
+            .iter()
+            .map(|e| TranscriptEntry {
+                id: Some(e.id),
+                sender_id: Some(e.sender_id.clone()),
+                sender_name: self.name_of(&e.sender_id),
+                mine: e.sender_id == self.my_id,
+                body: e.body.clone(),
+                sent_at: e.sent_at,
+                reply_to: e.reply_to,
+                edited: e.edited,
+                deleted: e.deleted,
+            })
+            .chain(
+                self.events
+                    .iter()
+                    .map(|(at, body)| TranscriptEntry::system(*at, body.clone())),
+            )
+            .collect();
+        out.sort_by_key(|e| e.sent_at);
+        out
+    }
+
+    pub fn get(&self, id: MessageId) -> Option<&Entry> {
+        self.index.get(&id).map(|&i| &self.entries[i])
+    }
+
+    /// Find a message from any unique prefix of its hex id
+    pub fn resolve(&self, short: &str) -> Result<MessageId, ConversationError> {
+        let short = short.trim_start_matches('#').to_lowercase();
+        let mut matches = self
+            .entries
+            .iter()
+            .filter(|e| !short.is_empty() && format!("{:016x}", e.id).starts_with(&short));
+
+        match (matches.next(), matches.next()) {
+            (Some(e), None) => Ok(e.id),
+            (Some(_), Some(_)) => Err(ConversationError::AmbiguousId(short)),
+            _ => Err(ConversationError::UnknownMessage(short)),
+        }
+    }
+
+    /// Only the author may edit, `from` is who sent the edit
+    pub fn apply_edit(&mut self, from: &str, edit: &ChatEdit) -> Result<(), ConversationError> {
+        let entry = self.owned_entry_mut(from, edit.id)?;
+        entry.body = edit.body.clone();
+        entry.edited = true;
+        if let Some(history) = &self.history {
+            history.record_edit(edit.id, &edit.body);
+        }
+        Ok(())
+    }
+
+    pub fn apply_delete(&mut self, from: &str, delete: &ChatDelete) -> Result<(), ConversationError> {
+        let entry = self.owned_entry_mut(from, delete.id)?;
+        entry.deleted = true;
+        entry.body.clear();
+        entry.reactions.clear();
+        if let Some(history) = &self.history {
+            history.record_delete(delete.id);
+        }
+        Ok(())
+    }
+
+    pub fn apply_reaction(&mut self, from: &str, reaction: &ChatReaction) -> Result<(), ConversationError> {
+        let entry = self.entry_mut(reaction.id)?;
+        if entry.deleted {
+            return Err(ConversationError::Deleted);
+        }
+
+        let who = entry.reactions.entry(reaction.emoji.clone()).or_default();
+        who.retain(|id| id != from);
+        if !reaction.remove {
+            who.push(from.to_string());
+        }
+        if who.is_empty() {
+            entry.reactions.remove(&reaction.emoji);
+        }
+        Ok(())
+    }
+
+    /// Whether `from` currently has `emoji` on message `id`
+    pub fn has_reacted(&self, from: &str, id: MessageId, emoji: &str) -> bool {
+        self.get(id)
+            .and_then(|e| e.reactions.get(emoji))
+            .is_some_and(|who| who.iter().any(|w| w == from))
+    }
+
+    /// Advance our own messages named in a receipt, returning the ids
+    /// whose status changed
+    pub fn apply_receipt(&mut self, receipt: &ChatReceipt) -> Vec<MessageId> {
+        let new_status = DeliveryStatus::from(receipt.status);
+        let mut changed = Vec::new();
+        for &id in &receipt.ids {
+            let Ok(entry) = self.entry_mut(id) else {
+                continue;
+            };
+            if let Some(status) = entry.status.as_mut()
+                && *status < new_status
+            {
+                *status = new_status;
+                changed.push(id);
+            }
+        }
+        changed
+    }
+
+    /// Our messages the peer has not confirmed, to be sent again
+    /// after a reconnect
+    pub fn unacknowledged(&self) -> Vec<ChatText> {
+        self.entries
+            .iter()
+            .filter(|e| e.status == Some(DeliveryStatus::Sent) && !e.deleted)
+            .map(|e| ChatText {
+                sender_id: e.sender_id.clone(),
+                body: e.body.clone(),
+                id: e.id,
+                sent_at: e.sent_at,
+                reply_to: e.reply_to,
+                // Whatever is left of the timer, so it still disappears
+                // on both sides at about the same time
+                expires_in: e
+                    .expires_at
+                    .map(|at| at.saturating_sub(now_millis()).div_ceil(1000).max(1)),
+            })
+            .collect()
+    }
+
+    /// Peer messages received since the last call
+    pub fn take_unread(&mut self) -> Vec<MessageId> {
+        std::mem::take(&mut self.unread)
+    }
+
+    /// The root message of `id` and every reply below it, in order
+    pub fn thread(&self, id: MessageId) -> Vec<&Entry> {
+        let root = self.root_of(id);
+        let Some(&first) = self.index.get(&root) else {
+            return Vec::new();
+        };
+
+        // Walk down from the root; `seen` guards against reply cycles
+        let mut positions = vec![first];
+        let mut seen = HashSet::from([root]);
+        let mut next = 0;
+        while let Some(&i) = positions.get(next) {
+            next += 1;
+            for child in self.replies.get(&self.entries[i].id).into_iter().flatten() {
+                if let Some(&j) = self.index.get(child)
+                    && seen.insert(*child)
+                {
+                    positions.push(j);
+                }
+            }
+        }
+        positions.sort_unstable();
+        positions.into_iter().map(|i| &self.entries[i]).collect()
+    }
+
+    /// One display line per message: "[abc123] sender: body"
+    /// with a quote line above replies
+    pub fn render(&self, entry: &Entry) -> String {
+        let mut out = String::new();
+
+        if let Some(parent_id) = entry.reply_to {
+            match self.get(parent_id) {
+                Some(parent) => out.push_str(&format!(
+                    "  ┌ re [{}] {}: {}\n",
+                    short_id(parent.id),
+                    self.display_sender(&parent.sender_id),
+                    snippet(parent)
+                )),
+                None => out.push_str(&format!("  ┌ re [{}] (unknown message)\n", short_id(parent_id))),
+            }
+        }
+
+        out.push_str(&format!(
+            "[{}] {}: ",
+            short_id(entry.id),
+            self.display_sender(&entry.sender_id)
+        ));
+        if entry.deleted {
+            out.push_str("(message deleted)");
+            return out;
+        }
+        out.push_str(&entry.body);
+        if entry.edited {
+            out.push_str(" (edited)");
+        }
+        if !entry.reactions.is_empty() {
+            let reactions: Vec<String> = entry
+                .reactions
+                .iter()
+                .map(|(emoji, who)| format!("{} {}", emoji, who.len()))
+                .collect();
+            out.push_str(&format!("  [{}]", reactions.join(", ")));
+        }
+        if let Some(status) = entry.status {
+            out.push_str(&format!("  {}", status.marker()));
+        }
+        if let Some(at) = entry.expires_at {
+            let left = Duration::from_millis(at.saturating_sub(now_millis()));
+            out.push_str(&format!("  ⏱ {}", format_remaining(left)));
+        }
+        out
+    }
+
+    pub fn set_display_name(&mut self, sender_id: &str, name: String) {
+        self.names.insert(sender_id.to_string(), name);
+    }
+
+    pub fn display_sender(&self, sender_id: &str) -> String {
+        if sender_id == self.my_id {
+            return "you".to_string();
+        }
+        self.name_of(sender_id)
+    }
+
+    /// Display name, also for our own id
+    pub fn name_of(&self, sender_id: &str) -> String {
+        self.names
+            .get(sender_id)
+            .cloned()
+            .unwrap_or_else(|| sender_id.to_string())
+    }
+
+    fn root_of(&self, id: MessageId) -> MessageId {
+        let mut root = id;
+        // Bounded walk in case a peer sent a reply cycle
+        for _ in 0..self.entries.len() {
+            match self.get(root).and_then(|e| e.reply_to) {
+                Some(parent) if self.get(parent).is_some() => root = parent,
+                _ => break,
+            }
+        }
+        root
+    }
+
+    fn entry_mut(&mut self, id: MessageId) -> Result<&mut Entry, ConversationError> {
+        let &i = self
+            .index
+            .get(&id)
+            .ok_or_else(|| ConversationError::UnknownMessage(short_id(id)))?;
+        Ok(&mut self.entries[i])
+    }
+
+    fn owned_entry_mut(&mut self, from: &str, id: MessageId) -> Result<&mut Entry, ConversationError> {
+        let entry = self.entry_mut(id)?;
+        if entry.sender_id != from {
+            return Err(ConversationError::NotYourMessage);
+        }
+        if entry.deleted {
+            return Err(ConversationError::Deleted);
+        }
+        Ok(entry)
+    }
+}
+
+fn snippet(entry: &Entry) -> String {
+    if entry.deleted {
+        return "(message deleted)".to_string();
+    }
+    let mut s: String = entry.body.chars().take(SNIPPET_LEN).collect();
+    if entry.body.chars().count() > SNIPPET_LEN {
+        s.push('…');
+    }
+    s
+}
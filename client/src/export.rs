@@ -0,0 +1,268 @@
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose, Engine};
+use serde::Serialize;
+use vc_core::protocol::chat::{now_millis, MessageId};
+use vc_core::protocol::nick::{fingerprint, FINGERPRINT_SEPARATOR};
+
+use crate::history::{self, format_timestamp, History, HistoryScope, TranscriptEntry};
+use crate::identity::Identity;
+
+/// Replaces identity keys when exporting with redaction
+const REDACTED: &str = "[key redacted]";
+/// Replaces fingerprints quoted in message text
+const REDACTED_FINGERPRINT: &str = "[fingerprint redacted]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    JsonLines,
+    Markdown,
+    Text,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "txt" | "text" => Ok(ExportFormat::Text),
+            other => Err(format!("unknown export format '{}', use jsonl, md or txt", other)),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Text => "txt",
+        }
+    }
+}
+
+/// One JSON Lines record
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    time: String,
+    timestamp_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sender: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    mine: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<String>,
+    body: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    edited: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+}
+
+/// Render a transcript; `redact` drops identity keys and fingerprints,
+/// including those of participants quoted in message text
+pub fn render(entries: &[TranscriptEntry], format: ExportFormat, redact: bool) -> String {
+    let mut fingerprints: Vec<String> = Vec::new();
+    if redact {
+        fingerprints = entries.iter().filter_map(|e| e.sender_id.as_deref()).map(key_fingerprint).collect();
+        fingerprints.sort();
+        fingerprints.dedup();
+    }
+    let body = |e: &TranscriptEntry| {
+        if redact {
+            redact_text(&e.body, &fingerprints)
+        } else {
+            e.body.clone()
+        }
+    };
+    let sender = |e: &TranscriptEntry| {
+        if redact {
+            redact_name(&e.sender_name)
+        } else {
+            e.sender_name.clone()
+        }
+    };
+    let mut out = String::new();
+
+    match format {
+        ExportFormat::JsonLines => {
+            for e in entries {
+                let record = JsonEntry {
+                    kind: if e.is_system() { "system" } else { "message" },
+                    time: format_timestamp(e.sent_at),
+                    timestamp_ms: e.sent_at,
+                    id: e.id.map(hex_id),
+                    sender: (!e.is_system()).then(|| sender(e)),
+                    fingerprint: e.sender_id.as_deref().filter(|_| !redact).map(key_fingerprint),
+                    key: e.sender_id.as_deref().filter(|_| !redact),
+                    mine: e.mine,
+                    reply_to: e.reply_to.map(hex_id),
+                    body: body(e),
+                    edited: e.edited,
+                    deleted: e.deleted,
+                };
+                out.push_str(&serde_json::to_string(&record).expect("transcript serializes"));
+                out.push('\n');
+            }
+        }
+        ExportFormat::Markdown => {
+            out.push_str(&format!("# Voicechat transcript\n\nExported {} UTC\n\n", format_timestamp(now_millis())));
+            for e in entries {
+                let time = format_timestamp(e.sent_at);
+                if e.is_system() {
+                    out.push_str(&format!("- *{} — {}*\n", time, body(e)));
+                    continue;
+                }
+                let mut line = format!("- **{}** `{}`", time, sender(e));
+                if let Some(parent) = e.reply_to {
+                    line.push_str(&format!(" (reply to `{}`)", &hex_id(parent)[..6]));
+                }
+                if e.deleted {
+                    line.push_str(": *message deleted*");
+                } else {
+                    // Keep multi-line bodies inside the list item
+                    line.push_str(&format!(": {}", body(e).replace('\n', "\n  ")));
+                    if e.edited {
+                        line.push_str(" *(edited)*");
+                    }
+                }
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        ExportFormat::Text => {
+            for e in entries {
+                let mut e = e.clone();
+                e.body = body(&e);
+                e.sender_name = sender(&e);
+                // Full names instead of "you" so the file reads on its own
+                e.mine = false;
+                out.push_str(&history::format_entry(&e));
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// What `client export` writes, and where
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub redact: bool,
+    /// Stdout when None
+    pub output: Option<PathBuf>,
+    /// One peer's or room's history; all of them when None
+    pub scope: Option<(HistoryScope, String)>,
+}
+
+/// `client export`, from the local history of `identity`
+pub fn run(options: ExportOptions, identity: &Identity) -> anyhow::Result<()> {
+    let ExportOptions {
+        format,
+        redact,
+        output,
+        scope,
+    } = options;
+    let secret = identity.secret_key_bytes();
+    let my_id = general_purpose::STANDARD.encode(identity.public_key_bytes());
+
+    let logs = match scope {
+        Some((scope, value)) => {
+            let log = History::open(&secret, scope, &value, &my_id)?;
+            if !log.exists() {
+                anyhow::bail!("no history for that {}", if scope == HistoryScope::Peer { "peer" } else { "room" });
+            }
+            vec![log]
+        }
+        None => history::open_all(&secret, &my_id)?,
+    };
+
+    let mut entries = Vec::new();
+    for log in &logs {
+        log.purge_expired()?;
+        entries.extend(log.load()?);
+    }
+    entries.sort_by_key(|e| e.sent_at);
+
+    let text = render(&entries, format, redact);
+    match output {
+        Some(path) => {
+            fs::write(&path, text)?;
+            eprintln!("Exported {} entries to {}", entries.len(), path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Default file name for an in-session export, in the current directory
+pub fn default_path(format: ExportFormat) -> PathBuf {
+    let stamp: String = format_timestamp(now_millis())
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect();
+    PathBuf::from(format!("voicechat-{}.{}", stamp, format.extension()))
+}
+
+/// Full message id as scripts see it; any unique prefix resolves
+pub fn hex_id(id: MessageId) -> String {
+    format!("{:016x}", id)
+}
+
+/// The id back from `hex_id`
+pub fn parse_hex_id(id: &str) -> Option<MessageId> {
+    MessageId::from_str_radix(id, 16).ok()
+}
+
+fn key_fingerprint(sender_id: &str) -> String {
+    match general_purpose::STANDARD.decode(sender_id) {
+        Ok(key) => fingerprint(&key),
+        Err(_) => fingerprint(sender_id.as_bytes()),
+    }
+}
+
+/// "alice~3fa9c1d2" without its fingerprint; names never contain the
+/// separator, so whatever follows it goes
+fn redact_name(name: &str) -> String {
+    let name = name.split(FINGERPRINT_SEPARATOR).next().unwrap_or_default();
+    redact_keys(name)
+}
+
+/// Keys, and the given fingerprints, replaced in message text
+fn redact_text(text: &str, fingerprints: &[String]) -> String {
+    fingerprints
+        .iter()
+        .fold(redact_keys(text), |text, fp| text.replace(fp.as_str(), REDACTED_FINGERPRINT))
+}
+
+/// Replace anything that decodes as a 32-byte base64 key
+fn redact_keys(text: &str) -> String {
+    text.split_inclusive(char::is_whitespace)
+        .map(|word| {
+            let trimmed = word.trim_end();
+            let token = trimmed.trim_matches(|c: char| !(c.is_ascii_alphanumeric() || "+/=".contains(c)));
+            let is_key = token.len() == 44
+                && general_purpose::STANDARD
+                    .decode(token)
+                    .is_ok_and(|bytes| bytes.len() == 32);
+            if is_key {
+                word.replacen(token, REDACTED, 1)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect()
+}
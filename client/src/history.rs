@@ -0,0 +1,516 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use vc_core::crypto::storage::StorageCipher;
+use vc_core::protocol::chat::{now_millis, ChatText, MessageId};
+
+/// Messages shown when rejoining, unless VOICECHAT_HISTORY_LINES says otherwise
+const DEFAULT_LINES: usize = 20;
+
+/// Which conversations share one history log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryScope {
+    /// Everything said with the same peer key
+    Peer,
+    /// Everything said in the same room code
+    Room,
+}
+
+impl FromStr for HistoryScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "peer" => Ok(HistoryScope::Peer),
+            "room" => Ok(HistoryScope::Room),
+            other => Err(format!("unknown history scope '{}', use off, peer or room", other)),
+        }
+    }
+}
+
+/// VOICECHAT_HISTORY = off (default) | peer | room
+pub fn scope_from_env() -> Option<HistoryScope> {
+    let value = std::env::var("VOICECHAT_HISTORY").ok()?;
+    if matches!(value.to_lowercase().as_str(), "" | "off" | "0" | "false" | "no") {
+        return None;
+    }
+    match value.parse() {
+        Ok(scope) => Some(scope),
+        Err(e) => {
+            tracing::warn!("{}, history disabled", e);
+            None
+        }
+    }
+}
+
+/// VOICECHAT_HISTORY_LINES, messages shown when rejoining
+pub fn lines_from_env() -> usize {
+    std::env::var("VOICECHAT_HISTORY_LINES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_LINES)
+}
+
+/// One record of the append-only log
+#[derive(Debug, Serialize, Deserialize)]
+enum HistoryEvent {
+    Text {
+        id: MessageId,
+        sender_id: String,
+        sender_name: String,
+        body: String,
+        sent_at: u64,
+        reply_to: Option<MessageId>,
+    },
+    Edit {
+        id: MessageId,
+        body: String,
+    },
+    Delete {
+        id: MessageId,
+    },
+    System {
+        at: u64,
+        body: String,
+    },
+    /// A disappearing message, dropped from the log once `expires_at`
+    /// has passed. Added after the others so old logs still decode.
+    ExpiringText {
+        id: MessageId,
+        sender_id: String,
+        sender_name: String,
+        body: String,
+        sent_at: u64,
+        reply_to: Option<MessageId>,
+        expires_at: u64,
+    },
+}
+
+impl HistoryEvent {
+    /// The message an event belongs to, if any
+    fn message_id(&self) -> Option<MessageId> {
+        match self {
+            HistoryEvent::Text { id, .. }
+            | HistoryEvent::ExpiringText { id, .. }
+            | HistoryEvent::Edit { id, .. }
+            | HistoryEvent::Delete { id } => Some(*id),
+            HistoryEvent::System { .. } => None,
+        }
+    }
+}
+
+/// One length-prefixed record as stored, with its event when it decrypts
+struct Record {
+    raw: Vec<u8>,
+    event: Option<HistoryEvent>,
+}
+
+/// A message or system event, as replayed from the log or taken from
+/// the live conversation
+#[derive(Debug, Clone)]
+pub struct TranscriptEntry {
+    /// None for system events
+    pub id: Option<MessageId>,
+    /// Base64 identity key, None for system events
+    pub sender_id: Option<String>,
+    pub sender_name: String,
+    pub mine: bool,
+    pub body: String,
+    pub sent_at: u64,
+    pub reply_to: Option<MessageId>,
+    pub edited: bool,
+    pub deleted: bool,
+}
+
+impl TranscriptEntry {
+    pub fn system(at: u64, body: String) -> Self {
+        Self {
+            id: None,
+            sender_id: None,
+            sender_name: String::new(),
+            mine: false,
+            body,
+            sent_at: at,
+            reply_to: None,
+            edited: false,
+            deleted: false,
+        }
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.sender_id.is_none()
+    }
+}
+
+/// Encrypted append-only log under ~/.voicechat/history. Every record
+/// is LEN || NONCE || CIPHERTEXT, sealed with a key derived from the
+/// identity secret.
+pub struct History {
+    path: PathBuf,
+    cipher: StorageCipher,
+    /// Binds records to this log so they cannot be moved between logs
+    aad: Vec<u8>,
+    my_id: String,
+    /// Where the last whole record ends, None until checked before an append
+    end: Mutex<Option<u64>>,
+}
+
+fn history_dir() -> PathBuf {
+    let mut dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    dir.push(".voicechat");
+    dir.push("history");
+    dir
+}
+
+fn history_cipher(identity_secret: &[u8; 32]) -> StorageCipher {
+    StorageCipher::from_identity(identity_secret, "history")
+}
+
+/// Every history log readable with this identity, oldest file first
+pub fn open_all(identity_secret: &[u8; 32], my_id: &str) -> io::Result<Vec<History>> {
+    let dir = history_dir();
+    let mut logs = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+            .collect::<Vec<_>>(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    logs.sort_by_key(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+
+    Ok(logs
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some(History {
+                path,
+                cipher: history_cipher(identity_secret),
+                aad: name.into_bytes(),
+                my_id: my_id.to_string(),
+                end: Mutex::new(None),
+            })
+        })
+        .collect())
+}
+
+impl History {
+    /// The log for one peer key or room code
+    pub fn open(identity_secret: &[u8; 32], scope: HistoryScope, value: &str, my_id: &str) -> io::Result<Self> {
+        let cipher = history_cipher(identity_secret);
+        let scope = match scope {
+            HistoryScope::Peer => format!("peer {}", value),
+            HistoryScope::Room => format!("room {}", value),
+        };
+        let name = cipher.file_name(&scope);
+
+        let dir = history_dir();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            path: dir.join(format!("{}.log", name)),
+            cipher,
+            aad: name.into_bytes(),
+            my_id: my_id.to_string(),
+            end: Mutex::new(None),
+        })
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn record_text(&self, text: &ChatText, sender_name: &str, expires_at: Option<u64>) {
+        let event = match expires_at {
+            Some(expires_at) => HistoryEvent::ExpiringText {
+                id: text.id,
+                sender_id: text.sender_id.clone(),
+                sender_name: sender_name.to_string(),
+                body: text.body.clone(),
+                sent_at: text.sent_at,
+                reply_to: text.reply_to,
+                expires_at,
+            },
+            None => HistoryEvent::Text {
+                id: text.id,
+                sender_id: text.sender_id.clone(),
+                sender_name: sender_name.to_string(),
+                body: text.body.clone(),
+                sent_at: text.sent_at,
+                reply_to: text.reply_to,
+            },
+        };
+        self.append(&event);
+    }
+
+    pub fn record_edit(&self, id: MessageId, body: &str) {
+        self.append(&HistoryEvent::Edit {
+            id,
+            body: body.to_string(),
+        });
+    }
+
+    pub fn record_delete(&self, id: MessageId) {
+        self.append(&HistoryEvent::Delete { id });
+    }
+
+    pub fn record_system(&self, at: u64, body: &str) {
+        self.append(&HistoryEvent::System {
+            at,
+            body: body.to_string(),
+        });
+    }
+
+    /// Replay the whole log, applying edits and deletes and leaving out
+    /// expired messages
+    pub fn load(&self) -> io::Result<Vec<TranscriptEntry>> {
+        let (records, torn) = self.read_records()?;
+        let now = now_millis();
+
+        let mut entries: Vec<TranscriptEntry> = Vec::new();
+        let mut index: HashMap<MessageId, usize> = HashMap::new();
+        let mut unreadable = usize::from(torn);
+
+        for record in records {
+            let Some(event) = record.event else {
+                unreadable += 1;
+                continue;
+            };
+
+            let (id, sender_id, sender_name, body, sent_at, reply_to) = match event {
+                HistoryEvent::Text {
+                    id,
+                    sender_id,
+                    sender_name,
+                    body,
+                    sent_at,
+                    reply_to,
+                } => (id, sender_id, sender_name, body, sent_at, reply_to),
+                HistoryEvent::ExpiringText {
+                    id,
+                    sender_id,
+                    sender_name,
+                    body,
+                    sent_at,
+                    reply_to,
+                    expires_at,
+                } => {
+                    // Not purged yet; edits to it find no entry either
+                    if expires_at <= now {
+                        continue;
+                    }
+                    (id, sender_id, sender_name, body, sent_at, reply_to)
+                }
+                HistoryEvent::Edit { id, body } => {
+                    if let Some(&i) = index.get(&id) {
+                        entries[i].body = body;
+                        entries[i].edited = true;
+                    }
+                    continue;
+                }
+                HistoryEvent::Delete { id } => {
+                    if let Some(&i) = index.get(&id) {
+                        entries[i].body.clear();
+                        entries[i].deleted = true;
+                    }
+                    continue;
+                }
+                HistoryEvent::System { at, body } => {
+                    entries.push(TranscriptEntry::system(at, body));
+                    continue;
+                }
+            };
+
+            if index.contains_key(&id) {
+                continue;
+            }
+            index.insert(id, entries.len());
+            entries.push(TranscriptEntry {
+                id: Some(id),
+                mine: sender_id == self.my_id,
+                sender_id: Some(sender_id),
+                sender_name,
+                body,
+                sent_at,
+                reply_to,
+                edited: false,
+                deleted: false,
+            });
+        }
+
+        if unreadable > 0 {
+            tracing::warn!(records = unreadable, path = %self.path.display(), "skipped unreadable history records");
+        }
+        Ok(entries)
+    }
+
+    /// Rewrite the log without expired messages and their edits,
+    /// returning how many messages were dropped
+    pub fn purge_expired(&self) -> io::Result<usize> {
+        let (records, _) = self.read_records()?;
+        let now = now_millis();
+
+        let expired: HashSet<MessageId> = records
+            .iter()
+            .filter_map(|r| match r.event {
+                Some(HistoryEvent::ExpiringText { id, expires_at, .. }) if expires_at <= now => Some(id),
+                _ => None,
+            })
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        // Records we cannot read are kept, they may belong to another key
+        let kept: Vec<u8> = records
+            .iter()
+            .filter(|r| {
+                r.event
+                    .as_ref()
+                    .and_then(HistoryEvent::message_id)
+                    .is_none_or(|id| !expired.contains(&id))
+            })
+            .flat_map(|r| r.raw.iter().copied())
+            .collect();
+
+        // Replace the file in one step so a crash leaves either log whole
+        let tmp = self.path.with_extension("log.tmp");
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(&kept)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        *self.end.lock().unwrap() = None;
+        Ok(expired.len())
+    }
+
+    /// Every complete record in file order, and whether the log ends in
+    /// a torn write
+    fn read_records(&self) -> io::Result<(Vec<Record>, bool)> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+            Err(e) => return Err(e),
+        };
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset + 4 <= data.len() {
+            let len = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            let Some(sealed) = data.get(offset + 4..offset + 4 + len) else {
+                // Torn write at the end of the log
+                return Ok((records, true));
+            };
+            let event = self
+                .cipher
+                .open(sealed, &self.aad)
+                .and_then(|plain| bincode::deserialize::<HistoryEvent>(&plain).ok());
+            records.push(Record {
+                raw: data[offset..offset + 4 + len].to_vec(),
+                event,
+            });
+            offset += 4 + len;
+        }
+        Ok((records, offset < data.len()))
+    }
+
+    fn append(&self, event: &HistoryEvent) {
+        if let Err(e) = self.try_append(event) {
+            tracing::warn!(path = %self.path.display(), error = %e, "could not write history");
+        }
+    }
+
+    fn try_append(&self, event: &HistoryEvent) -> io::Result<()> {
+        let plain = bincode::serialize(event).map_err(io::Error::other)?;
+        let sealed = self.cipher.seal(&plain, &self.aad);
+
+        let mut record = (sealed.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(&sealed);
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+
+        // Drop a torn write first, or its length would swallow this record
+        let mut end = self.end.lock().unwrap();
+        let whole = match *end {
+            Some(whole) if whole == file.metadata()?.len() => whole,
+            _ => {
+                *end = None;
+                let (records, torn) = self.read_records()?;
+                let whole = records.iter().map(|r| r.raw.len() as u64).sum();
+                if torn {
+                    file.set_len(whole)?;
+                }
+                whole
+            }
+        };
+
+        // One write per record keeps records whole if we crash
+        file.write_all(&record)?;
+        *end = Some(whole + record.len() as u64);
+        Ok(())
+    }
+}
+
+/// "[2026-10-19 14:03] alice~3fa9c1d2: hello (edited)"
+pub fn format_entry(entry: &TranscriptEntry) -> String {
+    if entry.is_system() {
+        return format!("[{}] * {}", format_timestamp(entry.sent_at), entry.body);
+    }
+    let sender = if entry.mine { "you" } else { &entry.sender_name };
+    let body = if entry.deleted {
+        "(message deleted)".to_string()
+    } else if entry.edited {
+        format!("{} (edited)", entry.body)
+    } else {
+        entry.body.clone()
+    };
+    format!("[{}] {}: {}", format_timestamp(entry.sent_at), sender, body)
+}
+
+/// Case-insensitive substring match on message bodies
+pub fn search<'a>(entries: &'a [TranscriptEntry], text: &str) -> Vec<&'a TranscriptEntry> {
+    let needle = text.to_lowercase();
+    entries
+        .iter()
+        .filter(|e| !e.deleted && e.body.to_lowercase().contains(&needle))
+        .collect()
+}
+
+/// Milliseconds since the epoch as "YYYY-MM-DD HH:MM" in UTC
+pub fn format_timestamp(millis: u64) -> String {
+    let secs = millis / 1000;
+    let days = (secs / 86_400) as i64;
+    let (hour, minute) = ((secs % 86_400) / 3600, (secs % 3600) / 60);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+}
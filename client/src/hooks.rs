@@ -0,0 +1,230 @@
+//! Event hooks: external commands run when something happens in a
+//! session, e.g. a desktop notification when someone says your name.
+//!
+//!   [[hooks]]
+//!   on = "mention"
+//!   command = "notify-send \"$VOICECHAT_SENDER\" \"$VOICECHAT_BODY\""
+//!
+//! Each run gets the details in VOICECHAT_* variables and the event as
+//! one JSON line on stdin, the same line `--json` prints. Hooks run on
+//! their own threads and are killed after `timeout` seconds.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::peers::load_own_nick;
+use crate::session::Event;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// How often a running hook is checked for having exited
+const POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// Any message from the peer
+    Message,
+    /// A message from the peer with your name or one of `words` in it
+    Mention,
+    PeerJoined,
+    PeerLeft,
+    CallStarted,
+    CallEnded,
+}
+
+impl HookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::Message => "message",
+            HookEvent::Mention => "mention",
+            HookEvent::PeerJoined => "peer_joined",
+            HookEvent::PeerLeft => "peer_left",
+            HookEvent::CallStarted => "call_started",
+            HookEvent::CallEnded => "call_ended",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub on: HookEvent,
+    /// Run by `sh -c`, or `cmd /C` on Windows
+    pub command: String,
+    /// Seconds before the command is killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// For mention hooks, words that count besides your display name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<String>,
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+/// The configured hooks of one session
+pub struct Hooks {
+    hooks: Vec<HookConfig>,
+    room: String,
+    /// Our display name, which mention hooks also look for
+    nick: Mutex<Option<String>>,
+    /// Hooks still running, so leaving can wait for them
+    running: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookConfig>, room: &str) -> Self {
+        Self {
+            hooks,
+            room: room.to_string(),
+            nick: Mutex::new(load_own_nick()),
+            running: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    /// After /nick, so mentions of the new name count
+    pub fn set_nick(&self, nick: &str) {
+        *self.nick.lock().unwrap() = Some(nick.to_string());
+    }
+
+    /// Start the hooks that match `event`; `peer` is the peer's name as
+    /// shown right now
+    pub fn fire(&self, event: &Event, peer: &str) {
+        let nick = self.nick.lock().unwrap().clone();
+        for hook in &self.hooks {
+            if !matches(hook, event, nick.as_deref()) {
+                continue;
+            }
+            let mut env = vec![
+                ("VOICECHAT_EVENT", hook.on.as_str().to_string()),
+                ("VOICECHAT_ROOM", self.room.clone()),
+                ("VOICECHAT_PEER", peer.to_string()),
+            ];
+            match event {
+                Event::Message { id, sender, body, .. } => {
+                    env.push(("VOICECHAT_MESSAGE_ID", id.clone()));
+                    env.push(("VOICECHAT_SENDER", sender.clone()));
+                    env.push(("VOICECHAT_BODY", body.clone()));
+                }
+                Event::PeerLeft { reason } => env.push(("VOICECHAT_REASON", reason.clone())),
+                _ => {}
+            }
+            let json = serde_json::to_string(event).expect("event serializes");
+
+            let hook = hook.clone();
+            let running = Arc::clone(&self.running);
+            *running.0.lock().unwrap() += 1;
+            thread::spawn(move || {
+                run(&hook, &env, &json);
+                let (count, done) = &*running;
+                *count.lock().unwrap() -= 1;
+                done.notify_all();
+            });
+        }
+    }
+
+    /// Wait for running hooks to finish; each is bounded by its timeout
+    pub fn wait(&self) {
+        let (count, done) = &*self.running;
+        let mut count = count.lock().unwrap();
+        while *count > 0 {
+            count = done.wait(count).unwrap();
+        }
+    }
+}
+
+fn matches(hook: &HookConfig, event: &Event, nick: Option<&str>) -> bool {
+    match (hook.on, event) {
+        (HookEvent::Message, Event::Message { .. }) => true,
+        (HookEvent::Mention, Event::Message { body, .. }) => {
+            is_mention(body, &hook.words) || nick.is_some_and(|nick| is_mention(body, &[nick.to_string()]))
+        }
+        (HookEvent::PeerJoined, Event::PeerJoined { .. }) => true,
+        (HookEvent::PeerLeft, Event::PeerLeft { .. }) => true,
+        (HookEvent::CallStarted, Event::CallStarted) => true,
+        (HookEvent::CallEnded, Event::CallEnded) => true,
+        _ => false,
+    }
+}
+
+/// Whether `body` has one of `words` as a whole word, ignoring case.
+/// "@alice" and "alice:" count for alice, "malice" does not.
+pub fn is_mention(body: &str, words: &[String]) -> bool {
+    let body = body.to_lowercase();
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    words.iter().filter(|word| !word.is_empty()).any(|word| {
+        let word = word.to_lowercase();
+        body.match_indices(&word).any(|(start, _)| {
+            let before = body[..start].chars().next_back();
+            let after = body[start + word.len()..].chars().next();
+            !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+        })
+    })
+}
+
+fn run(hook: &HookConfig, env: &[(&str, String)], json: &str) {
+    let mut command = shell(&hook.command);
+    command
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(event = hook.on.as_str(), error = %e, "cannot run hook");
+            return;
+        }
+    };
+    // On its own thread, a hook that never reads stdin must not keep
+    // us from enforcing the timeout. One that closes it early is fine.
+    if let Some(mut stdin) = child.stdin.take() {
+        let json = json.to_string();
+        thread::spawn(move || writeln!(stdin, "{}", json).ok());
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(hook.timeout);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    warn!(event = hook.on.as_str(), %status, "hook failed");
+                }
+                return;
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                warn!(event = hook.on.as_str(), timeout = hook.timeout, "hook timed out, killing it");
+                child.kill().ok();
+                child.wait().ok();
+                return;
+            }
+            Ok(None) => thread::sleep(POLL),
+            Err(e) => {
+                warn!(event = hook.on.as_str(), error = %e, "lost track of hook");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
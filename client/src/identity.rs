@@ -1,7 +1,8 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use rand_core::OsRng;
-use ed25519_dalek::{Keypair, PUBLIC_KEY_LENGTH};
+use ed25519_dalek::{Keypair, Signer, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
 
 fn get_identity_path() -> PathBuf {
     let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
@@ -16,28 +17,43 @@ pub struct Identity {
 
 impl Identity {
     pub fn load_or_create() -> Self {
-        let id_path = get_identity_path();
-        
-        if id_path.exists() {
-            let bytes = fs::read(&id_path).expect("Failed to load identity");
-
-            let keypair =
-                Keypair::from_bytes(&bytes).expect("Invalid identity keypair");
+        Self::load_or_create_at(&get_identity_path())
+    }
 
-            Self { keypair }
+    /// The identity kept in `id_path`, created there on first use
+    pub fn load_or_create_at(id_path: &Path) -> Self {
+        if id_path.exists() {
+            Self::load(id_path).expect("Failed to load identity")
         } else {
             let mut rng = OsRng;
             let keypair = Keypair::generate(&mut rng);
 
             fs::create_dir_all(id_path.parent().unwrap()).unwrap();
-            fs::write(&id_path, keypair.to_bytes()).unwrap();
+            fs::write(id_path, keypair.to_bytes()).unwrap();
 
             Self { keypair }
         }
     }
 
+    /// An existing identity; fails if the file is missing or not a key
+    pub fn load(id_path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(id_path)?;
+        let keypair = Keypair::from_bytes(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid identity keypair: {}", e)))?;
+        Ok(Self { keypair })
+    }
+
     pub fn public_key_bytes(&self) -> [u8; PUBLIC_KEY_LENGTH] {
         self.keypair.public.to_bytes()
     }
+
+    /// Seed for keys that protect local files
+    pub fn secret_key_bytes(&self) -> [u8; 32] {
+        self.keypair.secret.to_bytes()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+        self.keypair.sign(message).to_bytes()
+    }
 }
 
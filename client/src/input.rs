@@ -0,0 +1,297 @@
+//! Line editing support shared by the TUI and line mode: the input
+//! history kept across sessions and tab completion.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use vc_core::crypto::storage::StorageCipher;
+use vc_core::protocol::nick::FINGERPRINT_SEPARATOR;
+
+use crate::status::StatusLine;
+
+/// Lines kept in the input history
+const MAX_ENTRIES: usize = 1000;
+
+/// Commands whose arguments are file paths
+const PATH_COMMANDS: &[&str] = &["send", "export"];
+
+/// Everything typed at the prompt, newest last. It holds message text,
+/// so the file is encrypted like the chat history, with a key of its own.
+pub struct InputHistory {
+    path: Option<PathBuf>,
+    cipher: StorageCipher,
+    entries: Vec<String>,
+}
+
+fn input_dir() -> PathBuf {
+    let mut dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    dir.push(".voicechat");
+    dir.push("input");
+    dir
+}
+
+impl InputHistory {
+    /// The history of this identity; kept in memory only if the file
+    /// cannot be used
+    pub fn open(identity_secret: &[u8; 32]) -> Self {
+        let cipher = StorageCipher::from_identity(identity_secret, "input history");
+        let dir = input_dir();
+        let path = dir.join(format!("{}.log", cipher.file_name("input")));
+        let mut history = Self {
+            path: None,
+            cipher,
+            entries: Vec::new(),
+        };
+
+        match fs::create_dir_all(&dir).and_then(|_| history.read(&path)) {
+            Ok(entries) => {
+                history.path = Some(path);
+                let total = entries.len();
+                history.entries = entries;
+                if total > MAX_ENTRIES {
+                    history.entries.drain(..total - MAX_ENTRIES);
+                    if let Err(e) = history.rewrite() {
+                        tracing::warn!(error = %e, "could not shorten input history");
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "input history unavailable"),
+        }
+        history
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Remember a submitted line, unless it repeats the last one
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if let Err(e) = self.append(line) {
+            tracing::warn!(error = %e, "could not save input history");
+        }
+    }
+
+    /// Index of the newest entry before `before` that contains `query`
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        let end = before.min(self.entries.len());
+        self.entries[..end].iter().rposition(|entry| entry.contains(query))
+    }
+
+    fn read(&self, path: &PathBuf) -> io::Result<Vec<String>> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        // Same framing as the chat history: u32 length, sealed line
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 4 <= data.len() {
+            let len = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            let Some(sealed) = data.get(offset + 4..offset + 4 + len) else {
+                break;
+            };
+            if let Some(line) = self.cipher.open(sealed, b"input").and_then(|l| String::from_utf8(l).ok()) {
+                entries.push(line);
+            }
+            offset += 4 + len;
+        }
+        Ok(entries)
+    }
+
+    fn record(&self, line: &str) -> Vec<u8> {
+        let sealed = self.cipher.seal(line.as_bytes(), b"input");
+        let mut record = (sealed.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(&sealed);
+        record
+    }
+
+    fn append(&self, line: &str) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        open_private(path, false)?.write_all(&self.record(line))
+    }
+
+    /// Replace the file with the entries kept in memory
+    fn rewrite(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("log.tmp");
+        let mut file = open_private(&tmp, true)?;
+        for entry in &self.entries {
+            file.write_all(&self.record(entry))?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+fn open_private(path: &PathBuf, truncate: bool) -> io::Result<fs::File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if truncate {
+        options.write(true).truncate(true);
+    } else {
+        options.append(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Where a completion goes and what may go there
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Completion {
+    /// Byte offset where the replaced word starts
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+impl Completion {
+    /// Longest text every candidate starts with
+    pub fn common_prefix(&self) -> &str {
+        let Some(first) = self.candidates.first() else {
+            return "";
+        };
+        let mut prefix = first.as_str();
+        for candidate in &self.candidates[1..] {
+            let len = prefix
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(candidate.len()), |((i, _), _)| i);
+            prefix = &prefix[..len];
+        }
+        prefix
+    }
+}
+
+/// Tab completion for slash commands, the peer's name and file paths
+#[derive(Clone)]
+pub struct Completer {
+    commands: Vec<&'static str>,
+    status: Arc<Mutex<StatusLine>>,
+}
+
+impl Completer {
+    pub fn new(commands: Vec<&'static str>, status: Arc<Mutex<StatusLine>>) -> Self {
+        Self { commands, status }
+    }
+
+    /// Candidates for the word that ends at byte offset `pos`
+    pub fn complete(&self, line: &str, pos: usize) -> Completion {
+        let before = &line[..pos];
+
+        if let Some(command) = before.strip_prefix('/') {
+            match command.split_once(char::is_whitespace) {
+                // Still typing the command name; "//" is a literal slash
+                None if !command.starts_with('/') => {
+                    let mut candidates: Vec<String> = self
+                        .commands
+                        .iter()
+                        .filter(|name| name.starts_with(&command.to_lowercase()))
+                        .map(|name| format!("/{} ", name))
+                        .collect();
+                    candidates.sort();
+                    return Completion { start: 0, candidates };
+                }
+                Some((name, rest)) if PATH_COMMANDS.contains(&name.to_lowercase().as_str()) => {
+                    // /send takes the whole rest, spaces included
+                    let start = if name.eq_ignore_ascii_case("send") {
+                        pos - rest.trim_start().len()
+                    } else {
+                        word_start(before)
+                    };
+                    return Completion {
+                        start,
+                        candidates: complete_path(&line[start..pos]),
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        let start = word_start(before);
+        let word = &before[start..];
+        Completion {
+            start,
+            candidates: self.complete_name(word, start == 0),
+        }
+    }
+
+    /// The peer's name without its fingerprint, "@" kept if typed
+    fn complete_name(&self, word: &str, line_start: bool) -> Vec<String> {
+        let (at, prefix) = match word.strip_prefix('@') {
+            Some(prefix) => ("@", prefix),
+            None => ("", word),
+        };
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let peer = self.status.lock().unwrap().peer_name().to_string();
+        let name = peer.split(FINGERPRINT_SEPARATOR).next().unwrap_or_default();
+        if !name.to_lowercase().starts_with(&prefix.to_lowercase()) {
+            return Vec::new();
+        }
+        // Addressing someone at the start of a message, as on IRC
+        let suffix = if line_start && at.is_empty() { ": " } else { " " };
+        vec![format!("{}{}{}", at, name, suffix)]
+    }
+}
+
+fn word_start(before: &str) -> usize {
+    before.rfind(char::is_whitespace).map_or(0, |i| i + 1)
+}
+
+/// Entries of the directory in `word` whose names continue it.
+/// Directories end in "/", hidden files show up once "." is typed.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir_part, file_prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let dir = if dir_part.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir_part)
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(file_prefix) || (name.starts_with('.') && !file_prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir_part, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// `~/file` as typed at the prompt
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map(|h| h.join(rest)).unwrap_or_else(|| rest.into()),
+        None => path.into(),
+    }
+}
@@ -0,0 +1,108 @@
+//! `--json` mode: requests come in as JSON lines on stdin and every
+//! event goes out as one JSON line on stdout, with nothing else there.
+//!
+//! Input:
+//!   {"type":"message","text":"hello"}
+//!   {"type":"message","text":"yes","reply_to":"3fa9c1"}
+//!   {"type":"command","line":"/nick bot"}
+
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use serde::Deserialize;
+
+use client::session::Event;
+
+use crate::ui::{Input, Ui};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Message {
+        text: String,
+        #[serde(default)]
+        reply_to: Option<String>,
+    },
+    /// Any slash command, as typed in the other frontends
+    Command { line: String },
+}
+
+impl Request {
+    /// The same input line a user would type
+    fn into_line(self) -> String {
+        match self {
+            Request::Message { text, reply_to: None } => format!("/msg {}", text),
+            Request::Message {
+                text,
+                reply_to: Some(id),
+            } => format!("/reply {} {}", id, text),
+            Request::Command { line } => line,
+        }
+    }
+}
+
+/// Write one event line to stdout
+pub fn emit(event: &Event) {
+    let line = serde_json::to_string(event).expect("event serializes");
+    let mut out = io::stdout().lock();
+    writeln!(out, "{}", line).ok();
+    out.flush().ok();
+}
+
+pub struct JsonUi;
+
+impl JsonUi {
+    /// Also starts reading requests from stdin; end of input leaves
+    /// the room
+    pub fn start(input: Sender<Input>) -> Self {
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => {
+                        if input.send(Input::Line(request.into_line())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => emit(&Event::Error {
+                        message: format!("Invalid request: {}", e),
+                    }),
+                }
+            }
+            input.send(Input::Line("/exit".to_string())).ok();
+        });
+        Self
+    }
+}
+
+impl Ui for JsonUi {
+    fn print(&self, text: &str) {
+        emit(&Event::Output { text: text.to_string() });
+    }
+
+    fn notify(&self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let text = text.strip_prefix("[SYSTEM]: ").unwrap_or(text);
+        emit(&Event::System { text: text.to_string() });
+    }
+
+    fn refresh(&self) {}
+
+    fn event(&self, event: Event, _shown: &str) {
+        emit(&event);
+    }
+
+    fn error(&self, text: &str) {
+        emit(&Event::Error {
+            message: text.to_string(),
+        });
+    }
+}
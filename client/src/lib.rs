@@ -0,0 +1,33 @@
+//! The VoiceChat client as a library. `session` is the entry point for
+//! programs that embed it; the `client` binary is built on top.
+
+pub mod commands;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
+pub mod conversation;
+pub mod export;
+pub mod history;
+pub mod hooks;
+pub mod identity;
+pub mod input;
+pub mod logging;
+pub mod peers;
+pub mod reconnect;
+pub mod session;
+pub mod status;
+pub mod transfer;
+
+pub(crate) fn read_line_unbuffered(stream: &mut std::net::TcpStream, line: &mut String) -> anyhow::Result<()> {
+    use std::io::Read;
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte)?;
+        let ch = byte[0] as char;
+        if ch == '\n' {
+            break;
+        }
+        line.push(ch);
+    }
+    Ok(())
+}
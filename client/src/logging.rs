@@ -0,0 +1,56 @@
+//! Diagnostics go through `tracing`. The client binary installs the
+//! subscriber below; programs embedding the library install their own.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+
+/// Set by `init` when logs go to a file
+static LOG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The file `init` sends logs to, if any
+pub fn log_file() -> Option<&'static Path> {
+    LOG_FILE.get().map(PathBuf::as_path)
+}
+
+/// Parse a filter in RUST_LOG syntax, e.g. "warn,vc_core::net=trace"
+pub fn filter(level: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(level).map_err(|e| e.to_string())
+}
+
+/// Log events that pass `level` to `file`, or to stderr without it.
+/// Call once, before the first session is started.
+pub fn init(level: &str, format: LogFormat, file: Option<&Path>) -> io::Result<()> {
+    let filter = filter(level).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let file = match file {
+        Some(path) => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            LOG_FILE.set(path.to_path_buf()).ok();
+            Some(file)
+        }
+        None => None,
+    };
+
+    // On a terminal the time stamp is noise next to the chat
+    let result = match (file, format) {
+        (Some(file), LogFormat::Json) => builder.json().with_writer(Mutex::new(file)).try_init(),
+        (Some(file), LogFormat::Text) => builder.with_ansi(false).with_writer(Mutex::new(file)).try_init(),
+        (None, LogFormat::Json) => builder.json().with_writer(io::stderr).try_init(),
+        (None, LogFormat::Text) => builder
+            .without_time()
+            .with_ansi(io::stderr().is_terminal())
+            .with_writer(io::stderr)
+            .try_init(),
+    };
+    result.map_err(|e| io::Error::other(e.to_string()))
+}
@@ -1,219 +1,138 @@
-use std::env;
-mod identity;
 pub mod app;
 pub mod host;
+mod args;
 mod cli;
-use base64::{engine::general_purpose,Engine};
+mod json;
+mod tools;
+mod tui;
+mod ui;
 
-use vc_core::{room::code::{generate_room_code,validate_room_code}};
+use std::io;
+use std::process;
 
-fn main() -> anyhow::Result<()> {
-    let args:Vec<String>=env::args().collect();
-
-    if args.len()<2{
-        print_usage();
-        return Ok(());
-    }
-
-    // Get server address from environment variable or use default
-    let server_addr = std::env::var("SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:9000".to_string());
-    println!("Using server: {}", server_addr);
-
-    let identity=identity::Identity::load_or_create();
-    println!("My Public identity:{:?}",identity.public_key_bytes());
-
-    match args[1].as_str(){
-        "create"=>{
-            let room_code=generate_room_code();
-            let mut stream=
-                std::net::TcpStream::connect(&server_addr).expect("Cannot connect to the signaling");
-            use std::io::Write;
-            let pubkey_b64=
-                general_purpose::STANDARD.encode(identity.public_key_bytes());
-            writeln!(stream, "CREATE {} {}",room_code,pubkey_b64).unwrap();
-
-            println!("Room Created");
-            println!("Room Code: {}",room_code);
-            println!("Waiting for peer to join...");
-            
-            // Wait for PEER_PUBKEY message just like JOIN does
-            let mut line=String::new();
-            read_line_unbuffered(&mut stream, &mut line)?;
-            
-            let parts:Vec<&str>=line.trim().split_whitespace().collect();
-            if parts.len()==3 && parts[0]=="PEER_PUBKEY"{
-                let peer_pubkey=general_purpose::STANDARD
-                    .decode(parts[1])
-                    .expect("Invalid base64 peer key");
-
-                let role = parts[2];
-                println!("Received peer public key ({} bytes), role: {}",peer_pubkey.len(), role);
-
-                // Convert peer_pubkey to [u8; 32]
-                let peer_pubkey_array: [u8; 32] = peer_pubkey.as_slice()
-                    .try_into()
-                    .map_err(|_| anyhow::anyhow!("Peer public key must be 32 bytes"))?;
-
-                //1.Perform secure handshake based on role
-                let secure_stream = if role == "CLIENT" {
-                    vc_core::protocol::handshake::run(
-                        stream,
-                        identity.public_key_bytes(),
-                        peer_pubkey_array,
-                    )?
-                } else {
-                    vc_core::protocol::handshake::run_as_host(
-                        stream,
-                        identity.public_key_bytes(),
-                        peer_pubkey_array,
-                    )?
-                };
+use clap::{CommandFactory, Parser};
 
-                println!("Secure connection established!");
+use client::config::{self, Config};
+use client::export;
+use client::identity;
+use client::input::expand_home;
+use client::logging;
+use client::peers::{load_own_nick, save_own_nick};
+use client::session::{self, Event, Step, Target};
+use tracing::info;
+use vc_core::protocol::nick::{clean_nick, fingerprint};
 
-                // Generate UDP ports for voice based on role
-                let (my_voice_port, peer_voice_port, my_sender_id) = if role == "CLIENT" {
-                    (9001u16, 9002u16, 1u32)  // Client = ID 1
-                } else {
-                    (9002u16, 9001u16, 2u32)  // Host = ID 2
-                };
+use args::{Args, Command, ConfigAction};
 
-                // Start voice session
-                let voice_send_addr = format!("127.0.0.1:{}", peer_voice_port);
-                let voice_recv_bind = format!("0.0.0.0:{}", my_voice_port);
-                
-                let _voice_session = audio::VoiceSession::start(
-                    my_sender_id,
-                    &voice_send_addr,
-                    &voice_recv_bind,
-                )?;
-
-                //2. Start Chat Here
-                let sender_id = general_purpose::STANDARD.encode(identity.public_key_bytes());
-                cli::input_loop(secure_stream, sender_id)?;
-                return Ok(());
-            }
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let flags = args.setting_flags();
+    let config_path = args.config.clone().unwrap_or_else(config::config_path);
+    // --json: events as JSON lines on stdout, no audio
+    let json = matches!(args.command, Command::Create { json: true } | Command::Join { json: true, .. });
+    // Progress output; in --json mode stdout is kept for events
+    let say = |text: &str| if json { eprintln!("{}", text) } else { println!("{}", text) };
+    let fail = |text: &str| -> ! {
+        if json {
+            json::emit(&Event::Error { message: text.to_string() });
+        } else {
+            eprintln!("Error: {}", text);
         }
-        "join"=>{
-            if args.len()<3{
-                println!("Error:room code missing");
-                return Ok(());
-            }
-            let code=&args[2];
-
-            if !validate_room_code(code){
-                println!("Error:invalid room code format");
-                return Ok(());
-            }
-            let mut stream=
-                std::net::TcpStream::connect(&server_addr).expect("Cannot connect to signaling");
-
-            use std::io::{BufReader,BufRead,Write};
-            let pubkey_b64=
-                general_purpose::STANDARD.encode(identity.public_key_bytes());
-            writeln!(stream, "JOIN {} {}",code,pubkey_b64).unwrap();
-
-            // Read responses line by line WITHOUT BufReader to avoid buffering issues
-            let mut response=String::new();
-            read_line_unbuffered(&mut stream, &mut response)?;
-
-            let resp=response.trim().to_uppercase();
-
-            match resp.as_str(){
-                "ROOM_EXISTS" | "ROOM_JOINED"=>{
-                    println!("Connected to room {}",code);
-                
-                    let mut line=String::new();
-                    read_line_unbuffered(&mut stream, &mut line)?;
-                    
-                    let parts:Vec<&str>=line.trim().split_whitespace().collect();
-                    if parts.len()==3 && parts[0]=="PEER_PUBKEY"{
-                        let peer_pubkey=general_purpose::STANDARD
-                            .decode(parts[1])
-                            .expect("Invalid base64 peer key");
-
-                        let role = parts[2];
-                        println!("Received peer public key ({} bytes), role: {}",peer_pubkey.len(), role);
-
-                        // Convert peer_pubkey to [u8; 32]
-                        let peer_pubkey_array: [u8; 32] = peer_pubkey.as_slice()
-                            .try_into()
-                            .map_err(|_| anyhow::anyhow!("Peer public key must be 32 bytes"))?;
-
-                        //1.Perform secure handshake based on role
-                        let secure_stream = if role == "CLIENT" {
-                            vc_core::protocol::handshake::run(
-                                stream,
-                                identity.public_key_bytes(),
-                                peer_pubkey_array,
-                            )?
-                        } else {
-                            vc_core::protocol::handshake::run_as_host(
-                                stream,
-                                identity.public_key_bytes(),
-                                peer_pubkey_array,
-                            )?
-                        };
-
-                        println!("Secure connection established!");
-
-                        // Generate UDP ports for voice based on role
-                        let (my_voice_port, peer_voice_port, my_sender_id) = if role == "CLIENT" {
-                            (9001u16, 9002u16, 1u32)  // Client = ID 1
-                        } else {
-                            (9002u16, 9001u16, 2u32)  // Host = ID 2
-                        };
-
-                        // Start voice session
-                        let voice_send_addr = format!("127.0.0.1:{}", peer_voice_port);
-                        let voice_recv_bind = format!("0.0.0.0:{}", my_voice_port);
-                        
-                        let _voice_session = audio::VoiceSession::start(
-                            my_sender_id,
-                            &voice_send_addr,
-                            &voice_recv_bind,
-                        )?;
-
-                        //2. Start Chat Here
-                        let sender_id = general_purpose::STANDARD.encode(identity.public_key_bytes());
-                        cli::input_loop(secure_stream, sender_id)?;
-                        return Ok(());
-                    }
-                }
-                "ROOM_NOT_FOUND"=>{
-                    println!("Error:room not found");
+        process::exit(1);
+    };
+
+    // These still work when the settings are broken, to fix or report them
+    match &args.command {
+        Command::Config { action } => {
+            match action {
+                ConfigAction::Show => print!("{}", config::show(&config_path, &flags)?),
+                ConfigAction::Set { key, value } => {
+                    config::set(&config_path, key, Some(value))?;
+                    eprintln!("Set {} in {}", key, config_path.display());
                 }
-                "ROOM_FULL"=>{
-                    println!("Error:room full");
+                ConfigAction::Unset { key } => {
+                    config::set(&config_path, key, None)?;
+                    eprintln!("Unset {} in {}", key, config_path.display());
                 }
-                _=>{}
             }
+            return Ok(());
         }
-        _ =>{
-            print_usage();
+        Command::Doctor => return tools::doctor(Config::load(&config_path, &flags), &config_path),
+        Command::Completions { shell } => {
+            clap_complete::generate(*shell, &mut Args::command(), "client", &mut io::stdout());
+            return Ok(());
         }
+        _ => {}
     }
-    Ok(())
-}
 
-fn read_line_unbuffered(stream: &mut std::net::TcpStream, line: &mut String) -> anyhow::Result<()> {
-    use std::io::Read;
-    let mut byte = [0u8; 1];
-    loop {
-        stream.read_exact(&mut byte)?;
-        let ch = byte[0] as char;
-        if ch == '\n' {
-            break;
+    let config = match Config::load(&config_path, &flags) {
+        Ok(config) => config,
+        Err(e) => fail(&format!("invalid settings: {}", e)),
+    };
+    let mode = if json { ui::UiMode::Json } else { ui::UiMode::from_setting(config.ui) };
+    let log_file = config.log.file.as_deref().map(expand_home);
+    logging::init(&config.log.level, config.log.format, log_file.as_deref())?;
+    info!(path = %config_path.display(), "using config");
+    let identity_path = expand_home(&config.identity);
+    let identity=identity::Identity::load_or_create_at(&identity_path);
+
+    // Offline commands, kept before the connection output so stdout
+    // holds only their result
+    match args.command {
+        Command::Identity => {
+            tools::show_identity(&identity_path, &identity);
+            return Ok(());
+        }
+        Command::Peers => {
+            tools::list_peers();
+            return Ok(());
         }
-        line.push(ch);
+        Command::Devices => return tools::list_devices(&config),
+        Command::Export(export_args) => return export::run(export_args.into(), &identity),
+        _ => {}
     }
-    Ok(())
-}
 
-fn print_usage(){
-    println!("Usage:");
-    println!(" create");
-    println!(" join <ROOM_CODE>");
-}
+    let server_addr = config.server.clone();
+    info!(server = %server_addr, "using server");
+
+    vc_core::crypto::keylog::warn_if_enabled();
 
+    info!(key = %fingerprint(&identity.public_key_bytes()), "using identity");
 
+    // A configured name is used as if set with /nick
+    if let Some(nick) = &config.nickname {
+        let nick = match clean_nick(nick) {
+            Ok(nick) => nick,
+            Err(e) => fail(&format!("invalid nickname: {}", e)),
+        };
+        if load_own_nick().as_ref() != Some(&nick) {
+            save_own_nick(&nick)?;
+        }
+    }
+
+    let target = match args.command {
+        Command::Create { .. } => Target::Create,
+        Command::Join { code, .. } => Target::Join(code),
+        _ => unreachable!("offline commands returned above"),
+    };
+    // Scripts in --json mode may run where there is no audio device
+    let voice_options = config.voice_options();
+    let voice = (!json && !args.no_voice).then_some((config.voice_ports(), &voice_options));
+    let progress = |step: Step| match step {
+        Step::RoomCreated { room } => {
+            if json {
+                json::emit(&Event::RoomCreated { room: room.clone() });
+            }
+            say("Room Created");
+            say(&format!("Room Code: {}", room));
+            say("Waiting for peer to join...");
+        }
+        Step::Secured => say("Secure connection established!"),
+        step => info!(?step, "connecting"),
+    };
+    let session = match session::connect(&server_addr, &target, identity, voice, progress) {
+        Ok(session) => session,
+        Err(e) => fail(&e.to_string()),
+    };
+    cli::input_loop(session, mode, config.hooks)
+}
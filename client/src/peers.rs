@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose, Engine};
+use vc_core::protocol::nick::display_name;
+
+/// Name shown for a peer that never announced one
+const UNNAMED: &str = "peer";
+
+fn voicechat_dir() -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(".voicechat");
+    path
+}
+
+/// What we remember about one peer key
+#[derive(Debug, Clone, Default)]
+pub struct KnownPeer {
+    /// Local override, always wins over the announced name
+    pub alias: Option<String>,
+    /// Last display name the peer announced with a valid signature
+    pub nick: Option<String>,
+}
+
+/// ~/.voicechat/known_peers, one `<key> TAB <alias> TAB <nick>` line
+/// per peer, with `-` for an unset field
+pub struct KnownPeers {
+    path: PathBuf,
+    peers: HashMap<String, KnownPeer>,
+}
+
+impl KnownPeers {
+    pub fn load() -> Self {
+        let path = voicechat_dir().join("known_peers");
+        let mut peers = HashMap::new();
+
+        if let Ok(text) = fs::read_to_string(&path) {
+            for line in text.lines().filter(|l| !l.starts_with('#')) {
+                let mut fields = line.split('\t');
+                let Some(key) = fields.next().filter(|k| !k.is_empty()) else {
+                    continue;
+                };
+                let field = |f: Option<&str>| f.filter(|v| !v.is_empty() && *v != "-").map(String::from);
+                let alias = field(fields.next());
+                let nick = field(fields.next());
+                peers.insert(key.to_string(), KnownPeer { alias, nick });
+            }
+        }
+
+        Self { path, peers }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut out = String::from("# voicechat known peers: key<TAB>alias<TAB>nick\n");
+        let mut keys: Vec<&String> = self.peers.keys().collect();
+        keys.sort();
+        for key in keys {
+            let peer = &self.peers[key];
+            out.push_str(&format!(
+                "{}\t{}\t{}\n",
+                key,
+                peer.alias.as_deref().unwrap_or("-"),
+                peer.nick.as_deref().unwrap_or("-")
+            ));
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, out)
+    }
+
+    /// Every known peer, ordered by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &KnownPeer)> {
+        let mut peers: Vec<(&str, &KnownPeer)> = self.peers.iter().map(|(k, p)| (k.as_str(), p)).collect();
+        peers.sort_by_key(|(key, _)| *key);
+        peers.into_iter()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&KnownPeer> {
+        self.peers.get(key)
+    }
+
+    pub fn set_alias(&mut self, key: &str, alias: Option<String>) {
+        self.peers.entry(key.to_string()).or_default().alias = alias;
+    }
+
+    /// Remember a verified nick; returns the display names of other
+    /// known keys that used the same name before
+    pub fn record_nick(&mut self, key: &str, nick: &str) -> Vec<String> {
+        let others = self
+            .peers
+            .iter()
+            .filter(|(k, p)| k.as_str() != key && p.nick.as_deref() == Some(nick))
+            .map(|(k, _)| self.display(k))
+            .collect();
+        self.peers.entry(key.to_string()).or_default().nick = Some(nick.to_string());
+        others
+    }
+
+    /// Alias, else announced nick, else "peer", always with the key's
+    /// fingerprint
+    pub fn display(&self, key: &str) -> String {
+        let peer = self.peers.get(key);
+        let name = peer
+            .and_then(|p| p.alias.as_deref().or(p.nick.as_deref()))
+            .unwrap_or(UNNAMED);
+        display_name(name, &key_bytes(key))
+    }
+}
+
+/// Our own display name, kept in ~/.voicechat/nickname
+pub fn load_own_nick() -> Option<String> {
+    fs::read_to_string(voicechat_dir().join("nickname"))
+        .ok()
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+}
+
+pub fn save_own_nick(nick: &str) -> std::io::Result<()> {
+    let dir = voicechat_dir();
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("nickname"), format!("{}\n", nick))
+}
+
+/// Sender ids are base64 public keys
+fn key_bytes(key: &str) -> Vec<u8> {
+    general_purpose::STANDARD
+        .decode(key)
+        .unwrap_or_else(|_| key.as_bytes().to_vec())
+}
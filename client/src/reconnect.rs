@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+use vc_core::protocol::resume::{self, Resumed, ResumptionTicket};
+
+// Delay before each resume attempt
+const RETRY_DELAYS_SECS: [u64; 6] = [1, 2, 4, 8, 15, 30];
+// How long to wait at the signaling server for the peer to come back
+const PEER_WAIT: Duration = Duration::from_secs(30);
+
+/// Where to go to resume the session after the connection drops
+#[derive(Clone)]
+pub struct ReconnectInfo {
+    pub server_addr: String,
+    pub room_code: String,
+    pub pubkey_b64: String,
+}
+
+/// Keep trying to resume until it works, the ticket expires or
+/// `cancelled` returns true
+pub fn resume_with_retry(
+    info: &ReconnectInfo,
+    ticket: &ResumptionTicket,
+    received: u64,
+    cancelled: impl Fn() -> bool,
+) -> anyhow::Result<Resumed> {
+    let mut last_err = anyhow::anyhow!("no resume attempt made");
+
+    for delay in RETRY_DELAYS_SECS {
+        thread::sleep(Duration::from_secs(delay));
+        if cancelled() {
+            anyhow::bail!("resume cancelled");
+        }
+        if ticket.is_expired() {
+            anyhow::bail!("resumption ticket expired");
+        }
+
+        match resume_once(info, ticket, received) {
+            Ok(resumed) => return Ok(resumed),
+            Err(e) => {
+                tracing::info!(error = %e, "resume attempt failed");
+                last_err = e;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+fn resume_once(
+    info: &ReconnectInfo,
+    ticket: &ResumptionTicket,
+    received: u64,
+) -> anyhow::Result<Resumed> {
+    let mut stream = TcpStream::connect(&info.server_addr)?;
+    writeln!(stream, "RESUME {} {}", info.room_code, info.pubkey_b64)?;
+
+    stream.set_read_timeout(Some(PEER_WAIT))?;
+    let mut line = String::new();
+    crate::read_line_unbuffered(&mut stream, &mut line)?;
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 3 || parts[0] != "PEER_PUBKEY" {
+        anyhow::bail!("unexpected signaling reply: {}", line.trim());
+    }
+
+    let peer_pubkey = general_purpose::STANDARD.decode(parts[1])?;
+    if peer_pubkey.as_slice() != ticket.peer_identity.as_bytes() {
+        anyhow::bail!("a different peer answered the resume request");
+    }
+
+    if parts[2] == "CLIENT" {
+        resume::resume_as_client(stream, ticket, received)
+    } else {
+        resume::resume_as_host(stream, ticket, received)
+    }
+}
@@ -0,0 +1,1257 @@
+//! Chat sessions for programs that embed the client: create or join a
+//! room, then talk to the peer through a `Session` handle and read what
+//! happens from `Session::events`. The `client` binary is one frontend
+//! over this module; it sets sessions up with `connect`, which reports
+//! each `Step` of the way.
+//!
+//! ```no_run
+//! use client::identity::Identity;
+//! use client::session::{Event, Session};
+//!
+//! let session = Session::join("127.0.0.1:9000", "ABC123", Identity::load_or_create())?;
+//! session.send_text("hello")?;
+//! for event in session.events() {
+//!     if let Event::Message { body, .. } = event {
+//!         println!("{}", body);
+//!     }
+//! }
+//! # Ok::<(), client::session::SessionError>(())
+//! ```
+
+use std::fmt;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose, Engine};
+use serde::Serialize;
+use tracing::{debug, trace, warn};
+
+use audio::{MuteSwitch, VoiceOptions, VoiceSession};
+use vc_core::net::frame::CloseReason;
+use vc_core::net::keepalive::{KeepaliveConfig, RttStats};
+use vc_core::net::secure_stream::{SecureStream, SecureStreamError};
+use vc_core::protocol::chat::{
+    now_millis, ChatDelete, ChatEdit, ChatMessage, ChatNick, ChatPresence, ChatReaction, ChatReceipt,
+    ChatTimer, ChatTyping, MessageId, ReceiptStatus,
+};
+use vc_core::protocol::expiry::format_ttl;
+use vc_core::protocol::nick::{clean_nick, display_name, fingerprint, signing_payload, verify_nick};
+use vc_core::protocol::presence::PresenceStatus;
+use vc_core::room::code::{generate_room_code, validate_room_code};
+use vc_core::state::padding::PaddingPolicy;
+
+use crate::conversation::Conversation;
+use crate::export::hex_id;
+use crate::history::{self, History, HistoryScope};
+use crate::identity::Identity;
+use crate::peers::{load_own_nick, save_own_nick, KnownPeers};
+use crate::reconnect::{self, ReconnectInfo};
+use crate::status::{describe, StatusLine};
+use crate::transfer::{self, Transfers};
+
+/// Short socket reads so the receiver hands the shared stream to
+/// senders often, e.g. during file transfers
+const READ_POLL: Duration = Duration::from_millis(50);
+
+/// How often disappearing messages are checked for expiry
+const EXPIRY_SWEEP: Duration = Duration::from_secs(1);
+
+/// Something that happened in a session, with the details scripts
+/// need. Text frontends render their own line from it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The code to share, before anyone joined; sent by frontends
+    RoomCreated {
+        room: String,
+    },
+    PeerJoined {
+        room: String,
+        peer: String,
+        fingerprint: String,
+    },
+    PeerLeft {
+        reason: String,
+    },
+    /// A new message from the peer
+    Message {
+        id: String,
+        sender: String,
+        key: String,
+        body: String,
+        timestamp_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to: Option<String>,
+    },
+    /// The peer edited, deleted or reacted to a message
+    Updated {
+        id: String,
+        text: String,
+    },
+    Receipt {
+        ids: Vec<String>,
+        status: &'static str,
+    },
+    Typing {
+        active: bool,
+    },
+    /// Voice started with start_voice
+    CallStarted,
+    /// Voice stopped as the session was closed
+    CallEnded,
+    /// Presence, timers, names, file transfers and connection changes
+    System {
+        text: String,
+    },
+    /// Output of a command; sent by frontends
+    Output {
+        text: String,
+    },
+    /// A request failed; sent by frontends
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    /// The signaling server could not be reached or dropped us
+    Connect(io::Error),
+    /// The signaling server answered something unexpected
+    Signaling(String),
+    InvalidCode,
+    RoomNotFound,
+    RoomFull,
+    Handshake(String),
+    Voice(String),
+    /// A request that does not apply, e.g. an unknown message id
+    Invalid(String),
+    /// Presence changed too often, try again after this long
+    RateLimited(Duration),
+    Send(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Connect(e) => write!(f, "cannot reach the signaling server: {}", e),
+            SessionError::Signaling(reply) => write!(f, "unexpected signaling reply: {}", reply),
+            SessionError::InvalidCode => write!(f, "invalid room code format"),
+            SessionError::RoomNotFound => write!(f, "room not found"),
+            SessionError::RoomFull => write!(f, "room full"),
+            SessionError::Handshake(e) => write!(f, "secure handshake failed: {}", e),
+            SessionError::Voice(e) => write!(f, "cannot start voice: {}", e),
+            SessionError::Invalid(e) => write!(f, "{}", e),
+            SessionError::RateLimited(wait) => write!(
+                f,
+                "Changing status too often, try again in {:.0} s",
+                wait.as_secs_f64().ceil()
+            ),
+            SessionError::Send(e) => write!(f, "failed to send: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Connect(e)
+    }
+}
+
+/// Connection state that other threads can watch
+#[derive(Clone, Default)]
+pub struct Link {
+    connected: Arc<AtomicBool>,
+    resuming: Arc<AtomicBool>,
+    rtt: Arc<Mutex<Option<Duration>>>,
+}
+
+impl Link {
+    /// False once the session is over
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// True while a dropped connection is being resumed
+    pub fn is_resuming(&self) -> bool {
+        self.resuming.load(Ordering::SeqCst)
+    }
+
+    /// Smoothed round-trip time, refreshed every second
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.rtt.lock().unwrap()
+    }
+}
+
+/// Hands every event to all `events()` receivers still alive
+#[derive(Clone)]
+struct Emitter {
+    subscribers: Arc<Mutex<Vec<Sender<Event>>>>,
+}
+
+impl Emitter {
+    fn emit(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+/// Which end of the handshake we are, as assigned by the signaling server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Host,
+}
+
+/// UDP ports for voice, one per role: each side listens on its own and
+/// sends to the other's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoicePorts {
+    pub client: u16,
+    pub host: u16,
+}
+
+impl Default for VoicePorts {
+    fn default() -> Self {
+        Self { client: 9001, host: 9002 }
+    }
+}
+
+/// The room to set up a session in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Register a new room and wait for the peer
+    Create,
+    /// Join the room with this code
+    Join(String),
+}
+
+/// Progress of `connect`, reported as each step is done
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// The room is registered; the peer joins with this code
+    RoomCreated { room: String },
+    /// The signaling server let us into the room
+    RoomJoined { room: String },
+    /// The peer is in the room; the handshake starts
+    PeerFound { role: Role, fingerprint: String },
+    /// The end-to-end encrypted session is up
+    Secured,
+    /// Audio is flowing both ways
+    VoiceStarted,
+}
+
+/// Set up a session in `target` and, with `voice`, start audio in it.
+/// Both ends of a room go through the same steps; `progress` hears of
+/// each one and the first that fails ends the setup with its error.
+pub fn connect(
+    server: &str,
+    target: &Target,
+    identity: Identity,
+    voice: Option<(VoicePorts, &VoiceOptions)>,
+    mut progress: impl FnMut(Step),
+) -> Result<Session, SessionError> {
+    let pending = match target {
+        Target::Create => {
+            let pending = Session::create(server, identity)?;
+            progress(Step::RoomCreated {
+                room: pending.room_code.clone(),
+            });
+            pending
+        }
+        Target::Join(code) => {
+            let pending = PendingSession::join(server, code, identity)?;
+            progress(Step::RoomJoined {
+                room: pending.room_code.clone(),
+            });
+            pending
+        }
+    };
+
+    let mut session = pending.wait_with(&mut progress)?;
+    if let Some((ports, options)) = voice {
+        session.start_voice_with(ports, options)?;
+        progress(Step::VoiceStarted);
+    }
+    Ok(session)
+}
+
+/// A room registered at the signaling server, waiting for the peer
+pub struct PendingSession {
+    server: String,
+    room_code: String,
+    stream: TcpStream,
+    identity: Identity,
+}
+
+impl PendingSession {
+    /// The code the peer joins with
+    pub fn room_code(&self) -> &str {
+        &self.room_code
+    }
+
+    /// Block until the peer joins and the secure session is up
+    pub fn wait(self) -> Result<Session, SessionError> {
+        self.wait_with(|_| {})
+    }
+
+    fn join(server: &str, code: &str, identity: Identity) -> Result<Self, SessionError> {
+        if !validate_room_code(code) {
+            return Err(SessionError::InvalidCode);
+        }
+        let mut stream = TcpStream::connect(server)?;
+        writeln!(stream, "JOIN {} {}", code, pubkey_b64(&identity))?;
+
+        let mut response = String::new();
+        crate::read_line_unbuffered(&mut stream, &mut response).map_err(connect_error)?;
+        match response.trim().to_uppercase().as_str() {
+            "ROOM_EXISTS" | "ROOM_JOINED" => {}
+            "ROOM_NOT_FOUND" => return Err(SessionError::RoomNotFound),
+            "ROOM_FULL" => return Err(SessionError::RoomFull),
+            other => return Err(SessionError::Signaling(other.to_string())),
+        }
+        Ok(PendingSession {
+            server: server.to_string(),
+            room_code: code.to_string(),
+            stream,
+            identity,
+        })
+    }
+
+    fn wait_with(mut self, mut progress: impl FnMut(Step)) -> Result<Session, SessionError> {
+        let (peer_key, role) = read_peer(&mut self.stream)?;
+        progress(Step::PeerFound {
+            role,
+            fingerprint: fingerprint(&peer_key),
+        });
+        let public_key = self.identity.public_key_bytes();
+        let stream = match role {
+            Role::Client => vc_core::protocol::handshake::run(self.stream, public_key, peer_key),
+            Role::Host => vc_core::protocol::handshake::run_as_host(self.stream, public_key, peer_key),
+        }
+        .map_err(|e| SessionError::Handshake(e.to_string()))?;
+        let session = Session::start(self.server, self.room_code, stream, self.identity, role)?;
+        progress(Step::Secured);
+        Ok(session)
+    }
+}
+
+/// An end-to-end encrypted chat with one peer
+pub struct Session {
+    identity: Identity,
+    room_code: String,
+    role: Role,
+    sender_id: String,
+    peer_id: String,
+    peer_key: [u8; 32],
+    stream: Arc<Mutex<SecureStream>>,
+    conversation: Arc<Mutex<Conversation>>,
+    status: Arc<Mutex<StatusLine>>,
+    transfers: Arc<Mutex<Transfers>>,
+    known_peers: Arc<Mutex<KnownPeers>>,
+    link: Link,
+    emitter: Emitter,
+    /// Events from the start of the session, for the first events() call
+    first_events: Mutex<Option<Receiver<Event>>>,
+    voice: Option<VoiceSession>,
+    shutdown: Sender<()>,
+    receiver: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    /// Register a new room; the session starts once the peer joins
+    pub fn create(server: &str, identity: Identity) -> Result<PendingSession, SessionError> {
+        let room_code = generate_room_code();
+        let mut stream = TcpStream::connect(server)?;
+        writeln!(stream, "CREATE {} {}", room_code, pubkey_b64(&identity))?;
+        Ok(PendingSession {
+            server: server.to_string(),
+            room_code,
+            stream,
+            identity,
+        })
+    }
+
+    /// Join the room `code` and set up the secure session with whoever
+    /// created it
+    pub fn join(server: &str, code: &str, identity: Identity) -> Result<Session, SessionError> {
+        connect(server, &Target::Join(code.to_string()), identity, None, |_| {})
+    }
+
+    /// Everything after the handshake, the same for both roles
+    fn start(
+        server: String,
+        room_code: String,
+        mut stream: SecureStream,
+        identity: Identity,
+        role: Role,
+    ) -> Result<Session, SessionError> {
+        // Heartbeats detect a silently dead peer and measure RTT
+        stream.set_keepalive(KeepaliveConfig::default());
+        apply_traffic_shaping(&mut stream);
+        stream.set_read_timeout(READ_POLL);
+
+        // Incoming messages are attributed to the authenticated peer key,
+        // never to the sender_id a peer puts in the message
+        let peer_key = stream.peer_identity().to_bytes();
+        let peer_id = general_purpose::STANDARD.encode(peer_key);
+        let sender_id = pubkey_b64(&identity);
+
+        let conversation = Arc::new(Mutex::new(Conversation::new(sender_id.clone())));
+        let status = Arc::new(Mutex::new(StatusLine::new()));
+        let known_peers = Arc::new(Mutex::new(KnownPeers::load()));
+        refresh_peer_name(&peer_id, &known_peers, &conversation, &status);
+        let history = open_history(&identity, &peer_id, &room_code, &sender_id);
+        // Messages whose timer ran out while we were away
+        if let Some(history) = &history
+            && let Err(e) = history.purge_expired()
+        {
+            warn!(error = %e, "could not remove expired messages");
+        }
+        let peer_name = {
+            let mut conv = conversation.lock().unwrap();
+            conv.set_history(history);
+            let event = format!("Secure session with {} in room {}", conv.name_of(&peer_id), room_code);
+            conv.add_system(&event);
+            conv.name_of(&peer_id)
+        };
+
+        let emitter = Emitter {
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        };
+        let first_events = emitter.subscribe();
+        emitter.emit(Event::PeerJoined {
+            room: room_code.clone(),
+            peer: peer_name,
+            fingerprint: fingerprint(&peer_key),
+        });
+
+        let notify_emitter = emitter.clone();
+        let notify: transfer::Notify = Arc::new(move |line: &str| notify_emitter.emit(system_line(line)));
+        let transfers = Arc::new(Mutex::new(Transfers::new(transfer::download_dir_from_env(), notify)));
+
+        let link = Link::default();
+        link.connected.store(true, Ordering::SeqCst);
+        let (shutdown, shutdown_rx) = mpsc::channel();
+        let listener = Listener {
+            stream: Arc::new(Mutex::new(stream)),
+            conversation,
+            status,
+            transfers,
+            known_peers,
+            link,
+            emitter,
+            peer_id,
+            peer_key,
+            reconnect_info: ReconnectInfo {
+                server_addr: server,
+                room_code: room_code.clone(),
+                pubkey_b64: sender_id.clone(),
+            },
+        };
+
+        let mut session = Session {
+            identity,
+            room_code,
+            role,
+            sender_id,
+            peer_id: listener.peer_id.clone(),
+            peer_key,
+            stream: Arc::clone(&listener.stream),
+            conversation: Arc::clone(&listener.conversation),
+            status: Arc::clone(&listener.status),
+            transfers: Arc::clone(&listener.transfers),
+            known_peers: Arc::clone(&listener.known_peers),
+            link: listener.link.clone(),
+            emitter: listener.emitter.clone(),
+            first_events: Mutex::new(Some(first_events)),
+            voice: None,
+            shutdown,
+            receiver: None,
+        };
+        session.receiver = Some(thread::spawn(move || listener.run(shutdown_rx)));
+
+        session.set_own_name();
+        if let Some(nick) = load_own_nick() {
+            session.announce_nick(&nick)?;
+        }
+        Ok(session)
+    }
+
+    /// Everything that happens from now on. The first call also gets
+    /// the events since the session started, beginning with PeerJoined.
+    /// The iterator ends once the session is over.
+    pub fn events(&self) -> Receiver<Event> {
+        if let Some(events) = self.first_events.lock().unwrap().take() {
+            return events;
+        }
+        if !self.link.is_connected() {
+            return mpsc::channel().1;
+        }
+        self.emitter.subscribe()
+    }
+
+    pub fn room_code(&self) -> &str {
+        &self.room_code
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /// Our public key, base64, as it appears in transcripts
+    pub fn my_id(&self) -> &str {
+        &self.sender_id
+    }
+
+    /// The peer's authenticated public key, base64
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    pub fn peer_key(&self) -> &[u8; 32] {
+        &self.peer_key
+    }
+
+    pub fn peer_fingerprint(&self) -> String {
+        fingerprint(&self.peer_key)
+    }
+
+    /// The peer's announced name or our alias for them
+    pub fn peer_name(&self) -> String {
+        self.known_peers.lock().unwrap().display(&self.peer_id)
+    }
+
+    pub fn link(&self) -> Link {
+        self.link.clone()
+    }
+
+    pub fn conversation(&self) -> Arc<Mutex<Conversation>> {
+        Arc::clone(&self.conversation)
+    }
+
+    pub fn status(&self) -> Arc<Mutex<StatusLine>> {
+        Arc::clone(&self.status)
+    }
+
+    pub fn transfers(&self) -> Arc<Mutex<Transfers>> {
+        Arc::clone(&self.transfers)
+    }
+
+    pub fn known_peers(&self) -> Arc<Mutex<KnownPeers>> {
+        Arc::clone(&self.known_peers)
+    }
+
+    pub fn rtt_stats(&self) -> Option<RttStats> {
+        self.stream.lock().unwrap().rtt_stats()
+    }
+
+    pub fn send_text(&self, body: &str) -> Result<MessageId, SessionError> {
+        self.send_message(body, None)
+    }
+
+    pub fn reply(&self, to: MessageId, body: &str) -> Result<MessageId, SessionError> {
+        self.send_message(body, Some(to))
+    }
+
+    fn send_message(&self, body: &str, reply_to: Option<MessageId>) -> Result<MessageId, SessionError> {
+        debug!(bytes = body.len(), "sending message");
+        let txt = {
+            let mut conv = self.conversation.lock().unwrap();
+            let txt = conv.new_text(body, reply_to);
+            conv.add_text(&txt);
+            txt
+        };
+        let id = txt.id;
+        self.send(ChatMessage::Text(txt))?;
+        Ok(id)
+    }
+
+    /// Change the text of one of our messages
+    pub fn edit(&self, id: MessageId, body: &str) -> Result<(), SessionError> {
+        let edit = ChatEdit {
+            id,
+            body: body.to_string(),
+            edited_at: now_millis(),
+        };
+        self.conversation
+            .lock()
+            .unwrap()
+            .apply_edit(&self.sender_id, &edit)
+            .map_err(invalid)?;
+        self.send(ChatMessage::Edit(edit))
+    }
+
+    pub fn delete(&self, id: MessageId) -> Result<(), SessionError> {
+        let delete = ChatDelete { id };
+        self.conversation
+            .lock()
+            .unwrap()
+            .apply_delete(&self.sender_id, &delete)
+            .map_err(invalid)?;
+        self.send(ChatMessage::Delete(delete))
+    }
+
+    /// Add a reaction, or take it back if we already reacted with it
+    pub fn react(&self, id: MessageId, emoji: &str) -> Result<(), SessionError> {
+        let reaction = {
+            let mut conv = self.conversation.lock().unwrap();
+            let reaction = ChatReaction {
+                id,
+                emoji: emoji.to_string(),
+                remove: conv.has_reacted(&self.sender_id, id, emoji),
+            };
+            conv.apply_reaction(&self.sender_id, &reaction).map_err(invalid)?;
+            reaction
+        };
+        self.send(ChatMessage::Reaction(reaction))
+    }
+
+    /// Set the disappearing-message timer for both sides; false if it
+    /// already had this value
+    pub fn set_ttl(&self, ttl: Option<Duration>) -> Result<bool, SessionError> {
+        let timer = ChatTimer {
+            ttl_secs: ttl.map(|ttl| ttl.as_secs()),
+            set_at: now_millis(),
+        };
+        {
+            let mut conv = self.conversation.lock().unwrap();
+            if !conv.set_ttl(&timer) {
+                return Ok(false);
+            }
+            conv.add_system(&format!("You {}", describe_timer(timer.ttl_secs)));
+        }
+        self.send(ChatMessage::Timer(timer))?;
+        Ok(true)
+    }
+
+    pub fn set_presence(&self, status: PresenceStatus, text: Option<&str>) -> Result<ChatPresence, SessionError> {
+        let presence = self
+            .status
+            .lock()
+            .unwrap()
+            .set_mine(status, text)
+            .map_err(SessionError::RateLimited)?;
+        self.send(ChatMessage::Presence(presence.clone()))?;
+        Ok(presence)
+    }
+
+    /// Save and announce our display name; returns it as the peer sees it
+    pub fn set_nick(&self, name: &str) -> Result<String, SessionError> {
+        let nick = clean_nick(name).map_err(SessionError::Invalid)?;
+        if let Err(e) = save_own_nick(&nick) {
+            warn!(error = %e, "could not save display name");
+        }
+        self.announce_nick(&nick)?;
+        self.set_own_name();
+        Ok(display_name(&nick, &self.identity.public_key_bytes()))
+    }
+
+    /// Show the peer under our own name for them, or their announced
+    /// name again with None; returns the name now shown
+    pub fn set_alias(&self, alias: Option<&str>) -> Result<String, SessionError> {
+        let alias = alias.map(clean_nick).transpose().map_err(SessionError::Invalid)?;
+        {
+            let mut known = self.known_peers.lock().unwrap();
+            known.set_alias(&self.peer_id, alias);
+            if let Err(e) = known.save() {
+                warn!(error = %e, "could not save known peers");
+            }
+        }
+        refresh_peer_name(&self.peer_id, &self.known_peers, &self.conversation, &self.status);
+        Ok(self.peer_name())
+    }
+
+    /// Offer a file; returns the progress line to show
+    pub fn send_file(&self, path: impl AsRef<Path>) -> Result<String, SessionError> {
+        transfer::offer_file(&self.transfers, &self.stream, path.as_ref().to_path_buf()).map_err(SessionError::Invalid)
+    }
+
+    /// Accept the offer with this short id, or the only one pending
+    pub fn accept_file(&self, id: &str) -> Result<String, SessionError> {
+        let reply = self.transfers.lock().unwrap().accept(id);
+        let (reply, line) = reply.map_err(SessionError::Invalid)?;
+        self.send(reply)?;
+        Ok(line)
+    }
+
+    pub fn reject_file(&self, id: &str) -> Result<String, SessionError> {
+        let reply = self.transfers.lock().unwrap().reject(id);
+        let (reply, line) = reply.map_err(SessionError::Invalid)?;
+        self.send(reply)?;
+        Ok(line)
+    }
+
+    /// Typing indicators are best effort, not queued while resuming
+    pub fn set_typing(&self, active: bool) {
+        if self.link.is_resuming() {
+            return;
+        }
+        let msg = ChatMessage::Typing(ChatTyping { active });
+        if let Err(e) = send_chat_messgae(&mut self.stream.lock().unwrap(), &msg) {
+            warn!(error = %e, "could not send typing indicator");
+        }
+    }
+
+    /// Send read receipts for everything received so far
+    pub fn mark_read(&self) {
+        let ids = self.conversation.lock().unwrap().take_unread();
+        if ids.is_empty() {
+            return;
+        }
+        let receipt = ChatMessage::Receipt(ChatReceipt {
+            ids,
+            status: ReceiptStatus::Read,
+        });
+        if let Err(e) = send_chat_messgae(&mut self.stream.lock().unwrap(), &receipt) {
+            warn!(error = %e, "could not send read receipt");
+        }
+    }
+
+    /// Start sending and playing audio on the default ports and devices
+    pub fn start_voice(&mut self) -> Result<MuteSwitch, SessionError> {
+        self.start_voice_with(VoicePorts::default(), &VoiceOptions::default())
+    }
+
+    /// Start voice with the given ports, devices and codec settings;
+    /// which port is ours follows the role the signaling server gave us
+    pub fn start_voice_with(&mut self, ports: VoicePorts, options: &VoiceOptions) -> Result<MuteSwitch, SessionError> {
+        if let Some(voice) = &self.voice {
+            return Ok(voice.mute_switch());
+        }
+        let (my_voice_port, peer_voice_port, my_sender_id) = match self.role {
+            Role::Client => (ports.client, ports.host, 1u32),
+            Role::Host => (ports.host, ports.client, 2u32),
+        };
+        let voice = VoiceSession::start_with(
+            my_sender_id,
+            &format!("127.0.0.1:{}", peer_voice_port),
+            &format!("0.0.0.0:{}", my_voice_port),
+            options,
+        )
+        .map_err(|e| SessionError::Voice(e.to_string()))?;
+        let mute = voice.mute_switch();
+        self.voice = Some(voice);
+        self.emitter.emit(Event::CallStarted);
+        Ok(mute)
+    }
+
+    /// None while voice is off
+    pub fn mute_switch(&self) -> Option<MuteSwitch> {
+        self.voice.as_ref().map(|voice| voice.mute_switch())
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<(), SessionError> {
+        let mute = self
+            .mute_switch()
+            .ok_or_else(|| SessionError::Invalid("Voice is off".to_string()))?;
+        mute.set(muted);
+        Ok(())
+    }
+
+    /// Tell the peer we left and stop the session
+    pub fn close(mut self) {
+        self.leave();
+    }
+
+    fn leave(&mut self) {
+        let Some(receiver) = self.receiver.take() else {
+            return;
+        };
+        if self.voice.is_some() {
+            self.emitter.emit(Event::CallEnded);
+        }
+        self.stream.lock().unwrap().close(CloseReason::UserLeft).ok();
+        self.shutdown.send(()).ok();
+        // Wait for the receiver, unless it is sleeping between resume
+        // attempts
+        if !self.link.is_resuming() {
+            receiver.join().ok();
+        }
+        self.voice = None;
+    }
+
+    /// Our own name as it appears in transcripts and history
+    fn set_own_name(&self) {
+        let nick = load_own_nick().unwrap_or_else(|| "me".to_string());
+        let name = display_name(&nick, &self.identity.public_key_bytes());
+        self.conversation.lock().unwrap().set_display_name(&self.sender_id, name.clone());
+        self.status.lock().unwrap().set_my_name(name);
+    }
+
+    fn announce_nick(&self, nick: &str) -> Result<(), SessionError> {
+        let signature = self.identity.sign(&signing_payload(&self.identity.public_key_bytes(), nick));
+        self.send(ChatMessage::Nick(ChatNick {
+            name: nick.to_string(),
+            signature: signature.to_vec(),
+        }))
+    }
+
+    fn send(&self, msg: ChatMessage) -> Result<(), SessionError> {
+        let data = msg.to_bytes().map_err(|e| SessionError::Send(e.to_string()))?;
+        trace!(bytes = data.len(), "sending frame");
+        match self.stream.lock().unwrap().send(&data) {
+            Ok(()) => Ok(()),
+            // Rejected before it reached the outbox, so it will not be resent
+            Err(e @ (SecureStreamError::FrameTooLarge | SecureStreamError::Closed(_))) => {
+                Err(SessionError::Send(format!("Failed to send: {:?}", e)))
+            }
+            // Queued in the stream's outbox and resent after resume
+            Err(_) if self.link.is_resuming() => {
+                self.emitter.emit(Event::System {
+                    text: "Connection down, message will be sent once the session resumes".to_string(),
+                });
+                Ok(())
+            }
+            Err(e) => Err(SessionError::Send(format!("Failed to send: {:?}", e))),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+/// The receiver thread's share of the session
+struct Listener {
+    stream: Arc<Mutex<SecureStream>>,
+    conversation: Arc<Mutex<Conversation>>,
+    status: Arc<Mutex<StatusLine>>,
+    transfers: Arc<Mutex<Transfers>>,
+    known_peers: Arc<Mutex<KnownPeers>>,
+    link: Link,
+    emitter: Emitter,
+    peer_id: String,
+    peer_key: [u8; 32],
+    reconnect_info: ReconnectInfo,
+}
+
+impl Listener {
+    fn run(self, shutdown: Receiver<()>) {
+        let mut ticket = self.stream.lock().unwrap().resumption_ticket();
+        let mut last_sweep = Instant::now();
+        loop {
+            match shutdown.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => {
+                    debug!("receiver shutting down");
+                    break;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+
+            if last_sweep.elapsed() >= EXPIRY_SWEEP {
+                last_sweep = Instant::now();
+                let expired = self.conversation.lock().unwrap().expire(now_millis());
+                if expired > 0 {
+                    self.emitter.emit(Event::System {
+                        text: format!("{} message(s) disappeared", expired),
+                    });
+                }
+                *self.link.rtt.lock().unwrap() = self.stream.lock().unwrap().rtt_stats().map(|rtt| rtt.smoothed);
+            }
+
+            // Hold the lock only for the recv itself
+            let recv_result = self.stream.lock().unwrap().recv();
+
+            match recv_result {
+                Ok(data) => {
+                    trace!(bytes = data.len(), "received message");
+                    match ChatMessage::from_bytes(&data) {
+                        Ok(msg) => self.handle(msg),
+                        // A newer peer may send types we do not know yet
+                        Err(e) if e.is_skippable() => debug!(error = %e, "skipping message"),
+                        Err(e) => warn!(error = %e, "could not decode message"),
+                    }
+                }
+                // No data available yet, give senders a turn at the stream
+                Err(e) if e.is_timeout() => {
+                    thread::sleep(Duration::from_millis(2));
+                    continue;
+                }
+                Err(SecureStreamError::Closed(reason)) => {
+                    self.closed(reason);
+                    break;
+                }
+                Err(e @ (SecureStreamError::Crypto(_) | SecureStreamError::MalformedFrame)) => {
+                    tracing::error!(error = ?e, "closing session after a protocol error");
+                    self.stream.lock().unwrap().close(CloseReason::ProtocolError).ok();
+                    self.closed(CloseReason::ProtocolError);
+                    break;
+                }
+                // EOF, dead peer or socket error: the network dropped,
+                // so try to resume instead of giving up
+                Err(e) => {
+                    warn!(error = ?e, "connection lost, resuming");
+                    match self.resume(&e, &mut ticket, &shutdown) {
+                        Ok(()) => {}
+                        Err(e) => {
+                            self.conversation
+                                .lock()
+                                .unwrap()
+                                .add_system(&format!("Could not resume session: {}", e));
+                            self.emitter.emit(Event::PeerLeft {
+                                reason: format!("could not resume: {}", e),
+                            });
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.link.connected.store(false, Ordering::SeqCst);
+        // Dropping the emitter ends the event iterators
+        self.emitter.subscribers.lock().unwrap().clear();
+    }
+
+    fn handle(&self, msg: ChatMessage) {
+        // Duplicates are acked again, the first ack may have been lost
+        let ack = match &msg {
+            ChatMessage::Text(txt) => Some(txt.id),
+            _ => None,
+        };
+
+        let event = match msg {
+            ChatMessage::FileOffer(_)
+            | ChatMessage::FileAccept(_)
+            | ChatMessage::FileReject(_)
+            | ChatMessage::FileChunk(_)
+            | ChatMessage::FileComplete(_) => {
+                transfer::handle(&self.transfers, &self.stream, msg).map(|line| system_line(&line))
+            }
+            ChatMessage::Nick(nick) => {
+                self.handle_nick(nick);
+                None
+            }
+            msg => handle_incoming(
+                &mut self.conversation.lock().unwrap(),
+                &mut self.status.lock().unwrap(),
+                &self.peer_id,
+                msg,
+            ),
+        };
+        if let Some(event) = event {
+            self.emitter.emit(event);
+        }
+
+        if let Some(id) = ack {
+            let receipt = ChatMessage::Receipt(ChatReceipt {
+                ids: vec![id],
+                status: ReceiptStatus::Delivered,
+            });
+            if let Err(e) = send_chat_messgae(&mut self.stream.lock().unwrap(), &receipt) {
+                warn!(error = %e, "could not send delivery receipt");
+            }
+        }
+    }
+
+    fn closed(&self, reason: CloseReason) {
+        self.conversation
+            .lock()
+            .unwrap()
+            .add_system(&format!("Session closed ({})", reason));
+        self.emitter.emit(Event::PeerLeft {
+            reason: reason.to_string(),
+        });
+    }
+
+    fn resume(
+        &self,
+        error: &SecureStreamError,
+        ticket: &mut vc_core::protocol::resume::ResumptionTicket,
+        shutdown: &Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let text = if matches!(error, SecureStreamError::PeerTimeout) {
+            "Peer stopped responding, trying to resume..."
+        } else {
+            "Connection lost, trying to resume..."
+        };
+        self.emitter.emit(Event::System { text: text.to_string() });
+        self.conversation.lock().unwrap().add_system("Connection lost");
+        self.link.resuming.store(true, Ordering::SeqCst);
+
+        let received = self.stream.lock().unwrap().app_received();
+        let cancelled = || !matches!(shutdown.try_recv(), Err(TryRecvError::Empty));
+        let resumed = reconnect::resume_with_retry(&self.reconnect_info, ticket, received, cancelled).and_then(|resumed| {
+            let mut s = self.stream.lock().unwrap();
+            s.resume(resumed).map_err(|e| anyhow::anyhow!("Failed to resend: {:?}", e))?;
+            *ticket = s.resumption_ticket();
+            Ok(())
+        });
+        self.link.resuming.store(false, Ordering::SeqCst);
+        resumed?;
+
+        self.conversation.lock().unwrap().add_system("Session resumed");
+        self.emitter.emit(Event::System {
+            text: "Session resumed".to_string(),
+        });
+        retransmit_unacknowledged(&self.stream, &self.conversation);
+        for request in self.transfers.lock().unwrap().resume_requests() {
+            if let Err(e) = send_chat_messgae(&mut self.stream.lock().unwrap(), &request) {
+                warn!(error = %e, "could not resume download");
+            }
+        }
+        Ok(())
+    }
+
+    /// Accept a display name only when signed by the peer's identity key
+    fn handle_nick(&self, nick: ChatNick) {
+        if !verify_nick(&self.peer_key, &nick) {
+            warn!("display name signature check failed");
+            self.emitter.emit(system_line("Peer sent a display name with an invalid signature, ignored"));
+            return;
+        }
+
+        let (others, alias) = {
+            let mut known = self.known_peers.lock().unwrap();
+            if known.get(&self.peer_id).and_then(|p| p.nick.as_deref()) == Some(nick.name.as_str()) {
+                return;
+            }
+            let others = known.record_nick(&self.peer_id, &nick.name);
+            if let Err(e) = known.save() {
+                warn!(error = %e, "could not save known peers");
+            }
+            (others, known.get(&self.peer_id).and_then(|p| p.alias.clone()))
+        };
+        refresh_peer_name(&self.peer_id, &self.known_peers, &self.conversation, &self.status);
+
+        let shown = display_name(&nick.name, &self.peer_key);
+        self.conversation
+            .lock()
+            .unwrap()
+            .add_system(&format!("Peer announced the name {}", shown));
+        let line = match alias {
+            Some(alias) => format!("Peer calls themselves {}, shown as your alias {}", shown, alias),
+            None => format!("Peer is now known as {}", shown),
+        };
+        self.emitter.emit(system_line(&line));
+        if !others.is_empty() {
+            self.emitter.emit(system_line(&format!(
+                "Warning: {} used this name before, compare fingerprints",
+                others.join(", ")
+            )));
+        }
+    }
+}
+
+/// Apply a message from the peer and return the event for it, if any
+fn handle_incoming(conv: &mut Conversation, status: &mut StatusLine, peer_id: &str, msg: ChatMessage) -> Option<Event> {
+    let updated = match msg {
+        ChatMessage::Text(mut txt) => {
+            status.peer_sent_message();
+            txt.sender_id = peer_id.to_string();
+            if !conv.add_text(&txt) {
+                return None;
+            }
+            return Some(Event::Message {
+                id: hex_id(txt.id),
+                sender: conv.name_of(peer_id),
+                key: peer_id.to_string(),
+                body: txt.body,
+                timestamp_ms: txt.sent_at,
+                reply_to: txt.reply_to.map(hex_id),
+            });
+        }
+        ChatMessage::System(sys) => {
+            conv.add_system(&sys.body);
+            return Some(Event::System { text: sys.body });
+        }
+        ChatMessage::Edit(edit) => conv.apply_edit(peer_id, &edit).map(|_| edit.id),
+        ChatMessage::Delete(delete) => conv.apply_delete(peer_id, &delete).map(|_| delete.id),
+        ChatMessage::Reaction(reaction) => conv.apply_reaction(peer_id, &reaction).map(|_| reaction.id),
+        ChatMessage::Receipt(receipt) => {
+            let changed = conv.apply_receipt(&receipt);
+            if changed.is_empty() {
+                return None;
+            }
+            return Some(Event::Receipt {
+                ids: changed.into_iter().map(hex_id).collect(),
+                status: match receipt.status {
+                    ReceiptStatus::Delivered => "delivered",
+                    ReceiptStatus::Read => "read",
+                },
+            });
+        }
+        ChatMessage::Typing(typing) => {
+            let changed = status.set_peer_typing(typing.active);
+            return changed.then_some(Event::Typing { active: typing.active });
+        }
+        ChatMessage::Timer(timer) => {
+            if !conv.set_ttl(&timer) {
+                return None;
+            }
+            let text = format!("{} {}", conv.name_of(peer_id), describe_timer(timer.ttl_secs));
+            conv.add_system(&text);
+            return Some(Event::System { text });
+        }
+        ChatMessage::Presence(presence) => {
+            if !status.set_peer(&presence) {
+                debug!("dropping rate limited presence update");
+                return None;
+            }
+            let text = format!("{} is now {}", conv.name_of(peer_id), describe(status.peer()));
+            conv.add_system(&text);
+            return Some(Event::System { text });
+        }
+        // Handled by the receiver before it gets here
+        ChatMessage::FileOffer(_)
+        | ChatMessage::FileAccept(_)
+        | ChatMessage::FileReject(_)
+        | ChatMessage::FileChunk(_)
+        | ChatMessage::FileComplete(_)
+        | ChatMessage::Nick(_) => return None,
+    };
+
+    match updated {
+        Ok(id) => Some(Event::Updated {
+            id: hex_id(id),
+            text: conv.render(conv.get(id)?),
+        }),
+        Err(e) => {
+            debug!(error = %e, "ignoring update");
+            None
+        }
+    }
+}
+
+/// A progress or notice line as a System event, without the
+/// "[SYSTEM]: " its producers put in front
+fn system_line(line: &str) -> Event {
+    Event::System {
+        text: line.strip_prefix("[SYSTEM]: ").unwrap_or(line).to_string(),
+    }
+}
+
+/// "set disappearing messages to 5m" or "turned off disappearing messages"
+pub fn describe_timer(ttl_secs: Option<u64>) -> String {
+    match ttl_secs {
+        Some(secs) => format!("set disappearing messages to {}", format_ttl(Duration::from_secs(secs))),
+        None => "turned off disappearing messages".to_string(),
+    }
+}
+
+fn pubkey_b64(identity: &Identity) -> String {
+    general_purpose::STANDARD.encode(identity.public_key_bytes())
+}
+
+fn connect_error(e: anyhow::Error) -> SessionError {
+    match e.downcast::<io::Error>() {
+        Ok(e) => SessionError::Connect(e),
+        Err(e) => SessionError::Signaling(e.to_string()),
+    }
+}
+
+/// Wait for "PEER_PUBKEY <key> <role>" from the signaling server
+fn read_peer(stream: &mut TcpStream) -> Result<([u8; 32], Role), SessionError> {
+    let mut line = String::new();
+    crate::read_line_unbuffered(stream, &mut line).map_err(connect_error)?;
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 3 || parts[0] != "PEER_PUBKEY" {
+        return Err(SessionError::Signaling(line.trim().to_string()));
+    }
+    let peer_key = general_purpose::STANDARD
+        .decode(parts[1])
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key.as_slice()).ok())
+        .ok_or_else(|| SessionError::Signaling("peer public key must be 32 bytes of base64".to_string()))?;
+    let role = if parts[2] == "CLIENT" { Role::Client } else { Role::Host };
+    Ok((peer_key, role))
+}
+
+fn invalid(e: impl fmt::Display) -> SessionError {
+    SessionError::Invalid(e.to_string())
+}
+
+/// Length hiding from VOICECHAT_PADDING (none | buckets | mtu:<bytes>)
+/// and VOICECHAT_COVER_TRAFFIC (idle seconds between dummy frames)
+fn apply_traffic_shaping(stream: &mut SecureStream) {
+    if let Ok(policy) = std::env::var("VOICECHAT_PADDING") {
+        match policy.parse::<PaddingPolicy>() {
+            Ok(policy) => stream.set_padding(policy),
+            Err(e) => warn!("{}, sending unpadded frames", e),
+        }
+    }
+
+    if let Ok(secs) = std::env::var("VOICECHAT_COVER_TRAFFIC") {
+        match secs.parse::<f64>() {
+            Ok(secs) if secs > 0.0 => stream.set_cover_traffic(Some(Duration::from_secs_f64(secs))),
+            _ => warn!("invalid VOICECHAT_COVER_TRAFFIC: {}", secs),
+        }
+    }
+}
+
+/// History log for this peer or room, when VOICECHAT_HISTORY enables it
+fn open_history(identity: &Identity, peer_id: &str, room_code: &str, sender_id: &str) -> Option<History> {
+    let scope = history::scope_from_env()?;
+    let value = match scope {
+        HistoryScope::Peer => peer_id,
+        HistoryScope::Room => room_code,
+    };
+    match History::open(&identity.secret_key_bytes(), scope, value, sender_id) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            warn!(error = %e, "chat history unavailable");
+            None
+        }
+    }
+}
+
+/// Push the peer's current name from the known-peers store to
+/// everything that displays it
+fn refresh_peer_name(
+    peer_id: &str,
+    known_peers: &Mutex<KnownPeers>,
+    conversation: &Mutex<Conversation>,
+    status: &Mutex<StatusLine>,
+) {
+    let name = known_peers.lock().unwrap().display(peer_id);
+    conversation.lock().unwrap().set_display_name(peer_id, name.clone());
+    status.lock().unwrap().set_peer_name(name);
+}
+
+/// Send our messages again that were never acked; the peer drops
+/// the ones it already has by id
+fn retransmit_unacknowledged(stream: &Mutex<SecureStream>, conversation: &Mutex<Conversation>) {
+    let pending = conversation.lock().unwrap().unacknowledged();
+    if pending.is_empty() {
+        return;
+    }
+
+    debug!(messages = pending.len(), "retransmitting unacknowledged messages");
+    let mut s = stream.lock().unwrap();
+    for txt in pending {
+        if let Err(e) = send_chat_messgae(&mut s, &ChatMessage::Text(txt)) {
+            warn!(error = ?e, "retransmit failed");
+            break;
+        }
+    }
+}
+
+fn send_chat_messgae(stream: &mut SecureStream, msg: &ChatMessage) -> anyhow::Result<()> {
+    let data = msg.to_bytes()?;
+    trace!(bytes = data.len(), "sending frame");
+    stream.send(&data).map_err(|e| anyhow::anyhow!("Failed to send: {:?}", e))?;
+    Ok(())
+}
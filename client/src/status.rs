@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use vc_core::protocol::chat::ChatPresence;
+use vc_core::protocol::presence::{clamp_status_text, PresenceStatus, RateLimiter, TYPING_TIMEOUT};
+
+/// Presence and typing state of both sides, rendered into the prompt
+pub struct StatusLine {
+    mine: ChatPresence,
+    peer: ChatPresence,
+    peer_name: String,
+    my_name: String,
+    peer_typing_since: Option<Instant>,
+    /// Our own presence changes
+    outgoing: RateLimiter,
+    /// Typing and presence updates from the peer
+    incoming: RateLimiter,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        let online = ChatPresence {
+            status: PresenceStatus::Online,
+            text: None,
+        };
+        Self {
+            mine: online.clone(),
+            peer: online,
+            peer_name: "peer".to_string(),
+            my_name: "you".to_string(),
+            peer_typing_since: None,
+            outgoing: RateLimiter::new(3, Duration::from_secs(10)),
+            incoming: RateLimiter::new(10, Duration::from_secs(10)),
+        }
+    }
+
+    /// Change our presence, or say how long to wait when changing too often
+    pub fn set_mine(&mut self, status: PresenceStatus, text: Option<&str>) -> Result<ChatPresence, Duration> {
+        if !self.outgoing.allow() {
+            return Err(self.outgoing.retry_after());
+        }
+        self.mine = ChatPresence {
+            status,
+            text: text.map(clamp_status_text).filter(|t| !t.is_empty()),
+        };
+        Ok(self.mine.clone())
+    }
+
+    /// Apply the peer's presence; false when dropped by the rate limit
+    pub fn set_peer(&mut self, presence: &ChatPresence) -> bool {
+        if !self.incoming.allow() {
+            return false;
+        }
+        self.peer = ChatPresence {
+            status: presence.status,
+            text: presence.text.as_deref().map(clamp_status_text),
+        };
+        true
+    }
+
+    /// Returns true when the typing indicator changed
+    pub fn set_peer_typing(&mut self, active: bool) -> bool {
+        if !self.incoming.allow() {
+            return false;
+        }
+        let was_typing = self.peer_typing();
+        self.peer_typing_since = active.then(Instant::now);
+        was_typing != active
+    }
+
+    /// A message from the peer ends its typing
+    pub fn peer_sent_message(&mut self) {
+        self.peer_typing_since = None;
+    }
+
+    pub fn peer_typing(&self) -> bool {
+        self.peer_typing_since.is_some_and(|t| t.elapsed() < TYPING_TIMEOUT)
+    }
+
+    pub fn peer_name(&self) -> &str {
+        &self.peer_name
+    }
+
+    pub fn set_peer_name(&mut self, name: String) {
+        self.peer_name = name;
+    }
+
+    pub fn set_my_name(&mut self, name: String) {
+        self.my_name = name;
+    }
+
+    pub fn peer(&self) -> &ChatPresence {
+        &self.peer
+    }
+
+    pub fn my_name(&self) -> &str {
+        &self.my_name
+    }
+
+    pub fn mine(&self) -> &ChatPresence {
+        &self.mine
+    }
+
+    /// One line per participant for the TUI side pane, us first
+    pub fn participants(&self) -> Vec<String> {
+        let mut peer = format!("{} ({})", self.peer_name, describe(&self.peer));
+        if self.peer_typing() {
+            peer.push_str(" typing…");
+        }
+        vec![format!("{} ({})", self.my_name, describe(&self.mine)), peer]
+    }
+
+    /// e.g. "[bob~3fa9c1d2 away: lunch, typing…] [you: busy] > "
+    pub fn prompt(&self) -> String {
+        let mut peer = format!("{} {}", self.peer_name, describe(&self.peer));
+        if self.peer_typing() {
+            peer.push_str(", typing…");
+        }
+
+        let mut out = format!("[{}] ", peer);
+        if self.mine.status != PresenceStatus::Online || self.mine.text.is_some() {
+            out.push_str(&format!("[you: {}] ", describe(&self.mine)));
+        }
+        out.push_str("> ");
+        out
+    }
+}
+
+/// "away: lunch" or just "away"
+pub fn describe(presence: &ChatPresence) -> String {
+    match &presence.text {
+        Some(text) => format!("{}: {}", presence.status, text),
+        None => presence.status.to_string(),
+    }
+}
@@ -0,0 +1,154 @@
+//! Subcommands that do not join a room: identity, peers, devices and
+//! doctor
+
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+
+use client::config::{Config, ConfigError};
+use client::identity::Identity;
+use client::input::expand_home;
+use client::peers::{load_own_nick, KnownPeers};
+use vc_core::protocol::nick::{display_name, fingerprint};
+
+/// How long doctor waits for the signaling server
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn show_identity(path: &Path, identity: &Identity) {
+    let key = identity.public_key_bytes();
+    println!("Identity:    {}", path.display());
+    println!("Public key:  {}", general_purpose::STANDARD.encode(key));
+    println!("Fingerprint: {}", fingerprint(&key));
+    if let Some(nick) = load_own_nick() {
+        println!("Name:        {}", display_name(&nick, &key));
+    }
+}
+
+pub fn list_peers() {
+    let known = KnownPeers::load();
+    let mut any = false;
+    for (key, peer) in known.iter() {
+        any = true;
+        match (&peer.alias, &peer.nick) {
+            (Some(_), Some(nick)) => println!("{}  {}  (calls itself {})", known.display(key), key, nick),
+            _ => println!("{}  {}", known.display(key), key),
+        }
+    }
+    if !any {
+        println!("No known peers yet");
+    }
+}
+
+/// `*` marks the device a session would use
+pub fn list_devices(config: &Config) -> anyhow::Result<()> {
+    let devices = audio::list_devices()?;
+    let input = config.audio.input_device.clone().or(devices.default_input);
+    let output = config.audio.output_device.clone().or(devices.default_output);
+    for (title, names, used) in [
+        ("Input devices:", &devices.inputs, input),
+        ("Output devices:", &devices.outputs, output),
+    ] {
+        println!("{}", title);
+        if names.is_empty() {
+            println!("  (none)");
+        }
+        for name in names {
+            let mark = if used.as_ref() == Some(name) { '*' } else { ' ' };
+            println!("{} {}", mark, name);
+        }
+    }
+    Ok(())
+}
+
+/// Check everything a session needs, print one line per check and
+/// fail if any of them did
+pub fn doctor(config: Result<Config, ConfigError>, config_path: &Path) -> anyhow::Result<()> {
+    let mut failed = 0;
+    let mut report = |ok: bool, what: &str, detail: String| {
+        if !ok {
+            failed += 1;
+        }
+        println!("{} {:<9} {}", if ok { "[ok]  " } else { "[FAIL]" }, what, detail);
+    };
+
+    let config = match config {
+        Ok(config) => {
+            let detail = if config_path.exists() {
+                config_path.display().to_string()
+            } else {
+                format!("{} (not created, using defaults)", config_path.display())
+            };
+            report(true, "config", detail);
+            config
+        }
+        Err(e) => {
+            report(false, "config", format!("{}; checking with defaults", e));
+            Config::default()
+        }
+    };
+
+    let path = expand_home(&config.identity);
+    match Identity::load(&path) {
+        Ok(identity) => report(
+            true,
+            "identity",
+            format!("{} ({})", path.display(), fingerprint(&identity.public_key_bytes())),
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            report(true, "identity", format!("{} (created on first run)", path.display()))
+        }
+        Err(e) => report(false, "identity", format!("{}: {}", path.display(), e)),
+    }
+
+    match reach(&config.server) {
+        Ok(()) => report(true, "server", format!("{} is reachable", config.server)),
+        Err(e) => report(false, "server", format!("{}: {}", config.server, e)),
+    }
+
+    match audio::list_devices() {
+        Ok(devices) => {
+            for (what, wanted, names, default) in [
+                ("input", &config.audio.input_device, &devices.inputs, &devices.default_input),
+                ("output", &config.audio.output_device, &devices.outputs, &devices.default_output),
+            ] {
+                match (wanted, default) {
+                    (Some(name), _) if names.contains(name) => report(true, what, name.clone()),
+                    (Some(name), _) => report(false, what, format!("no device named {}", name)),
+                    (None, Some(name)) => report(true, what, format!("{} (default)", name)),
+                    (None, None) => report(false, what, "no default device".to_string()),
+                }
+            }
+        }
+        Err(e) => report(false, "audio", e.to_string()),
+    }
+
+    for (role, port) in [("client", config.voice.client_port), ("host", config.voice.host_port)] {
+        match UdpSocket::bind(("0.0.0.0", port)) {
+            Ok(_) => report(true, "port", format!("UDP {} ({}) is free", port, role)),
+            Err(e) => report(false, "port", format!("UDP {} ({}): {}", port, role, e)),
+        }
+    }
+
+    match audio::check_codec(&config.voice_options().codec) {
+        Ok(()) => report(true, "codec", "Opus encoder ready".to_string()),
+        Err(e) => report(false, "codec", e.to_string()),
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} check(s) failed", failed);
+    }
+    Ok(())
+}
+
+fn reach(server: &str) -> std::io::Result<()> {
+    let mut last = std::io::Error::new(std::io::ErrorKind::NotFound, "no address");
+    for addr in server.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use sha2::{Digest, Sha256};
+use vc_core::net::secure_stream::SecureStream;
+use vc_core::protocol::chat::{
+    short_id, ChatMessage, FileAccept, FileChunk, FileComplete, FileOffer, FileReject,
+};
+use vc_core::protocol::transfer::{
+    format_size, sanitize_filename, sha256_file, TransferId, CHUNK_SIZE,
+};
+
+/// Chunks written per lock of the shared stream, so chat messages
+/// never wait behind a whole file
+const CHUNKS_PER_LOCK: usize = 8;
+
+/// Progress is printed every this many percent
+const PROGRESS_STEP: u64 = 10;
+
+/// Prints a line from a background thread and redraws the prompt
+pub type Notify = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// VOICECHAT_DOWNLOAD_DIR, or ~/.voicechat/downloads
+pub fn download_dir_from_env() -> PathBuf {
+    match std::env::var_os("VOICECHAT_DOWNLOAD_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+            path.push(".voicechat");
+            path.push("downloads");
+            path
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Offered,
+    Transferring,
+    Done,
+    Rejected,
+    Failed(String),
+}
+
+struct Outgoing {
+    path: PathBuf,
+    offer: FileOffer,
+    state: State,
+    /// Bumped for every accept, older sender threads stop when it changes
+    generation: Arc<AtomicU64>,
+}
+
+struct Incoming {
+    offer: FileOffer,
+    state: State,
+    part_path: PathBuf,
+    file: Option<File>,
+    hasher: Sha256,
+    received: u64,
+    saved_as: Option<PathBuf>,
+}
+
+/// File transfers of the current session, both directions
+pub struct Transfers {
+    download_dir: PathBuf,
+    outgoing: HashMap<TransferId, Outgoing>,
+    incoming: HashMap<TransferId, Incoming>,
+    notify: Notify,
+}
+
+impl Transfers {
+    pub fn new(download_dir: PathBuf, notify: Notify) -> Self {
+        Self {
+            download_dir,
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+            notify,
+        }
+    }
+
+    /// Open a .part file for a pending offer and ask the peer to start
+    /// The reply for the peer and a line for the user
+    pub fn accept(&mut self, short: &str) -> Result<(ChatMessage, String), String> {
+        let id = self.resolve_offer(short)?;
+        fs::create_dir_all(&self.download_dir)
+            .map_err(|e| format!("Cannot create {}: {}", self.download_dir.display(), e))?;
+
+        let incoming = self.incoming.get_mut(&id).expect("resolved offer");
+        let file = File::create(&incoming.part_path)
+            .map_err(|e| format!("Cannot create {}: {}", incoming.part_path.display(), e))?;
+        incoming.file = Some(file);
+        incoming.state = State::Transferring;
+
+        let line = format!(
+            "[FILE] Receiving {} ({}) into {}",
+            incoming.offer.name,
+            format_size(incoming.offer.size),
+            self.download_dir.display()
+        );
+        Ok((ChatMessage::FileAccept(FileAccept { id, offset: 0 }), line))
+    }
+
+    pub fn reject(&mut self, short: &str) -> Result<(ChatMessage, String), String> {
+        let id = self.resolve_offer(short)?;
+        let incoming = self.incoming.get_mut(&id).expect("resolved offer");
+        incoming.state = State::Rejected;
+        let line = format!("[FILE] Rejected {}", incoming.offer.name);
+        Ok((ChatMessage::FileReject(FileReject { id }), line))
+    }
+
+    /// After a reconnect, ask the peer to continue every unfinished
+    /// download where it stopped
+    pub fn resume_requests(&self) -> Vec<ChatMessage> {
+        self.incoming
+            .values()
+            .filter(|t| t.state == State::Transferring)
+            .map(|t| {
+                ChatMessage::FileAccept(FileAccept {
+                    id: t.offer.id,
+                    offset: t.received,
+                })
+            })
+            .collect()
+    }
+
+    /// One line per transfer for /transfers
+    pub fn list(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for t in self.outgoing.values() {
+            lines.push(format!(
+                "[{}] send {} ({}): {}",
+                short_id(t.offer.id),
+                t.offer.name,
+                format_size(t.offer.size),
+                describe_state(&t.state)
+            ));
+        }
+        for t in self.incoming.values() {
+            let mut line = format!(
+                "[{}] receive {} ({}): {}",
+                short_id(t.offer.id),
+                t.offer.name,
+                format_size(t.offer.size),
+                describe_state(&t.state)
+            );
+            if t.state == State::Transferring {
+                line.push_str(&format!(" {}%", percent(t.received, t.offer.size)));
+            }
+            if let Some(path) = &t.saved_as {
+                line.push_str(&format!(" -> {}", path.display()));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Pending incoming offer by id prefix, or the only one when no id is given
+    fn resolve_offer(&self, short: &str) -> Result<TransferId, String> {
+        let short = short.trim().trim_start_matches('#').to_lowercase();
+        let matches: Vec<TransferId> = self
+            .incoming
+            .values()
+            .filter(|t| t.state == State::Offered)
+            .map(|t| t.offer.id)
+            .filter(|id| format!("{:016x}", id).starts_with(&short))
+            .collect();
+
+        match matches.as_slice() {
+            [id] => Ok(*id),
+            [] if short.is_empty() => Err("No pending file offers".to_string()),
+            [] => Err(format!("No pending file offer with id {}", short)),
+            _ => Err("Several offers are pending, give the transfer id".to_string()),
+        }
+    }
+
+    fn on_offer(&mut self, offer: FileOffer) -> Option<String> {
+        if self.incoming.contains_key(&offer.id) {
+            return None;
+        }
+
+        let offer = FileOffer {
+            name: sanitize_filename(&offer.name),
+            ..offer
+        };
+        let part_path = self
+            .download_dir
+            .join(format!(".{}.{:016x}.part", offer.name, offer.id));
+        let line = format!(
+            "[FILE] Peer offers {} ({}) [{}]. Type /accept {} or /reject {}",
+            offer.name,
+            format_size(offer.size),
+            short_id(offer.id),
+            short_id(offer.id),
+            short_id(offer.id)
+        );
+
+        self.incoming.insert(
+            offer.id,
+            Incoming {
+                offer,
+                state: State::Offered,
+                part_path,
+                file: None,
+                hasher: Sha256::new(),
+                received: 0,
+                saved_as: None,
+            },
+        );
+        Some(line)
+    }
+
+    /// Write the next chunk in order; returns a progress line and, once
+    /// the whole file is in, the verdict for the sender
+    fn on_chunk(&mut self, chunk: FileChunk) -> (Option<String>, Option<ChatMessage>) {
+        let Some(t) = self.incoming.get_mut(&chunk.id) else {
+            return (None, None);
+        };
+        // Chunks resent after a reconnect may overlap what we already have
+        if t.state != State::Transferring || chunk.offset != t.received {
+            return (None, None);
+        }
+        if t.received + chunk.data.len() as u64 > t.offer.size {
+            return fail_incoming(t, "peer sent more data than offered");
+        }
+
+        let Some(file) = t.file.as_mut() else {
+            return (None, None);
+        };
+        if let Err(e) = file.write_all(&chunk.data) {
+            return fail_incoming(t, &format!("write failed: {}", e));
+        }
+        t.hasher.update(&chunk.data);
+
+        let before = percent(t.received, t.offer.size) / PROGRESS_STEP;
+        t.received += chunk.data.len() as u64;
+        let after = percent(t.received, t.offer.size) / PROGRESS_STEP;
+
+        if t.received == t.offer.size {
+            return self.finish_incoming(chunk.id);
+        }
+        let progress = (after > before).then(|| {
+            format!(
+                "[FILE] {} {}% ({} / {})",
+                t.offer.name,
+                after * PROGRESS_STEP,
+                format_size(t.received),
+                format_size(t.offer.size)
+            )
+        });
+        (progress, None)
+    }
+
+    fn finish_incoming(&mut self, id: TransferId) -> (Option<String>, Option<ChatMessage>) {
+        let download_dir = self.download_dir.clone();
+        let t = self.incoming.get_mut(&id).expect("finishing known transfer");
+
+        if let Some(mut file) = t.file.take() {
+            file.flush().ok();
+        }
+        let digest: [u8; 32] = std::mem::take(&mut t.hasher).finalize().into();
+        if digest != t.offer.sha256 {
+            return fail_incoming(t, "SHA-256 mismatch, file discarded");
+        }
+
+        let target = unique_path(&download_dir, &t.offer.name);
+        if let Err(e) = fs::rename(&t.part_path, &target) {
+            return fail_incoming(t, &format!("cannot save {}: {}", target.display(), e));
+        }
+
+        t.state = State::Done;
+        t.saved_as = Some(target.clone());
+        (
+            Some(format!(
+                "[FILE] Received {} ({}), SHA-256 verified, saved to {}",
+                t.offer.name,
+                format_size(t.offer.size),
+                target.display()
+            )),
+            Some(ChatMessage::FileComplete(FileComplete { id, ok: true })),
+        )
+    }
+}
+
+/// Hash the file in the background, then offer it to the peer.
+/// Returns the line to show while hashing.
+pub fn offer_file(
+    transfers: &Arc<Mutex<Transfers>>,
+    stream: &Arc<Mutex<SecureStream>>,
+    path: PathBuf,
+) -> Result<String, String> {
+    let meta = match fs::metadata(&path) {
+        Ok(meta) if meta.is_file() => meta,
+        Ok(_) => return Err(format!("[FILE] {} is not a regular file", path.display())),
+        Err(e) => return Err(format!("[FILE] Cannot read {}: {}", path.display(), e)),
+    };
+
+    let name = sanitize_filename(&path.file_name().unwrap_or_default().to_string_lossy());
+    let line = format!("[FILE] Hashing {} ({})...", name, format_size(meta.len()));
+
+    let transfers = Arc::clone(transfers);
+    let stream = Arc::clone(stream);
+    thread::spawn(move || {
+        let notify = Arc::clone(&transfers.lock().unwrap().notify);
+        let sha256 = match sha256_file(&path) {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                notify(&format!("[FILE] Cannot read {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        let offer = FileOffer {
+            id: rand::random(),
+            name,
+            size: meta.len(),
+            sha256,
+        };
+        let msg = ChatMessage::FileOffer(offer.clone());
+        let id = offer.id;
+
+        let mut t = transfers.lock().unwrap();
+        t.outgoing.insert(
+            id,
+            Outgoing {
+                path,
+                offer,
+                state: State::Offered,
+                generation: Arc::new(AtomicU64::new(0)),
+            },
+        );
+        if let Err(e) = send(&stream, &msg) {
+            notify(&format!("[FILE] Failed to send offer: {}", e));
+            return;
+        }
+        let name = &t.outgoing[&id].offer.name;
+        notify(&format!("[FILE] Offered {} [{}], waiting for the peer to accept", name, short_id(id)));
+    });
+    Ok(line)
+}
+
+/// Apply a file transfer message from the peer and return the line to show
+pub fn handle(
+    transfers: &Arc<Mutex<Transfers>>,
+    stream: &Arc<Mutex<SecureStream>>,
+    msg: ChatMessage,
+) -> Option<String> {
+    let mut t = transfers.lock().unwrap();
+    match msg {
+        ChatMessage::FileOffer(offer) => t.on_offer(offer),
+        ChatMessage::FileChunk(chunk) => {
+            let (line, reply) = t.on_chunk(chunk);
+            if let Some(reply) = reply
+                && let Err(e) = send(stream, &reply)
+            {
+                tracing::warn!(error = %e, "could not send transfer result");
+            }
+            line
+        }
+        ChatMessage::FileAccept(accept) => {
+            let notify = Arc::clone(&t.notify);
+            let out = t.outgoing.get_mut(&accept.id)?;
+            if accept.offset > out.offer.size
+                || !matches!(out.state, State::Offered | State::Transferring)
+            {
+                return None;
+            }
+
+            let resumed = out.state == State::Transferring;
+            out.state = State::Transferring;
+            spawn_sender(stream, notify, out, accept.offset);
+            Some(if resumed {
+                format!(
+                    "[FILE] Resuming {} at {}",
+                    out.offer.name,
+                    format_size(accept.offset)
+                )
+            } else {
+                format!("[FILE] Peer accepted {}, sending", out.offer.name)
+            })
+        }
+        ChatMessage::FileReject(reject) => {
+            let out = t.outgoing.get_mut(&reject.id)?;
+            out.state = State::Rejected;
+            Some(format!("[FILE] Peer rejected {}", out.offer.name))
+        }
+        ChatMessage::FileComplete(complete) => {
+            let out = t.outgoing.get_mut(&complete.id)?;
+            if complete.ok {
+                out.state = State::Done;
+                Some(format!("[FILE] {} delivered and verified", out.offer.name))
+            } else {
+                out.state = State::Failed("peer could not verify the file".to_string());
+                Some(format!("[FILE] Peer could not verify {}", out.offer.name))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn spawn_sender(stream: &Arc<Mutex<SecureStream>>, notify: Notify, out: &Outgoing, offset: u64) {
+    let generation = Arc::clone(&out.generation);
+    let my_generation = generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let stream = Arc::clone(stream);
+    let path = out.path.clone();
+    let offer = out.offer.clone();
+
+    thread::spawn(move || {
+        let still_current = || generation.load(Ordering::SeqCst) == my_generation;
+        match send_chunks(&stream, &path, &offer, offset, &still_current, &notify) {
+            Ok(()) => {}
+            Err(_) if !still_current() => {}
+            Err(e) => notify(&format!(
+                "[FILE] Sending {} paused: {}. It continues when the peer reconnects",
+                offer.name, e
+            )),
+        }
+    });
+}
+
+fn send_chunks(
+    stream: &Mutex<SecureStream>,
+    path: &Path,
+    offer: &FileOffer,
+    mut offset: u64,
+    still_current: &dyn Fn() -> bool,
+    notify: &Notify,
+) -> anyhow::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    // An empty file still needs one chunk to complete on the other side
+    if offer.size == 0 {
+        let chunk = ChatMessage::FileChunk(FileChunk {
+            id: offer.id,
+            offset: 0,
+            data: Vec::new(),
+        });
+        send(stream, &chunk)?;
+    }
+
+    while offset < offer.size {
+        let before = percent(offset, offer.size) / PROGRESS_STEP;
+        {
+            let mut s = stream.lock().unwrap();
+            for _ in 0..CHUNKS_PER_LOCK {
+                if !still_current() {
+                    return Ok(());
+                }
+                if offset >= offer.size {
+                    break;
+                }
+
+                let n = CHUNK_SIZE.min((offer.size - offset) as usize);
+                file.read_exact(&mut buf[..n])
+                    .map_err(|e| anyhow::anyhow!("file changed while sending ({})", e))?;
+                let chunk = ChatMessage::FileChunk(FileChunk {
+                    id: offer.id,
+                    offset,
+                    data: buf[..n].to_vec(),
+                });
+                s.send(&chunk.to_bytes()?)
+                    .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+                offset += n as u64;
+            }
+        }
+
+        let after = percent(offset, offer.size) / PROGRESS_STEP;
+        if after > before && offset < offer.size {
+            notify(&format!(
+                "[FILE] {} {}% ({} / {})",
+                offer.name,
+                after * PROGRESS_STEP,
+                format_size(offset),
+                format_size(offer.size)
+            ));
+        }
+    }
+
+    notify(&format!("[FILE] Sent {}, waiting for the peer to verify it", offer.name));
+    Ok(())
+}
+
+fn send(stream: &Mutex<SecureStream>, msg: &ChatMessage) -> anyhow::Result<()> {
+    let data = msg.to_bytes()?;
+    stream
+        .lock()
+        .unwrap()
+        .send(&data)
+        .map_err(|e| anyhow::anyhow!("{:?}", e))
+}
+
+fn fail_incoming(t: &mut Incoming, reason: &str) -> (Option<String>, Option<ChatMessage>) {
+    t.file = None;
+    fs::remove_file(&t.part_path).ok();
+    t.state = State::Failed(reason.to_string());
+    (
+        Some(format!("[FILE] Receiving {} failed: {}", t.offer.name, reason)),
+        Some(ChatMessage::FileComplete(FileComplete {
+            id: t.offer.id,
+            ok: false,
+        })),
+    )
+}
+
+/// `dir/name`, or `dir/stem (n).ext` when that already exists
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .expect("some free file name")
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    (done * 100).checked_div(total).unwrap_or(100)
+}
+
+fn describe_state(state: &State) -> String {
+    match state {
+        State::Offered => "offered".to_string(),
+        State::Transferring => "in progress".to_string(),
+        State::Done => "done".to_string(),
+        State::Rejected => "rejected".to_string(),
+        State::Failed(reason) => format!("failed ({})", reason),
+    }
+}
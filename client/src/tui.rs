@@ -0,0 +1,588 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use audio::MuteSwitch;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use vc_core::protocol::presence::TypingNotifier;
+
+use client::input::{Completer, InputHistory};
+use client::logging;
+use client::session::Link;
+use client::status::StatusLine;
+
+use crate::ui::{Input, Ui};
+
+/// Lines kept in the message pane
+const SCROLLBACK: usize = 5000;
+
+/// Redraw and typing-timeout check interval while no key is pressed
+const TICK: Duration = Duration::from_millis(100);
+
+const PARTICIPANTS_WIDTH: u16 = 32;
+
+/// Session facts the status bar and participant list show
+pub struct TuiInfo {
+    pub room_code: String,
+    pub peer_fingerprint: String,
+    pub status: Arc<Mutex<StatusLine>>,
+    pub link: Link,
+    /// None when voice is off
+    pub mute: Option<MuteSwitch>,
+}
+
+/// Everything drawn that is not in TuiInfo
+#[derive(Default)]
+struct Screen {
+    lines: Vec<String>,
+    input: String,
+    /// Byte offset into `input`
+    cursor: usize,
+    /// Lines scrolled up from the bottom
+    scroll: usize,
+    /// Query of a running Ctrl-R search
+    search: Option<String>,
+}
+
+impl Screen {
+    fn set_input(&mut self, text: &str) {
+        self.input = text.to_string();
+        self.cursor = self.input.len();
+    }
+}
+
+/// Input state the screen does not show directly
+struct Editing {
+    history: InputHistory,
+    completer: Completer,
+    /// History entry shown by Up/Down, and the line typed before
+    browse: Option<(usize, String)>,
+    /// Entry matched by the Ctrl-R search, and the line before it
+    found: Option<usize>,
+    original: String,
+}
+
+impl Screen {
+    fn push(&mut self, text: &str) {
+        for line in text.lines() {
+            self.lines.push(line.to_string());
+            // Keep the view still while the user reads older lines
+            if self.scroll > 0 {
+                self.scroll += 1;
+            }
+        }
+        if self.lines.len() > SCROLLBACK {
+            self.lines.drain(..self.lines.len() - SCROLLBACK);
+        }
+    }
+}
+
+/// Full-screen mode: message pane, participant list, status bar and an
+/// input line that incoming messages never overwrite
+pub struct Tui {
+    screen: Arc<Mutex<Screen>>,
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    /// Gives the terminal its stderr back once the UI is gone
+    redirect: Mutex<Option<StderrRedirect>>,
+}
+
+impl Tui {
+    /// Take over the terminal. Output on stderr would tear the screen,
+    /// so until shutdown it goes to the `log.file`, or else to
+    /// ~/.voicechat/client.log.
+    pub fn start(
+        info: TuiInfo,
+        input: Sender<Input>,
+        history: InputHistory,
+        completer: Completer,
+    ) -> io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        let log = logging::log_file().map(Path::to_path_buf).unwrap_or_else(log_path);
+        let redirect = match StderrRedirect::to(&log) {
+            Ok(redirect) => redirect,
+            Err(e) => {
+                ratatui::restore();
+                return Err(e);
+            }
+        };
+
+        let screen = Arc::new(Mutex::new(Screen::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let screen = Arc::clone(&screen);
+            let stop = Arc::clone(&stop);
+            let mut editing = Editing {
+                history,
+                completer,
+                browse: None,
+                found: None,
+                original: String::new(),
+            };
+            thread::spawn(move || {
+                if let Err(e) = run(terminal, &screen, &info, &input, &mut editing, &stop) {
+                    tracing::error!(error = %e, "terminal UI failed");
+                }
+                ratatui::restore();
+                // Leave like /exit if the UI went away first
+                input.send(Input::Line("/exit".to_string())).ok();
+            })
+        };
+
+        Ok(Self {
+            screen,
+            stop,
+            thread: Mutex::new(Some(thread)),
+            redirect: Mutex::new(Some(redirect)),
+        })
+    }
+}
+
+impl Ui for Tui {
+    fn print(&self, text: &str) {
+        self.screen.lock().unwrap().push(text);
+    }
+
+    fn notify(&self, text: &str) {
+        if !text.is_empty() {
+            self.screen.lock().unwrap().push(text);
+        }
+    }
+
+    // Redrawn on every tick anyway
+    fn refresh(&self) {}
+
+    fn waits_for_exit(&self) -> bool {
+        true
+    }
+
+    fn shutdown(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            thread.join().ok();
+        }
+        self.redirect.lock().unwrap().take();
+    }
+}
+
+fn log_path() -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(".voicechat");
+    path.push("client.log");
+    path
+}
+
+/// Stderr sent to the end of a file; dropping it points stderr back
+/// at the terminal
+struct StderrRedirect {
+    #[cfg(unix)]
+    saved: std::os::fd::OwnedFd,
+}
+
+impl StderrRedirect {
+    #[cfg(unix)]
+    fn to(path: &Path) -> io::Result<Self> {
+        use std::os::fd::{AsFd, AsRawFd};
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let log = OpenOptions::new().create(true).append(true).open(path)?;
+        let saved = io::stderr().as_fd().try_clone_to_owned()?;
+        // SAFETY: both descriptors are valid; dup2 only replaces fd 2
+        if unsafe { libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { saved })
+    }
+
+    #[cfg(not(unix))]
+    fn to(_path: &Path) -> io::Result<Self> {
+        Ok(Self {})
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StderrRedirect {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: `saved` is a valid descriptor we own
+        unsafe { libc::dup2(self.saved.as_raw_fd(), libc::STDERR_FILENO) };
+    }
+}
+
+fn run(
+    mut terminal: DefaultTerminal,
+    screen: &Mutex<Screen>,
+    info: &TuiInfo,
+    input: &Sender<Input>,
+    editing: &mut Editing,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let mut typing = TypingNotifier::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        terminal.draw(|frame| draw(frame, &screen.lock().unwrap(), info))?;
+
+        if event::poll(TICK)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            let mut screen = screen.lock().unwrap();
+            let result = if screen.search.is_some() {
+                handle_search_key(&mut screen, key, &mut typing, editing)
+            } else {
+                handle_key(&mut screen, key, &mut typing, editing)
+            };
+            match result {
+                KeyResult::None => {}
+                KeyResult::Send(item) => {
+                    if input.send(item).is_err() {
+                        break;
+                    }
+                }
+                KeyResult::Submit(line, stop_typing) => {
+                    if let Some(active) = stop_typing {
+                        input.send(Input::Typing(active)).ok();
+                    }
+                    if input.send(Input::Line(line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(active) = typing.poll() {
+            input.send(Input::Typing(active)).ok();
+        }
+    }
+    Ok(())
+}
+
+enum KeyResult {
+    None,
+    Send(Input),
+    /// A finished line, and the typing change to send before it
+    Submit(String, Option<bool>),
+}
+
+fn handle_key(screen: &mut Screen, key: KeyEvent, typing: &mut TypingNotifier, editing: &mut Editing) -> KeyResult {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('c') if ctrl => return KeyResult::Submit("/exit".to_string(), typing.submitted()),
+        KeyCode::Char('d') if ctrl && screen.input.is_empty() => {
+            return KeyResult::Submit("/exit".to_string(), typing.submitted());
+        }
+        KeyCode::Char('a') if ctrl => screen.cursor = 0,
+        KeyCode::Char('e') if ctrl => screen.cursor = screen.input.len(),
+        KeyCode::Char('u') if ctrl => {
+            screen.input.drain(..screen.cursor);
+            screen.cursor = 0;
+        }
+        KeyCode::Char('r') if ctrl => {
+            editing.found = None;
+            editing.original = screen.input.clone();
+            screen.search = Some(String::new());
+        }
+        KeyCode::Char(c) if !ctrl => {
+            screen.input.insert(screen.cursor, c);
+            screen.cursor += c.len_utf8();
+            // Commands are not chat, the peer need not see us "typing" them
+            if !screen.input.starts_with('/')
+                && let Some(active) = typing.keystroke()
+            {
+                return KeyResult::Send(Input::Typing(active));
+            }
+        }
+        KeyCode::Enter => return submit(screen, typing, editing),
+        KeyCode::Tab => complete(screen, &editing.completer),
+        KeyCode::Up => {
+            let (index, draft) = editing
+                .browse
+                .take()
+                .unwrap_or_else(|| (editing.history.entries().len(), screen.input.clone()));
+            let index = index.saturating_sub(1);
+            if let Some(entry) = editing.history.entries().get(index) {
+                screen.set_input(entry);
+            }
+            editing.browse = Some((index, draft));
+        }
+        KeyCode::Down => {
+            if let Some((index, draft)) = editing.browse.take() {
+                match editing.history.entries().get(index + 1) {
+                    Some(entry) => {
+                        screen.set_input(entry);
+                        editing.browse = Some((index + 1, draft));
+                    }
+                    // Past the newest entry: back to what was typed
+                    None => screen.set_input(&draft),
+                }
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some(c) = screen.input[..screen.cursor].chars().next_back() {
+                screen.cursor -= c.len_utf8();
+                screen.input.remove(screen.cursor);
+            }
+        }
+        KeyCode::Delete if screen.cursor < screen.input.len() => {
+            screen.input.remove(screen.cursor);
+        }
+        KeyCode::Left => {
+            if let Some(c) = screen.input[..screen.cursor].chars().next_back() {
+                screen.cursor -= c.len_utf8();
+            }
+        }
+        KeyCode::Right => {
+            if let Some(c) = screen.input[screen.cursor..].chars().next() {
+                screen.cursor += c.len_utf8();
+            }
+        }
+        KeyCode::Home => screen.cursor = 0,
+        KeyCode::End => screen.cursor = screen.input.len(),
+        KeyCode::PageUp => screen.scroll = (screen.scroll + 10).min(screen.lines.len()),
+        KeyCode::PageDown => screen.scroll = screen.scroll.saturating_sub(10),
+        _ => {}
+    }
+    KeyResult::None
+}
+
+fn submit(screen: &mut Screen, typing: &mut TypingNotifier, editing: &mut Editing) -> KeyResult {
+    let line = std::mem::take(&mut screen.input);
+    screen.cursor = 0;
+    screen.scroll = 0;
+    editing.browse = None;
+    if line.trim().is_empty() {
+        return KeyResult::None;
+    }
+    editing.history.add(&line);
+    KeyResult::Submit(line, typing.submitted())
+}
+
+/// Ctrl-R: typing narrows the search, Ctrl-R again goes to older
+/// matches, Enter sends the match, Esc or Ctrl-G gives the old line
+/// back and any other key keeps the match for editing
+fn handle_search_key(screen: &mut Screen, key: KeyEvent, typing: &mut TypingNotifier, editing: &mut Editing) -> KeyResult {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let query = screen.search.get_or_insert_with(String::new);
+    let newest = editing.history.entries().len();
+    match key.code {
+        KeyCode::Char('r') if ctrl => {
+            let before = editing.found.unwrap_or(newest);
+            if let Some(found) = editing.history.search(query, before) {
+                editing.found = Some(found);
+            }
+        }
+        KeyCode::Char('g') if ctrl => return cancel_search(screen, editing),
+        KeyCode::Esc => return cancel_search(screen, editing),
+        KeyCode::Char(c) if !ctrl => {
+            query.push(c);
+            // The current match may still fit the longer query
+            let before = editing.found.map_or(newest, |found| found + 1);
+            editing.found = editing.history.search(query, before);
+        }
+        KeyCode::Backspace => {
+            query.pop();
+            editing.found = editing.history.search(query, newest).filter(|_| !query.is_empty());
+        }
+        KeyCode::Enter => {
+            screen.search = None;
+            return submit(screen, typing, editing);
+        }
+        _ => {
+            screen.search = None;
+            return KeyResult::None;
+        }
+    }
+
+    let shown = match editing.found {
+        Some(found) => editing.history.entries()[found].clone(),
+        None => editing.original.clone(),
+    };
+    screen.set_input(&shown);
+    KeyResult::None
+}
+
+fn cancel_search(screen: &mut Screen, editing: &mut Editing) -> KeyResult {
+    screen.search = None;
+    let original = std::mem::take(&mut editing.original);
+    screen.set_input(&original);
+    KeyResult::None
+}
+
+/// Tab: fill in the only candidate or what all candidates share, and
+/// list them in the message pane when that adds nothing
+fn complete(screen: &mut Screen, completer: &Completer) {
+    let completion = completer.complete(&screen.input, screen.cursor);
+    let word = &screen.input[completion.start..screen.cursor];
+    let fill = match completion.candidates.as_slice() {
+        [] => return,
+        [only] => only.clone(),
+        _ => completion.common_prefix().to_string(),
+    };
+
+    if fill.len() > word.len() {
+        screen.input.replace_range(completion.start..screen.cursor, &fill);
+        screen.cursor = completion.start + fill.len();
+    } else {
+        screen.push(&completion.candidates.join("  "));
+        screen.scroll = 0;
+    }
+}
+
+fn draw(frame: &mut Frame, screen: &Screen, info: &TuiInfo) {
+    let [main, status_bar, input] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(1),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+    let [messages, participants] =
+        Layout::horizontal([Constraint::Min(20), Constraint::Length(PARTICIPANTS_WIDTH)]).areas(main);
+
+    draw_messages(frame, messages, screen, &info.room_code);
+
+    let people: Vec<Line> = info
+        .status
+        .lock()
+        .unwrap()
+        .participants()
+        .into_iter()
+        .map(|p| Line::from(format!("● {}", p)))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(people).block(Block::default().borders(Borders::ALL).title(" Participants ")),
+        participants,
+    );
+
+    frame.render_widget(
+        Paragraph::new(status_text(info)).style(Style::default().add_modifier(Modifier::REVERSED)),
+        status_bar,
+    );
+
+    draw_input(frame, input, screen);
+}
+
+fn draw_messages(frame: &mut Frame, area: Rect, screen: &Screen, room_code: &str) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Room {} ", room_code));
+    let inner = block.inner(area);
+    let width = inner.width.max(1) as usize;
+    let height = inner.height as usize;
+
+    // Wrap from the newest line back until the pane and the scroll
+    // offset are covered
+    let wanted = height + screen.scroll;
+    let mut rows: Vec<Line> = Vec::new();
+    for line in screen.lines.iter().rev() {
+        let style = line_style(line);
+        for row in wrap(line, width).into_iter().rev() {
+            rows.push(Line::styled(row, style));
+        }
+        if rows.len() >= wanted {
+            break;
+        }
+    }
+    let scroll = screen.scroll.min(rows.len().saturating_sub(height));
+    let visible: Vec<Line> = rows.into_iter().skip(scroll).take(height).rev().collect();
+
+    let title = if scroll > 0 {
+        block.title_bottom(format!(" ↑ {} more (PgDn) ", scroll))
+    } else {
+        block
+    };
+    frame.render_widget(Paragraph::new(visible).block(title), area);
+}
+
+fn draw_input(frame: &mut Frame, area: Rect, screen: &Screen) {
+    let block = Block::default().borders(Borders::ALL);
+    let inner = block.inner(area);
+    let prompt = match &screen.search {
+        Some(query) => format!("(reverse-i-search)`{}': ", query),
+        None => "> ".to_string(),
+    };
+    let width = (inner.width as usize).saturating_sub(prompt.width() + 1).max(1);
+
+    // Scroll long input sideways so the cursor stays visible
+    let before = &screen.input[..screen.cursor];
+    let mut start = 0;
+    while before[start..].width() > width {
+        start += before[start..].chars().next().map_or(1, char::len_utf8);
+    }
+    let shown: String = screen.input[start..]
+        .chars()
+        .scan(0, |w, c| {
+            *w += c.width().unwrap_or(0);
+            (*w <= width).then_some(c)
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(format!("{}{}", prompt, shown)).block(block), area);
+    let x = inner.x + (prompt.width() + before[start..].width()) as u16;
+    frame.set_cursor_position((x, inner.y));
+}
+
+/// " room ABCD │ peer 3fa9c1d2 │ 🔒 encrypted │ RTT 12 ms │ mic on "
+fn status_text(info: &TuiInfo) -> String {
+    let link = if !info.link.is_connected() {
+        "✗ disconnected"
+    } else if info.link.is_resuming() {
+        "⟳ reconnecting"
+    } else {
+        "🔒 end-to-end encrypted"
+    };
+    let rtt = match info.link.rtt() {
+        Some(rtt) => format!("RTT {:.0} ms", rtt.as_secs_f64() * 1000.0),
+        None => "RTT –".to_string(),
+    };
+    let mic = match &info.mute {
+        Some(mute) if mute.is_muted() => "mic muted",
+        Some(_) => "mic on",
+        None => "voice off",
+    };
+    format!(
+        " room {} │ peer {} │ {} │ {} │ {} ",
+        info.room_code, info.peer_fingerprint, link, rtt, mic
+    )
+}
+
+fn line_style(line: &str) -> Style {
+    if line.starts_with("[SYSTEM]") {
+        Style::default().fg(Color::Yellow)
+    } else if line.starts_with("[FILE]") {
+        Style::default().fg(Color::Cyan)
+    } else if line.starts_with("  ┌") {
+        Style::default().fg(Color::DarkGray)
+    } else {
+        Style::default()
+    }
+}
+
+/// Split a line into rows of at most `width` columns
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut used = 0;
+    for c in line.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width && used > 0 {
+            rows.push(String::new());
+            used = 0;
+        }
+        rows.last_mut().unwrap().push(c);
+        used += w;
+    }
+    rows
+}
@@ -0,0 +1,285 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, ExternalPrinter, Helper};
+
+use client::config::UiPreference;
+#[cfg(unix)]
+use client::control::ControlRequest;
+use client::input::{Completer, InputHistory};
+use client::session::Event;
+use client::status::StatusLine;
+
+/// What the user entered
+pub enum Input {
+    Line(String),
+    /// Started or stopped typing, from frontends that see keystrokes
+    Typing(bool),
+    /// A call from the control socket
+    #[cfg(unix)]
+    Control(ControlRequest),
+}
+
+#[cfg(unix)]
+impl From<ControlRequest> for Input {
+    fn from(request: ControlRequest) -> Self {
+        Input::Control(request)
+    }
+}
+
+/// Where a chat session shows its output. Implementations keep the
+/// user's half-typed input intact when lines arrive.
+pub trait Ui: Send + Sync {
+    /// Output of the command the user just ran
+    fn print(&self, text: &str);
+    /// Something that happened on its own: an incoming message, a
+    /// system event, transfer progress
+    fn notify(&self, text: &str);
+    /// Redraw whatever shows the session state
+    fn refresh(&self);
+    /// A session event; `shown` is the line text frontends display
+    fn event(&self, event: Event, shown: &str) {
+        match event {
+            // Comes before there is a prompt to keep intact
+            Event::PeerJoined { .. } => self.print(shown),
+            // The next message clears the indicator anyway
+            Event::Typing { active: false } => {}
+            _ => self.notify(shown),
+        }
+    }
+    /// A command failed or the input was not understood
+    fn error(&self, text: &str) {
+        self.print(text);
+    }
+    /// Give the terminal back before the process goes on printing
+    fn shutdown(&self) {}
+    /// Whether the user leaves on their own once the session is over,
+    /// instead of the frontend quitting right away
+    fn waits_for_exit(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiMode {
+    /// Full-screen terminal UI
+    Tui,
+    /// Plain lines and a prompt, also for pipes and dumb terminals
+    Line,
+    /// Events as JSON lines on stdout, for scripts and bots
+    Json,
+}
+
+impl UiMode {
+    /// The `ui` setting; auto uses the TUI when both stdin and stdout
+    /// are terminals
+    pub fn from_setting(ui: UiPreference) -> Self {
+        match ui {
+            UiPreference::Line => UiMode::Line,
+            UiPreference::Tui => UiMode::Tui,
+            UiPreference::Auto if io::stdin().is_terminal() && io::stdout().is_terminal() => UiMode::Tui,
+            UiPreference::Auto => UiMode::Line,
+        }
+    }
+}
+
+/// Line mode: output goes above a prompt that shows presence and typing
+pub struct LineUi {
+    status: Arc<Mutex<StatusLine>>,
+    /// Set when editing on a terminal
+    editor: Option<EditorHandle>,
+}
+
+/// The line editor running on its own thread
+struct EditorHandle {
+    /// Prints above the line being edited without garbling it
+    printer: Mutex<Box<dyn ExternalPrinter + Send>>,
+    /// Tells the editor the last line was handled, so the next prompt
+    /// shows the state after it
+    ready: SyncSender<()>,
+    #[cfg(unix)]
+    termios: Option<libc::termios>,
+}
+
+impl LineUi {
+    /// Also starts reading lines from stdin: through a line editor with
+    /// history and completion on a terminal, plain lines otherwise
+    pub fn start(
+        status: Arc<Mutex<StatusLine>>,
+        input: Sender<Input>,
+        history: InputHistory,
+        completer: Completer,
+    ) -> Self {
+        if io::stdin().is_terminal() && io::stdout().is_terminal() {
+            match start_editor(&status, input.clone(), history, completer) {
+                Ok(editor) => {
+                    return Self {
+                        status,
+                        editor: Some(editor),
+                    };
+                }
+                Err(e) => tracing::warn!("line editing unavailable: {}", e),
+            }
+        }
+
+        // Read stdin on its own thread so a closed session is noticed
+        // without waiting for the next line of input
+        thread::spawn(move || {
+            loop {
+                let mut line = String::new();
+                match io::stdin().read_line(&mut line) {
+                    // The control socket may still hold the channel open
+                    Ok(0) | Err(_) => {
+                        input.send(Input::Line("/exit".to_string())).ok();
+                        break;
+                    }
+                    Ok(_) => {
+                        if input.send(Input::Line(line)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Self { status, editor: None }
+    }
+}
+
+fn start_editor(
+    status: &Arc<Mutex<StatusLine>>,
+    input: Sender<Input>,
+    mut history: InputHistory,
+    completer: Completer,
+) -> rustyline::Result<EditorHandle> {
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .auto_add_history(false)
+        .build();
+    let mut editor: Editor<LineHelper, DefaultHistory> = Editor::with_config(config)?;
+    editor.set_helper(Some(LineHelper(completer)));
+    for entry in history.entries() {
+        editor.add_history_entry(entry.as_str())?;
+    }
+    let printer = editor.create_external_printer()?;
+    let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+
+    let status = Arc::clone(status);
+    thread::spawn(move || {
+        loop {
+            let prompt = status.lock().unwrap().prompt();
+            let line = match editor.readline(&prompt) {
+                Ok(line) => {
+                    history.add(&line);
+                    editor.add_history_entry(line.as_str()).ok();
+                    line
+                }
+                // Ctrl-C and Ctrl-D leave, as in the TUI
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => "/exit".to_string(),
+                Err(e) => {
+                    tracing::error!(error = %e, "cannot read input");
+                    "/exit".to_string()
+                }
+            };
+
+            while ready_rx.try_recv().is_ok() {}
+            if input.send(Input::Line(line)).is_err() || ready_rx.recv().is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(EditorHandle {
+        printer: Mutex::new(Box::new(printer)),
+        ready: ready_tx,
+        #[cfg(unix)]
+        termios: saved_termios(),
+    })
+}
+
+/// Terminal settings from before the editor switched to raw mode, put
+/// back if the session ends while a line is being edited
+#[cfg(unix)]
+fn saved_termios() -> Option<libc::termios> {
+    let mut termios = std::mem::MaybeUninit::uninit();
+    // SAFETY: tcgetattr fills the struct when it returns 0
+    (unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } == 0)
+        .then(|| unsafe { termios.assume_init() })
+}
+
+impl Ui for LineUi {
+    fn print(&self, text: &str) {
+        match &self.editor {
+            Some(editor) => {
+                editor.printer.lock().unwrap().print(format!("{}\n", text)).ok();
+            }
+            None => println!("{}", text),
+        }
+    }
+
+    fn notify(&self, text: &str) {
+        if self.editor.is_some() {
+            // The editor keeps its prompt until the next line, so an
+            // empty text has nothing to redraw
+            if !text.is_empty() {
+                self.print(text);
+            }
+            return;
+        }
+        // An empty text only redraws the prompt
+        if text.is_empty() {
+            println!();
+        } else {
+            println!("\n{}", text);
+        }
+        self.refresh();
+    }
+
+    fn refresh(&self) {
+        match &self.editor {
+            Some(editor) => {
+                editor.ready.try_send(()).ok();
+            }
+            None => {
+                print!("{}", self.status.lock().unwrap().prompt());
+                io::stdout().flush().ok();
+            }
+        }
+    }
+
+    fn shutdown(&self) {
+        #[cfg(unix)]
+        if let Some(termios) = self.editor.as_ref().and_then(|e| e.termios.as_ref()) {
+            // SAFETY: restores settings read from the same descriptor
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+        }
+    }
+}
+
+/// Tab completion for the line editor
+struct LineHelper(Completer);
+
+impl rustyline::completion::Completer for LineHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let completion = self.0.complete(line, pos);
+        Ok((completion.start, completion.candidates))
+    }
+}
+
+impl Hinter for LineHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LineHelper {}
+
+impl Validator for LineHelper {}
+
+impl Helper for LineHelper {}
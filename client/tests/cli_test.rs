@@ -0,0 +1,61 @@
+// Tests for the command line of the client binary
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_client"))
+        .args(args)
+        .output()
+        .expect("client runs")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_help_lists_subcommands_and_global_flags() {
+    let output = client(&["--help"]);
+    assert!(output.status.success());
+    let help = stdout(&output);
+    for word in [
+        "create", "join", "identity", "peers", "devices", "doctor", "export", "--server", "--identity",
+        "--no-voice", "--verbose", "--config",
+    ] {
+        assert!(help.contains(word), "help does not mention {}", word);
+    }
+}
+
+#[test]
+fn test_bad_arguments_are_rejected() {
+    assert!(!client(&["join"]).status.success());
+    assert!(!client(&["export", "--format", "pdf"]).status.success());
+    assert!(!client(&["--ui", "fancy", "identity"]).status.success());
+    assert!(!client(&["dance"]).status.success());
+}
+
+#[test]
+fn test_completions_for_each_shell() {
+    for shell in ["bash", "zsh", "fish"] {
+        let output = client(&["completions", shell]);
+        assert!(output.status.success());
+        let script = stdout(&output);
+        assert!(script.contains("doctor"), "{} completions lack subcommands", shell);
+    }
+}
+
+#[test]
+fn test_config_flag_and_global_flags_after_subcommand() {
+    let dir = std::env::temp_dir().join(format!("voicechat-cli-{}", std::process::id()));
+    let path = dir.join("config.toml");
+    let path_arg = path.to_str().unwrap();
+
+    let set = client(&["config", "set", "server", "file:1", "--config", path_arg]);
+    assert!(set.status.success());
+    let show = stdout(&client(&["--config", path_arg, "config", "show"]));
+    assert!(show.contains("server = \"file:1\"  # file"));
+    let show = stdout(&client(&["config", "show", "--config", path_arg, "--server", "flag:2"]));
+    fs::remove_dir_all(&dir).ok();
+    assert!(show.contains("server = \"flag:2\"  # flag"));
+}
@@ -0,0 +1,156 @@
+// Tests for slash command dispatch
+
+use std::cell::RefCell;
+
+use client::commands::{Args, Command, CommandError, CommandResult, Flow, Registry};
+
+/// What the handlers were called with
+#[derive(Default)]
+struct Calls(RefCell<Vec<String>>);
+
+fn cmd_msg(calls: &Calls, args: &mut Args) -> CommandResult {
+    let text = args.required_rest()?;
+    calls.0.borrow_mut().push(format!("msg {}", text));
+    Ok(Flow::Continue)
+}
+
+fn cmd_edit(calls: &Calls, args: &mut Args) -> CommandResult {
+    let id = args.required()?;
+    let text = args.required_rest()?;
+    calls.0.borrow_mut().push(format!("edit {} {}", id, text));
+    Ok(Flow::Continue)
+}
+
+fn cmd_history(calls: &Calls, args: &mut Args) -> CommandResult {
+    let count = args.parse::<usize>()?.unwrap_or(20);
+    args.finish()?;
+    calls.0.borrow_mut().push(format!("history {}", count));
+    Ok(Flow::Continue)
+}
+
+fn cmd_exit(_: &Calls, args: &mut Args) -> CommandResult {
+    args.finish()?;
+    Ok(Flow::Exit)
+}
+
+fn registry() -> Registry<Calls> {
+    let mut registry = Registry::new();
+    registry.register(Command {
+        name: "msg",
+        aliases: &["say"],
+        args: "<text>",
+        help: "Send a message",
+        handler: cmd_msg,
+    });
+    registry.register(Command {
+        name: "edit",
+        aliases: &[],
+        args: "<id> <text>",
+        help: "Change one of your messages",
+        handler: cmd_edit,
+    });
+    registry.register(Command {
+        name: "history",
+        aliases: &[],
+        args: "[count]",
+        help: "Show logged messages",
+        handler: cmd_history,
+    });
+    registry.register(Command {
+        name: "quit",
+        aliases: &["exit", "q"],
+        args: "",
+        help: "Leave the room",
+        handler: cmd_exit,
+    });
+    registry.set_default("msg");
+    registry
+}
+
+/// Dispatch each line and return what the handlers saw
+fn run(lines: &[&str]) -> Vec<String> {
+    let registry = registry();
+    let calls = Calls::default();
+    for line in lines {
+        registry.dispatch(&calls, line).unwrap();
+    }
+    calls.0.into_inner()
+}
+
+#[test]
+fn test_dispatch_by_name_and_alias() {
+    assert_eq!(
+        run(&["/msg hi there", "/SAY  loud", "/edit 3fa9  fixed  text ", "/history 5", "/history"]),
+        ["msg hi there", "msg loud", "edit 3fa9 fixed  text", "history 5", "history 20"]
+    );
+    let registry = registry();
+    let calls = Calls::default();
+    assert_eq!(registry.dispatch(&calls, "/q"), Ok(Flow::Exit));
+    assert_eq!(registry.dispatch(&calls, "/exit"), Ok(Flow::Exit));
+    assert_eq!(registry.dispatch(&calls, "   "), Ok(Flow::Continue));
+    assert!(calls.0.into_inner().is_empty());
+}
+
+#[test]
+fn test_plain_text_and_double_slash_go_to_the_default() {
+    assert_eq!(
+        run(&["hello", "  /msg  spaced  ", "//shrug", "// not a command"]),
+        ["msg hello", "msg spaced", "msg /shrug", "msg / not a command"]
+    );
+}
+
+#[test]
+fn test_dispatch_errors() {
+    let registry = registry();
+    let calls = Calls::default();
+    assert_eq!(registry.dispatch(&calls, "/dance"), Err(CommandError::Unknown("dance".to_string())));
+    assert_eq!(registry.dispatch(&calls, "/edit 3fa9"), Err(CommandError::Usage("/edit <id> <text>".to_string())));
+    assert_eq!(registry.dispatch(&calls, "/quit now"), Err(CommandError::Usage("/quit".to_string())));
+    assert_eq!(registry.dispatch(&calls, "/msg"), Err(CommandError::Usage("/msg <text>".to_string())));
+    let err = registry.dispatch(&calls, "/history lots").unwrap_err();
+    assert!(matches!(err, CommandError::Invalid(_)));
+    assert_eq!(
+        CommandError::Unknown("dance".to_string()).to_string(),
+        "Unknown command /dance, type /help for a list"
+    );
+
+    // Without a default, text needs a command
+    let mut bare: Registry<Calls> = Registry::new();
+    bare.register(Command {
+        name: "quit",
+        aliases: &[],
+        args: "",
+        help: "Leave the room",
+        handler: cmd_exit,
+    });
+    assert!(matches!(bare.dispatch(&calls, "hello"), Err(CommandError::Invalid(_))));
+    assert!(calls.0.into_inner().is_empty());
+}
+
+#[test]
+fn test_help_lists_every_command() {
+    let registry = registry();
+    assert_eq!(registry.names(), ["msg", "edit", "history", "quit"]);
+    assert_eq!(
+        registry.help(),
+        "Commands:\n  \
+         /msg <text>        Send a message (also /say)\n  \
+         /edit <id> <text>  Change one of your messages\n  \
+         /history [count]   Show logged messages\n  \
+         /quit              Leave the room (also /exit, /q)\n\
+         Text without a leading / is sent like /msg."
+    );
+}
+
+#[test]
+#[should_panic(expected = "registered twice")]
+fn test_taken_alias_is_a_bug() {
+    let mut registry = registry();
+    registry.register(Command {
+        name: "leave",
+        aliases: &["q"],
+        args: "",
+        help: "",
+        handler: cmd_exit,
+    });
+}
@@ -0,0 +1,93 @@
+// Tests for the layered client settings and `client config set`
+
+use std::fs;
+use std::path::PathBuf;
+
+use client::config::{self, Config, LogFormat, UiPreference};
+
+fn temp_config(name: &str, text: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voicechat-config-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_defaults_without_file() {
+    let path = std::env::temp_dir().join("voicechat-config-missing/config.toml");
+    let config = Config::load(&path, &[]).unwrap();
+    assert_eq!(config.ui, UiPreference::Auto);
+    assert_eq!(config.voice.client_port, 9001);
+    assert_eq!(config.voice.host_port, 9002);
+    assert_eq!(config.codec.complexity, None);
+    assert!(config.hooks.is_empty());
+    assert_eq!(config.log.level, "warn");
+    assert_eq!(config.log.format, LogFormat::Text);
+}
+
+#[test]
+fn test_flags_override_file() {
+    let path = temp_config("layers", "server = \"file:1\"\nui = \"line\"\n[voice]\nclient_port = 7001\n");
+    let config = Config::load(&path, &[("server", "flag:2".to_string())]).unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+
+    assert_eq!(config.server, "flag:2");
+    assert_eq!(config.ui, UiPreference::Line);
+    assert_eq!(config.voice.client_port, 7001);
+    // Sections merge key by key
+    assert_eq!(config.voice.host_port, 9002);
+}
+
+#[test]
+fn test_env_overrides_file_but_not_flags() {
+    let path = temp_config("env", "[codec]\nbitrate = 16000\n");
+    // SAFETY: no other test in this file reads VOICECHAT_BITRATE
+    unsafe { std::env::set_var("VOICECHAT_BITRATE", "32000") };
+    let from_env = Config::load(&path, &[]).unwrap();
+    let from_flag = Config::load(&path, &[("codec.bitrate", "48000".to_string())]).unwrap();
+    unsafe { std::env::remove_var("VOICECHAT_BITRATE") };
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+
+    assert_eq!(from_env.codec.bitrate, Some(32000));
+    assert_eq!(from_flag.codec.bitrate, Some(48000));
+}
+
+#[test]
+fn test_invalid_settings_are_rejected() {
+    assert!(Config::parse("srever = \"typo:1\"").is_err());
+    assert!(Config::parse("[codec]\ncomplexity = 11").is_err());
+    assert!(Config::parse("[voice]\nclient_port = 9002").is_err());
+    assert!(Config::parse("ui = \"fancy\"").is_err());
+
+    let path = temp_config("flags", "");
+    assert!(Config::load(&path, &[("voice.host_port", "many".to_string())]).is_err());
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+}
+
+#[test]
+fn test_set_keeps_comments_and_checks_values() {
+    let path = temp_config("set", "# my server\nserver = \"a:1\"  # office\n");
+    config::set(&path, "codec.fec", Some("on")).unwrap();
+    config::set(&path, "nickname", Some("alice")).unwrap();
+    assert!(config::set(&path, "codec.bitrate", Some("1")).is_err());
+    assert!(config::set(&path, "no.such", Some("1")).is_err());
+    config::set(&path, "nickname", None).unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+    assert!(text.contains("# my server"));
+    assert!(text.contains("# office"));
+    assert!(!text.contains("nickname"));
+    let config = Config::parse(&text).unwrap();
+    assert!(config.codec.fec);
+    assert_eq!(config.codec.bitrate, None);
+}
+
+#[test]
+fn test_log_filters_are_checked() {
+    let config = Config::parse("[log]\nlevel = \"warn,vc_core::net=trace\"\nformat = \"json\"\n").unwrap();
+    assert_eq!(config.log.format, LogFormat::Json);
+    assert!(Config::parse("[log]\nlevel = \"client=loud\"").is_err());
+    assert!(Config::parse("[log]\nformat = \"xml\"").is_err());
+}
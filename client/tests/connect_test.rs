@@ -0,0 +1,318 @@
+// Tests for session setup against a fake signaling server, which
+// either plays the peer or relays between two sessions
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+
+use client::identity::Identity;
+use client::session::{self, Event, Role, Session, SessionError, Step, Target};
+use vc_core::net::secure_stream::SecureStream;
+use vc_core::protocol::chat::{ChatMessage, ChatNick};
+use vc_core::protocol::handshake;
+use vc_core::protocol::nick::{fingerprint, signing_payload};
+
+const ROOM: &str = "AB12-CD34";
+
+/// Sessions save peers and history under $HOME; keep them out of the
+/// real one. Each test uses its own key files.
+fn test_home() -> PathBuf {
+    static INIT: Once = Once::new();
+    let home = std::env::temp_dir().join(format!("voicechat-connect-{}", std::process::id()));
+    // SAFETY: every test calls this before any thread reads HOME
+    INIT.call_once(|| unsafe { std::env::set_var("HOME", &home) });
+    home
+}
+
+fn identity(name: &str) -> Identity {
+    Identity::load_or_create_at(&test_home().join(format!("{}.key", name)))
+}
+
+/// Accept one connection and hand it to `script`, which plays the
+/// signaling server. Returns the address to connect to.
+fn fake_server(script: impl FnOnce(TcpStream) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        script(stream);
+    });
+    addr
+}
+
+/// One request line; read byte by byte so nothing after it is consumed
+fn read_line(stream: &mut TcpStream) -> String {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while stream.read(&mut byte).unwrap() == 1 && byte[0] != b'\n' {
+        line.push(byte[0]);
+    }
+    String::from_utf8(line).unwrap()
+}
+
+fn decode_key(b64: &str) -> [u8; 32] {
+    general_purpose::STANDARD.decode(b64).unwrap().try_into().unwrap()
+}
+
+/// Answer `request` ("CREATE" or "JOIN") with `reply` lines and, if
+/// `role` is given, tell the client its role and run the peer's side of
+/// the handshake. The peer's stream comes back on the channel.
+fn signaling(
+    request: &'static str,
+    reply: &'static str,
+    role: Option<Role>,
+    peer: [u8; 32],
+) -> (String, mpsc::Receiver<(String, SecureStream)>) {
+    let (tx, rx) = mpsc::channel();
+    let addr = fake_server(move |mut stream| {
+        let line = read_line(&mut stream);
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        assert_eq!(parts[0], request);
+        let room = parts[1].to_string();
+        let client_key = decode_key(parts[2]);
+
+        write!(stream, "{}", reply).unwrap();
+        let Some(role) = role else {
+            return;
+        };
+        let role_name = if role == Role::Client { "CLIENT" } else { "HOST" };
+        writeln!(stream, "PEER_PUBKEY {} {}", general_purpose::STANDARD.encode(peer), role_name).unwrap();
+        let secured = match role {
+            Role::Client => handshake::run_as_host(stream, peer, client_key),
+            Role::Host => handshake::run(stream, peer, client_key),
+        };
+        tx.send((room, secured.unwrap())).ok();
+    });
+    (addr, rx)
+}
+
+/// Pair the first two clients like the real server: the creator hosts,
+/// then bytes are relayed both ways
+fn fake_relay() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (mut host, _) = listener.accept().unwrap();
+        let create = read_line(&mut host);
+        let (mut guest, _) = listener.accept().unwrap();
+        let join = read_line(&mut guest);
+        let host_key = create.split_whitespace().nth(2).unwrap().to_string();
+        let guest_key = join.split_whitespace().nth(2).unwrap().to_string();
+
+        writeln!(guest, "ROOM_JOINED").unwrap();
+        writeln!(host, "PEER_PUBKEY {} HOST", guest_key).unwrap();
+        writeln!(guest, "PEER_PUBKEY {} CLIENT", host_key).unwrap();
+        for (mut from, mut to) in [
+            (host.try_clone().unwrap(), guest.try_clone().unwrap()),
+            (guest, host),
+        ] {
+            thread::spawn(move || {
+                io::copy(&mut from, &mut to).ok();
+                to.shutdown(Shutdown::Write).ok();
+            });
+        }
+    });
+    addr
+}
+
+/// Next event, skipping joins, receipts and system notices
+fn next_event(events: &mpsc::Receiver<Event>) -> Result<Event, RecvTimeoutError> {
+    loop {
+        match events.recv_timeout(Duration::from_secs(5))? {
+            Event::PeerJoined { .. } | Event::System { .. } | Event::Receipt { .. } => continue,
+            event => return Ok(event),
+        }
+    }
+}
+
+#[test]
+fn test_join_reports_each_step() {
+    let peer = identity("join-peer").public_key_bytes();
+    let (addr, peer_side) = signaling("JOIN", "ROOM_JOINED\n", Some(Role::Client), peer);
+
+    let mut steps = Vec::new();
+    let session = session::connect(&addr, &Target::Join(ROOM.to_string()), identity("join-me"), None, |step| {
+        steps.push(step)
+    })
+    .unwrap();
+    let (room, _peer_stream) = peer_side.recv().unwrap();
+
+    assert_eq!(room, ROOM);
+    assert_eq!(steps, vec![
+        Step::RoomJoined { room: ROOM.to_string() },
+        Step::PeerFound {
+            role: Role::Client,
+            fingerprint: fingerprint(&peer),
+        },
+        Step::Secured,
+    ]);
+    assert_eq!(session.role(), Role::Client);
+    assert_eq!(session.peer_key(), &peer);
+}
+
+#[test]
+fn test_create_reports_the_room_before_the_peer() {
+    let peer = identity("create-peer").public_key_bytes();
+    let (addr, peer_side) = signaling("CREATE", "", Some(Role::Host), peer);
+
+    let mut steps = Vec::new();
+    let session = session::connect(&addr, &Target::Create, identity("create-me"), None, |step| {
+        steps.push(step)
+    })
+    .unwrap();
+    let (room, _peer_stream) = peer_side.recv().unwrap();
+
+    assert_eq!(steps[0], Step::RoomCreated { room: room.clone() });
+    assert!(matches!(steps[1], Step::PeerFound { role: Role::Host, .. }));
+    assert_eq!(steps[2], Step::Secured);
+    assert_eq!(session.room_code(), room);
+    assert_eq!(session.role(), Role::Host);
+}
+
+#[test]
+fn test_join_refusals_are_typed() {
+    let peer = identity("refusals-peer").public_key_bytes();
+    let (addr, _) = signaling("JOIN", "ROOM_NOT_FOUND\n", None, peer);
+    let mut steps = Vec::new();
+    let result = session::connect(&addr, &Target::Join(ROOM.to_string()), identity("refusals-me"), None, |step| {
+        steps.push(step)
+    });
+    assert!(matches!(result, Err(SessionError::RoomNotFound)));
+    assert!(steps.is_empty());
+
+    let (addr, _) = signaling("JOIN", "ROOM_FULL\n", None, peer);
+    assert!(matches!(Session::join(&addr, ROOM, identity("refusals-me")), Err(SessionError::RoomFull)));
+
+    // Checked before connecting
+    let target = Target::Join("not a code".to_string());
+    let result = session::connect("127.0.0.1:1", &target, identity("refusals-me"), None, |_| {});
+    assert!(matches!(result, Err(SessionError::InvalidCode)));
+}
+
+#[test]
+fn test_failures_stop_at_their_step() {
+    let me = identity("failures-me").public_key_bytes();
+
+    // The server hangs up instead of introducing the peer
+    let (addr, _) = signaling("JOIN", "ROOM_JOINED\n", None, me);
+    let mut steps = Vec::new();
+    let result = session::connect(&addr, &Target::Join(ROOM.to_string()), identity("failures-me"), None, |step| {
+        steps.push(step)
+    });
+    assert!(matches!(result, Err(SessionError::Connect(_))));
+    assert_eq!(steps, vec![Step::RoomJoined { room: ROOM.to_string() }]);
+
+    // A peer key that is not a key
+    let addr = fake_server(|mut stream| {
+        read_line(&mut stream);
+        stream.write_all(b"ROOM_JOINED\nPEER_PUBKEY c2hvcnQ= CLIENT\n").unwrap();
+    });
+    let result = session::connect(&addr, &Target::Join(ROOM.to_string()), identity("failures-me"), None, |_| {});
+    assert!(matches!(result, Err(SessionError::Signaling(_))));
+
+    // The peer vanishes during the handshake
+    let addr = fake_server(move |mut stream| {
+        read_line(&mut stream);
+        let peer = general_purpose::STANDARD.encode(me);
+        write!(stream, "ROOM_JOINED\nPEER_PUBKEY {} CLIENT\n", peer).unwrap();
+        let _ = stream.read(&mut [0u8; 256]);
+    });
+    let mut steps = Vec::new();
+    let result = session::connect(&addr, &Target::Join(ROOM.to_string()), identity("failures-me"), None, |step| {
+        steps.push(step)
+    });
+    assert!(matches!(result, Err(SessionError::Handshake(_))));
+    assert!(matches!(steps.last(), Some(Step::PeerFound { .. })));
+}
+
+#[test]
+fn test_two_sessions_talk_and_close() {
+    let addr = fake_relay();
+    let pending = Session::create(&addr, identity("pair-host")).unwrap();
+    let room = pending.room_code().to_string();
+
+    // Sessions stay on the thread that made them; the guest sends its
+    // events out and closes when told to
+    let (guest_tx, guest_side) = mpsc::channel();
+    let (close_tx, close) = mpsc::channel::<()>();
+    let guest = thread::spawn(move || {
+        let guest = Session::join(&addr, &room, identity("pair-guest")).unwrap();
+        let id = guest.send_text("hello from the guest").unwrap();
+        guest_tx.send((guest.events(), id, guest.my_id().to_string())).unwrap();
+        close.recv().ok();
+        guest.close();
+    });
+    let host = pending.wait().unwrap();
+    let host_events = host.events();
+    let (guest_events, id, guest_id) = guest_side.recv().unwrap();
+
+    match next_event(&host_events).unwrap() {
+        Event::Message { id: got, key, body, .. } => {
+            assert_eq!(got, format!("{:016x}", id));
+            assert_eq!(key, guest_id);
+            assert_eq!(body, "hello from the guest");
+        }
+        other => panic!("expected a message, got {:?}", other),
+    }
+    host.send_text("and back").unwrap();
+    assert!(matches!(next_event(&guest_events).unwrap(), Event::Message { body, .. } if body == "and back"));
+
+    // Too big for one frame: refused rather than reported as queued
+    let huge = "x".repeat(70_000);
+    assert!(matches!(host.send_text(&huge), Err(SessionError::Send(_))));
+
+    // Closing one side ends the other's event stream
+    close_tx.send(()).unwrap();
+    guest.join().unwrap();
+    assert!(matches!(next_event(&host_events).unwrap(), Event::PeerLeft { .. }));
+    assert_eq!(next_event(&host_events).unwrap_err(), RecvTimeoutError::Disconnected);
+    assert!(!host.link().is_connected());
+    assert!(matches!(host.send_text("too late"), Err(SessionError::Send(_))));
+}
+
+/// Join a peer that announces `name`; returns the system notices from
+/// the announcement on
+fn notices_for_announced_name(peer: &Identity, name: &str) -> Vec<String> {
+    let key = peer.public_key_bytes();
+    let (addr, peer_side) = signaling("JOIN", "ROOM_JOINED\n", Some(Role::Client), key);
+    let session = Session::join(&addr, ROOM, identity("names-me")).unwrap();
+    let events = session.events();
+    let (_, mut stream) = peer_side.recv().unwrap();
+
+    let nick = ChatNick {
+        name: name.to_string(),
+        signature: peer.sign(&signing_payload(&key, name)).to_vec(),
+    };
+    stream.send(&ChatMessage::Nick(nick).to_bytes().unwrap()).unwrap();
+
+    let mut notices: Vec<String> = Vec::new();
+    let mut wait = Duration::from_secs(5);
+    while let Ok(event) = events.recv_timeout(wait) {
+        if let Event::System { text } = event
+            && (!notices.is_empty() || text.starts_with("Peer is now known as"))
+        {
+            notices.push(text);
+            wait = Duration::from_millis(300);
+        }
+    }
+    notices
+}
+
+#[test]
+fn test_reused_name_warns_in_its_own_notice() {
+    let first = notices_for_announced_name(&identity("names-first"), "mallory");
+    assert_eq!(first.len(), 1);
+
+    let second = notices_for_announced_name(&identity("names-second"), "mallory");
+    assert_eq!(second.len(), 2);
+    assert!(second[0].starts_with("Peer is now known as mallory"));
+    assert!(!second[0].contains('\n'));
+    assert!(second[1].starts_with("Warning: mallory"));
+    assert!(second[1].ends_with("used this name before, compare fingerprints"));
+}
@@ -0,0 +1,177 @@
+// Tests for the JSON-RPC control socket
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use client::control::{Call, ControlRequest, ControlServer};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voicechat-control-{}-{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+/// A server whose chat loop answers status and send, and fails mute
+fn server(path: PathBuf) -> ControlServer {
+    let (tx, rx) = mpsc::channel::<ControlRequest>();
+    let server = ControlServer::start(path, tx).unwrap();
+    thread::spawn(move || {
+        for request in rx {
+            let result = match request.call {
+                Call::Status => Ok(json!({ "room": "AB12-CD34", "connected": true })),
+                Call::Send { text, reply_to } => Ok(json!({ "text": text, "reply_to": reply_to })),
+                Call::Mute => Err("Voice is off".to_string()),
+                other => Err(format!("unexpected {:?}", other)),
+            };
+            request.reply.send(result).ok();
+        }
+    });
+    server
+}
+
+/// One connection, one JSON value per line each way
+struct Client {
+    out: UnixStream,
+    lines: io::Lines<BufReader<UnixStream>>,
+}
+
+impl Client {
+    fn connect(server: &ControlServer) -> Self {
+        let out = UnixStream::connect(server.path()).unwrap();
+        let lines = BufReader::new(out.try_clone().unwrap()).lines();
+        Self { out, lines }
+    }
+
+    fn call(&mut self, line: &str) -> Value {
+        writeln!(self.out, "{}", line).unwrap();
+        serde_json::from_str(&self.lines.next().unwrap().unwrap()).unwrap()
+    }
+}
+
+fn error_code(response: &Value) -> i64 {
+    response["error"]["code"].as_i64().unwrap()
+}
+
+#[test]
+fn test_call_parse() {
+    let call = Call::parse("send", json!({ "text": "hi", "reply_to": "3fa9" })).unwrap();
+    assert!(matches!(call, Call::Send { text, reply_to: Some(id) } if text == "hi" && id == "3fa9"));
+    let call = Call::parse("send", json!({ "text": "hi" })).unwrap();
+    assert!(matches!(call, Call::Send { reply_to: None, .. }));
+    assert!(matches!(Call::parse("mute", Value::Null), Ok(Call::Mute)));
+    assert!(matches!(Call::parse("unmute", json!({})), Ok(Call::Unmute)));
+    assert!(matches!(Call::parse("status", Value::Null), Ok(Call::Status)));
+    assert!(matches!(Call::parse("participants", Value::Null), Ok(Call::Participants)));
+    assert!(matches!(Call::parse("leave", Value::Null), Ok(Call::Leave)));
+
+    let (code, message) = Call::parse("dance", Value::Null).unwrap_err();
+    assert_eq!((code, message.as_str()), (-32601, "Method not found: dance"));
+    for params in [Value::Null, json!({}), json!({ "text": 5 }), json!(["hi", 5])] {
+        let (code, message) = Call::parse("send", params).unwrap_err();
+        assert_eq!(code, -32602);
+        assert!(message.starts_with("Invalid params"), "{}", message);
+    }
+}
+
+#[test]
+fn test_requests_and_responses() {
+    let dir = temp_dir("calls");
+    let server = server(dir.join("control.sock"));
+    let mut client = Client::connect(&server);
+
+    assert_eq!(
+        client.call(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#),
+        json!({ "jsonrpc": "2.0", "id": 1, "result": { "room": "AB12-CD34", "connected": true } })
+    );
+    // Blank lines are skipped, ids of any type come back as sent
+    assert_eq!(
+        client.call("\n\n{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"send\",\"params\":{\"text\":\"hi\",\"reply_to\":\"3fa9\"}}"),
+        json!({ "jsonrpc": "2.0", "id": "a", "result": { "text": "hi", "reply_to": "3fa9" } })
+    );
+    let failed = client.call(r#"{"jsonrpc":"2.0","id":3,"method":"mute"}"#);
+    assert_eq!(failed["id"], 3);
+    assert_eq!(failed["error"], json!({ "code": -32000, "message": "Voice is off" }));
+
+    // A second client gets its own connection
+    let mut other = Client::connect(&server);
+    assert_eq!(other.call(r#"{"jsonrpc":"2.0","id":9,"method":"status"}"#)["id"], 9);
+    assert_eq!(client.call(r#"{"jsonrpc":"2.0","id":4,"method":"status"}"#)["id"], 4);
+    drop(server);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_error_codes() {
+    let dir = temp_dir("errors");
+    let server = server(dir.join("control.sock"));
+    let mut client = Client::connect(&server);
+
+    let response = client.call("{not json");
+    assert_eq!(error_code(&response), -32700);
+    assert_eq!(response["id"], Value::Null);
+    assert_eq!(error_code(&client.call(r#"{"jsonrpc":"2.0","id":1}"#)), -32700);
+
+    let response = client.call(r#"{"jsonrpc":"1.0","id":2,"method":"status"}"#);
+    assert_eq!(error_code(&response), -32600);
+    assert_eq!(response["id"], 2);
+    assert_eq!(error_code(&client.call(r#"{"id":3,"method":"status"}"#)), -32600);
+
+    let response = client.call(r#"{"jsonrpc":"2.0","id":4,"method":"dance"}"#);
+    assert_eq!(response["error"], json!({ "code": -32601, "message": "Method not found: dance" }));
+
+    let response = client.call(r#"{"jsonrpc":"2.0","id":5,"method":"send","params":{"body":"hi"}}"#);
+    assert_eq!(error_code(&response), -32602);
+    assert_eq!(response["id"], 5);
+
+    // The connection stays usable after errors
+    assert!(client.call(r#"{"jsonrpc":"2.0","id":6,"method":"status"}"#)["result"].is_object());
+    drop(server);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_socket_is_private_and_removed_on_drop() {
+    let dir = temp_dir("mode");
+    let path = dir.join("nested/control.sock");
+    let server = server(path.clone());
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    drop(server);
+    assert!(!path.exists());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_stale_socket_is_replaced_but_live_one_is_not() {
+    let dir = temp_dir("stale");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("control.sock");
+
+    // Left behind by a client that crashed: nobody is listening
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let first = server(path.clone());
+    assert_eq!(Client::connect(&first).call(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#)["id"], 1);
+
+    // Still in use by a running client
+    let (tx, _rx) = mpsc::channel::<ControlRequest>();
+    let err = ControlServer::start(path.clone(), tx).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    assert!(path.exists());
+    drop(first);
+
+    // Never deletes something that is not a socket
+    fs::write(&path, b"notes").unwrap();
+    let (tx, _rx) = mpsc::channel::<ControlRequest>();
+    let err = ControlServer::start(path.clone(), tx).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read(&path).unwrap(), b"notes");
+    fs::remove_dir_all(&dir).ok();
+}
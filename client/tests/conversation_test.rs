@@ -0,0 +1,266 @@
+// Tests for the in-memory conversation: ownership, threads, reactions,
+// receipts and disappearing messages
+
+use std::time::Duration;
+
+use client::conversation::{Conversation, ConversationError, DeliveryStatus, Entry};
+use vc_core::protocol::chat::{
+    now_millis, short_id, ChatDelete, ChatEdit, ChatReaction, ChatReceipt, ChatText, ChatTimer, MessageId,
+    ReceiptStatus,
+};
+
+const ME: &str = "me";
+const PEER: &str = "peer";
+
+/// A message from `sender` with a fixed id
+fn text(sender: &str, id: MessageId, body: &str, reply_to: Option<MessageId>) -> ChatText {
+    let mut text = ChatText::new(sender.to_string(), body.to_string(), reply_to);
+    text.id = id;
+    text
+}
+
+fn edit(id: MessageId, body: &str) -> ChatEdit {
+    ChatEdit {
+        id,
+        body: body.to_string(),
+        edited_at: 0,
+    }
+}
+
+fn react(id: MessageId, emoji: &str, remove: bool) -> ChatReaction {
+    ChatReaction {
+        id,
+        emoji: emoji.to_string(),
+        remove,
+    }
+}
+
+fn receipt(ids: &[MessageId], status: ReceiptStatus) -> ChatReceipt {
+    ChatReceipt {
+        ids: ids.to_vec(),
+        status,
+    }
+}
+
+fn status(conv: &Conversation, id: MessageId) -> Option<DeliveryStatus> {
+    conv.get(id).unwrap().status
+}
+
+fn ids(entries: Vec<&Entry>) -> Vec<MessageId> {
+    entries.iter().map(|e| e.id).collect()
+}
+
+#[test]
+fn test_only_the_author_edits_or_deletes() {
+    let mut conv = Conversation::new(ME.to_string());
+    assert!(conv.add_text(&text(PEER, 1, "helo", None)));
+    assert!(!conv.add_text(&text(PEER, 1, "helo", None)));
+
+    let err = conv.apply_edit(ME, &edit(1, "hijacked")).unwrap_err();
+    assert!(matches!(err, ConversationError::NotYourMessage));
+    assert!(matches!(conv.apply_delete(ME, &ChatDelete { id: 1 }), Err(ConversationError::NotYourMessage)));
+    assert_eq!(conv.get(1).unwrap().body, "helo");
+
+    conv.apply_edit(PEER, &edit(1, "hello")).unwrap();
+    let entry = conv.get(1).unwrap();
+    assert!(entry.edited);
+    assert!(conv.render(entry).ends_with("peer: hello (edited)"));
+
+    conv.apply_delete(PEER, &ChatDelete { id: 1 }).unwrap();
+    assert!(conv.get(1).unwrap().body.is_empty());
+    assert!(conv.render(conv.get(1).unwrap()).ends_with("(message deleted)"));
+    assert!(matches!(conv.apply_edit(PEER, &edit(1, "back")), Err(ConversationError::Deleted)));
+    assert!(matches!(conv.apply_edit(PEER, &edit(7, "?")), Err(ConversationError::UnknownMessage(_))));
+}
+
+#[test]
+fn test_threads_follow_replies_to_the_root() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(PEER, 1, "lunch?", None));
+    conv.add_text(&text(ME, 2, "unrelated", None));
+    conv.add_text(&text(ME, 3, "sure", Some(1)));
+    conv.add_text(&text(PEER, 4, "noon then", Some(3)));
+    conv.add_text(&text(ME, 5, "or one", Some(1)));
+    conv.add_text(&text(PEER, 6, "re: unrelated", Some(2)));
+
+    assert_eq!(ids(conv.thread(1)), [1, 3, 4, 5]);
+    // Any message in the thread finds the whole of it
+    assert_eq!(ids(conv.thread(4)), [1, 3, 4, 5]);
+    assert_eq!(ids(conv.thread(6)), [2, 6]);
+    assert!(conv.thread(99).is_empty());
+
+    let quoted = conv.render(conv.get(4).unwrap());
+    assert!(quoted.starts_with(&format!("  ┌ re [{}] you: sure\n", short_id(3))));
+    // A reply to a message we never saw starts its own thread
+    conv.add_text(&text(PEER, 7, "what?", Some(42)));
+    assert_eq!(ids(conv.thread(7)), [7]);
+    assert!(conv.render(conv.get(7).unwrap()).contains("(unknown message)"));
+}
+
+#[test]
+fn test_reply_cycles_do_not_hang() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(PEER, 1, "a", Some(2)));
+    conv.add_text(&text(PEER, 2, "b", Some(1)));
+    conv.add_text(&text(PEER, 3, "c", Some(2)));
+    assert_eq!(ids(conv.thread(3)), [1, 2, 3]);
+}
+
+#[test]
+fn test_reactions_toggle_per_sender() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(PEER, 1, "news", None));
+
+    conv.apply_reaction(ME, &react(1, "👍", false)).unwrap();
+    conv.apply_reaction(PEER, &react(1, "👍", false)).unwrap();
+    // Reacting twice counts once
+    conv.apply_reaction(ME, &react(1, "👍", false)).unwrap();
+    conv.apply_reaction(ME, &react(1, "🎉", false)).unwrap();
+    assert!(conv.has_reacted(ME, 1, "👍"));
+    assert!(conv.render(conv.get(1).unwrap()).ends_with("[🎉 1, 👍 2]"));
+
+    conv.apply_reaction(ME, &react(1, "🎉", true)).unwrap();
+    conv.apply_reaction(ME, &react(1, "👍", true)).unwrap();
+    assert!(!conv.has_reacted(ME, 1, "👍"));
+    assert!(conv.has_reacted(PEER, 1, "👍"));
+    assert!(!conv.get(1).unwrap().reactions.contains_key("🎉"));
+
+    conv.apply_delete(PEER, &ChatDelete { id: 1 }).unwrap();
+    assert!(conv.get(1).unwrap().reactions.is_empty());
+    assert!(matches!(conv.apply_reaction(ME, &react(1, "👍", false)), Err(ConversationError::Deleted)));
+}
+
+#[test]
+fn test_short_ids_resolve_by_unique_prefix() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(PEER, 0xabc1_0000_0000_0001, "one", None));
+    conv.add_text(&text(PEER, 0xabc2_0000_0000_0002, "two", None));
+
+    assert_eq!(conv.resolve("abc1").unwrap(), 0xabc1_0000_0000_0001);
+    assert_eq!(conv.resolve("#ABC2").unwrap(), 0xabc2_0000_0000_0002);
+    assert_eq!(conv.resolve(&short_id(0xabc2_0000_0000_0002)).unwrap(), 0xabc2_0000_0000_0002);
+    assert!(matches!(conv.resolve("abc"), Err(ConversationError::AmbiguousId(_))));
+    assert!(matches!(conv.resolve("ffff"), Err(ConversationError::UnknownMessage(_))));
+    assert!(matches!(conv.resolve(""), Err(ConversationError::UnknownMessage(_))));
+}
+
+#[test]
+fn test_receipts_only_move_forward() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(ME, 1, "one", None));
+    conv.add_text(&text(ME, 2, "two", None));
+    conv.add_text(&text(PEER, 3, "theirs", None));
+    assert_eq!(status(&conv, 1), Some(DeliveryStatus::Sent));
+    assert_eq!(status(&conv, 3), None);
+
+    assert_eq!(conv.apply_receipt(&receipt(&[1, 2], ReceiptStatus::Delivered)), [1, 2]);
+    assert_eq!(conv.apply_receipt(&receipt(&[1], ReceiptStatus::Read)), [1]);
+    // A late delivery receipt does not undo the read
+    assert!(conv.apply_receipt(&receipt(&[1], ReceiptStatus::Delivered)).is_empty());
+    assert!(conv.apply_receipt(&receipt(&[2], ReceiptStatus::Delivered)).is_empty());
+    assert_eq!(status(&conv, 1), Some(DeliveryStatus::Read));
+    assert_eq!(status(&conv, 2), Some(DeliveryStatus::Delivered));
+    assert!(conv.render(conv.get(1).unwrap()).ends_with("✓✓ read"));
+
+    // Receipts for peer messages or unknown ids change nothing
+    assert!(conv.apply_receipt(&receipt(&[3, 99], ReceiptStatus::Read)).is_empty());
+    assert_eq!(status(&conv, 3), None);
+}
+
+#[test]
+fn test_unconfirmed_messages_are_sent_again() {
+    let mut conv = Conversation::new(ME.to_string());
+    conv.add_text(&text(ME, 1, "delivered", None));
+    conv.add_text(&text(ME, 2, "lost", Some(1)));
+    conv.add_text(&text(ME, 3, "deleted", None));
+    conv.add_text(&text(PEER, 4, "theirs", None));
+    conv.add_text(&text(ME, 5, "read", None));
+    conv.apply_receipt(&receipt(&[1], ReceiptStatus::Delivered));
+    conv.apply_receipt(&receipt(&[5], ReceiptStatus::Read));
+    conv.apply_delete(ME, &ChatDelete { id: 3 }).unwrap();
+
+    let again = conv.unacknowledged();
+    assert_eq!(again.len(), 1);
+    assert_eq!((again[0].id, again[0].body.as_str(), again[0].reply_to), (2, "lost", Some(1)));
+    assert_eq!(again[0].sender_id, ME);
+    assert_eq!(again[0].expires_in, None);
+
+    // The peer sees a retransmit once
+    let mut peer = Conversation::new(PEER.to_string());
+    assert!(peer.add_text(&text(ME, 2, "lost", Some(1))));
+    assert!(!peer.add_text(&again[0]));
+    assert_eq!(peer.take_unread(), [2]);
+    assert!(peer.take_unread().is_empty());
+
+    conv.apply_receipt(&receipt(&[2], ReceiptStatus::Delivered));
+    assert!(conv.unacknowledged().is_empty());
+}
+
+#[test]
+fn test_expiring_message_is_dropped_after_its_ttl() {
+    let mut conv = Conversation::new(ME.to_string());
+    let mut brief = text(PEER, 1, "self-destructs", None);
+    brief.expires_in = Some(30);
+    conv.add_text(&brief);
+    conv.add_text(&text(PEER, 2, "stays", None));
+    conv.add_text(&text(ME, 3, "re", Some(1)));
+
+    // Counted from when it arrived here
+    let expires_at = conv.get(1).unwrap().expires_at.unwrap();
+    assert!(expires_at >= now_millis() + 29_000 && expires_at <= now_millis() + 30_000);
+    assert!(conv.render(conv.get(1).unwrap()).contains("  ⏱ "));
+
+    assert_eq!(conv.expire(expires_at - 1), 0);
+    assert_eq!(conv.expire(expires_at), 1);
+    assert!(conv.get(1).is_none());
+    assert_eq!(ids(conv.thread(3)), [3]);
+    assert_eq!(conv.transcript().len(), 2);
+    // A retransmit does not bring it back
+    assert!(!conv.add_text(&brief));
+    assert!(conv.get(1).is_none());
+}
+
+#[test]
+fn test_unconfirmed_expiring_message_keeps_its_remaining_time() {
+    let mut conv = Conversation::new(ME.to_string());
+    let mut mine = text(ME, 1, "soon gone", None);
+    mine.expires_in = Some(60);
+    mine.sent_at = now_millis() - 20_000;
+    conv.add_text(&mine);
+
+    let again = conv.unacknowledged();
+    assert!((39..=40).contains(&again[0].expires_in.unwrap()));
+}
+
+#[test]
+fn test_ttl_negotiation_applies_the_latest_timer() {
+    let mut conv = Conversation::new(ME.to_string());
+    assert_eq!(conv.ttl(), None);
+    assert_eq!(conv.new_text("plain", None).expires_in, None);
+
+    // The peer ran /ttl 5m
+    let theirs = ChatTimer {
+        ttl_secs: Some(300),
+        set_at: 2_000,
+    };
+    assert!(conv.set_ttl(&theirs));
+    assert_eq!(conv.ttl(), Some(Duration::from_secs(300)));
+    assert_eq!(conv.new_text("brief", None).expires_in, Some(300));
+    // Seen again after a resume, nothing changes
+    assert!(!conv.set_ttl(&theirs));
+
+    // Our older /ttl off crossed theirs on the wire and loses
+    let stale = ChatTimer {
+        ttl_secs: None,
+        set_at: 1_000,
+    };
+    assert!(!conv.set_ttl(&stale));
+    assert_eq!(conv.ttl(), Some(Duration::from_secs(300)));
+
+    let off = ChatTimer {
+        ttl_secs: None,
+        set_at: 3_000,
+    };
+    assert!(conv.set_ttl(&off));
+    assert_eq!(conv.new_text("kept", None).expires_in, None);
+}
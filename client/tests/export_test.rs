@@ -0,0 +1,146 @@
+// Tests for transcript export in each format, with and without redaction
+
+use base64::{engine::general_purpose, Engine};
+
+use client::export::{self, ExportFormat};
+use client::history::TranscriptEntry;
+use vc_core::protocol::nick::{display_name, fingerprint};
+
+/// 2024-02-29 12:34 UTC
+const NOON: u64 = 1_709_210_040_000;
+
+fn key(byte: u8) -> String {
+    general_purpose::STANDARD.encode([byte; 32])
+}
+
+fn message(id: u64, sender: u8, name: &str, body: &str, at: u64) -> TranscriptEntry {
+    TranscriptEntry {
+        id: Some(id),
+        sender_id: Some(key(sender)),
+        sender_name: display_name(name, &[sender; 32]),
+        mine: sender == 1,
+        body: body.to_string(),
+        sent_at: at,
+        reply_to: None,
+        edited: false,
+        deleted: false,
+    }
+}
+
+/// Alice asks, Bob answers, edits and deletes, and a reconnect in between
+fn transcript() -> Vec<TranscriptEntry> {
+    let question = message(0xa1, 1, "alice", "ship it?", NOON);
+    let mut answer = message(0xb2, 2, "bob", "yes\nafter lunch", NOON + 60_000);
+    answer.reply_to = Some(0xa1);
+    answer.edited = true;
+    let mut retracted = message(0xb3, 2, "bob", "", NOON + 180_000);
+    retracted.deleted = true;
+    vec![
+        question,
+        TranscriptEntry::system(NOON + 30_000, "bob reconnected".to_string()),
+        answer,
+        retracted,
+    ]
+}
+
+#[test]
+fn test_json_lines() {
+    let out = export::render(&transcript(), ExportFormat::JsonLines, false);
+    let (alice, bob) = (fingerprint(&[1; 32]), fingerprint(&[2; 32]));
+    let expected = [
+        format!(
+            r#"{{"type":"message","time":"2024-02-29 12:34","timestamp_ms":1709210040000,"id":"00000000000000a1","sender":"alice~{}","fingerprint":"{}","key":"{}","mine":true,"body":"ship it?"}}"#,
+            alice,
+            alice,
+            key(1)
+        ),
+        r#"{"type":"system","time":"2024-02-29 12:34","timestamp_ms":1709210070000,"body":"bob reconnected"}"#.to_string(),
+        format!(
+            r#"{{"type":"message","time":"2024-02-29 12:35","timestamp_ms":1709210100000,"id":"00000000000000b2","sender":"bob~{}","fingerprint":"{}","key":"{}","reply_to":"00000000000000a1","body":"yes\nafter lunch","edited":true}}"#,
+            bob,
+            bob,
+            key(2)
+        ),
+        format!(
+            r#"{{"type":"message","time":"2024-02-29 12:37","timestamp_ms":1709210220000,"id":"00000000000000b3","sender":"bob~{}","fingerprint":"{}","key":"{}","body":"","deleted":true}}"#,
+            bob,
+            bob,
+            key(2)
+        ),
+    ];
+    assert_eq!(out, expected.join("\n") + "\n");
+}
+
+#[test]
+fn test_markdown() {
+    let out = export::render(&transcript(), ExportFormat::Markdown, false);
+    let (alice, bob) = (fingerprint(&[1; 32]), fingerprint(&[2; 32]));
+    let (header, list) = out.split_once("UTC\n\n").unwrap();
+    assert!(header.starts_with("# Voicechat transcript\n\nExported "));
+    assert_eq!(
+        list,
+        format!(
+            "- **2024-02-29 12:34** `alice~{a}`: ship it?\n\
+             - *2024-02-29 12:34 — bob reconnected*\n\
+             - **2024-02-29 12:35** `bob~{b}` (reply to `000000`): yes\n  after lunch *(edited)*\n\
+             - **2024-02-29 12:37** `bob~{b}`: *message deleted*\n",
+            a = alice,
+            b = bob
+        )
+    );
+}
+
+#[test]
+fn test_text() {
+    let out = export::render(&transcript(), ExportFormat::Text, false);
+    let (alice, bob) = (fingerprint(&[1; 32]), fingerprint(&[2; 32]));
+    // Our own messages carry our name, not "you"
+    assert_eq!(
+        out,
+        format!(
+            "[2024-02-29 12:34] alice~{a}: ship it?\n\
+             [2024-02-29 12:34] * bob reconnected\n\
+             [2024-02-29 12:35] bob~{b}: yes\nafter lunch (edited)\n\
+             [2024-02-29 12:37] bob~{b}: (message deleted)\n",
+            a = alice,
+            b = bob
+        )
+    );
+}
+
+#[test]
+fn test_redaction_removes_keys_and_fingerprints() {
+    let mut entries = transcript();
+    let (alice, bob) = (fingerprint(&[1; 32]), fingerprint(&[2; 32]));
+    entries[0].body = format!("my key is {} and bob is bob~{}", key(9), bob);
+    // A peer with no name yet shows as its key
+    entries.push(TranscriptEntry {
+        sender_name: key(3),
+        ..message(0xc4, 3, "", "hi", NOON + 240_000)
+    });
+
+    for format in [ExportFormat::JsonLines, ExportFormat::Markdown, ExportFormat::Text] {
+        let out = export::render(&entries, format, true);
+        for secret in [key(1), key(2), key(3), key(9), alice.clone(), bob.clone(), fingerprint(&[3; 32])] {
+            assert!(!out.contains(&secret), "{:?} export leaks {}:\n{}", format, secret, out);
+        }
+        assert!(out.contains("my key is [key redacted] and bob is bob~[fingerprint redacted]"));
+        assert!(out.contains("alice"));
+    }
+
+    let json = export::render(&entries, ExportFormat::JsonLines, true);
+    let first: serde_json::Value = serde_json::from_str(json.lines().next().unwrap()).unwrap();
+    assert_eq!(first["sender"], "alice");
+    assert!(first.get("key").is_none() && first.get("fingerprint").is_none());
+    assert_eq!(first["id"], "00000000000000a1");
+}
+
+#[test]
+fn test_format_names() {
+    assert_eq!("JSON".parse::<ExportFormat>().unwrap(), ExportFormat::JsonLines);
+    assert_eq!("md".parse::<ExportFormat>().unwrap().extension(), "md");
+    assert_eq!("text".parse::<ExportFormat>().unwrap(), ExportFormat::Text);
+    assert!("pdf".parse::<ExportFormat>().unwrap_err().contains("jsonl, md or txt"));
+    assert!(export::default_path(ExportFormat::Markdown).to_string_lossy().starts_with("voicechat-"));
+    assert_eq!(export::parse_hex_id(&export::hex_id(0xb2)), Some(0xb2));
+}
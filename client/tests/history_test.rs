@@ -0,0 +1,161 @@
+// Tests for the encrypted history log, replayed from a temporary $HOME
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Once;
+
+use client::history::{self, History, HistoryScope};
+use vc_core::crypto::storage::StorageCipher;
+use vc_core::protocol::chat::{now_millis, ChatText};
+
+const ME: &str = "bWU=";
+const PEER: &str = "cGVlcg==";
+
+/// Logs live under $HOME; keep them out of the real one. Each test
+/// writes its own log.
+fn test_home() -> PathBuf {
+    static INIT: Once = Once::new();
+    let home = std::env::temp_dir().join(format!("voicechat-history-{}", std::process::id()));
+    // SAFETY: every test calls this before any thread reads HOME
+    INIT.call_once(|| unsafe { std::env::set_var("HOME", &home) });
+    home
+}
+
+fn log_path(secret: &[u8; 32], peer: &str) -> PathBuf {
+    let name = StorageCipher::from_identity(secret, "history").file_name(&format!("peer {}", peer));
+    test_home().join(".voicechat/history").join(format!("{}.log", name))
+}
+
+/// A fresh log for `peer`, under its own identity secret
+fn open(peer: &str) -> (History, PathBuf) {
+    let secret = [peer.len() as u8; 32];
+    let path = log_path(&secret, peer);
+    fs::remove_file(&path).ok();
+    (History::open(&secret, HistoryScope::Peer, peer, ME).unwrap(), path)
+}
+
+fn text(sender: &str, body: &str) -> ChatText {
+    ChatText::new(sender.to_string(), body.to_string(), None)
+}
+
+fn bodies(history: &History) -> Vec<String> {
+    history.load().unwrap().into_iter().map(|entry| entry.body).collect()
+}
+
+#[test]
+fn test_replay_applies_edits_and_deletes() {
+    let (history, _) = open("replay");
+    assert!(!history.exists());
+    assert!(history.load().unwrap().is_empty());
+
+    let hello = text(ME, "hello");
+    let typo = text(PEER, "helo");
+    let mut reply = text(PEER, "oops");
+    reply.reply_to = Some(hello.id);
+    history.record_text(&hello, "me", None);
+    history.record_text(&typo, "peer", None);
+    history.record_system(now_millis(), "peer is away");
+    history.record_text(&reply, "peer", None);
+    history.record_edit(typo.id, "hello back");
+    history.record_delete(reply.id);
+    // Seen again after a resume, kept once
+    history.record_text(&hello, "me", None);
+
+    let entries = history.load().unwrap();
+    assert_eq!(entries.len(), 4);
+    assert!(entries[0].mine && !entries[1].mine);
+    assert_eq!(entries[1].body, "hello back");
+    assert!(entries[1].edited);
+    assert!(entries[2].is_system());
+    assert!(entries[3].deleted && entries[3].body.is_empty());
+    assert_eq!(entries[3].reply_to, Some(hello.id));
+
+    assert!(history::format_entry(&entries[0]).ends_with("] you: hello"));
+    assert!(history::format_entry(&entries[1]).ends_with("] peer: hello back (edited)"));
+    assert!(history::format_entry(&entries[2]).ends_with("] * peer is away"));
+    assert!(history::format_entry(&entries[3]).ends_with("] peer: (message deleted)"));
+}
+
+#[test]
+fn test_expired_messages_are_hidden_then_purged() {
+    let (history, path) = open("expiry");
+    let gone = text(PEER, "gone");
+    let later = text(PEER, "later");
+    history.record_text(&text(ME, "kept"), "me", None);
+    history.record_text(&gone, "peer", Some(now_millis() - 1));
+    history.record_edit(gone.id, "gone, edited");
+    history.record_text(&later, "peer", Some(now_millis() + 60_000));
+    assert_eq!(bodies(&history), ["kept", "later"]);
+
+    let before = fs::metadata(&path).unwrap().len();
+    assert_eq!(history.purge_expired().unwrap(), 1);
+    assert!(fs::metadata(&path).unwrap().len() < before);
+    assert!(!path.with_extension("log.tmp").exists());
+    assert_eq!(bodies(&history), ["kept", "later"]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    // Nothing left to drop leaves the file alone
+    assert_eq!(history.purge_expired().unwrap(), 0);
+}
+
+#[test]
+fn test_torn_record_at_the_end_is_skipped() {
+    let (history, path) = open("torn");
+    history.record_text(&text(ME, "first"), "me", None);
+    history.record_text(&text(PEER, "second"), "peer", None);
+
+    // A crash mid-write: the length promises more than was written
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&200u32.to_be_bytes()).unwrap();
+    file.write_all(b"half a record").unwrap();
+    drop(file);
+    assert_eq!(bodies(&history), ["first", "second"]);
+
+    // The next append cuts the torn bytes off instead of landing behind them
+    history.record_text(&text(ME, "third"), "me", None);
+    assert_eq!(bodies(&history), ["first", "second", "third"]);
+}
+
+#[test]
+fn test_log_is_sealed_to_its_key() {
+    let (history, path) = open("sealed");
+    history.record_text(&text(ME, "secret plans"), "me", None);
+    let raw = fs::read(&path).unwrap();
+    assert!(!raw.windows(6).any(|w| w == b"secret"));
+
+    // Another identity cannot read it, even under its own name
+    let other = [9; 32];
+    fs::copy(&path, log_path(&other, "sealed")).unwrap();
+    let reader = History::open(&other, HistoryScope::Peer, "sealed", ME).unwrap();
+    assert!(reader.exists());
+    assert!(reader.load().unwrap().is_empty());
+}
+
+#[test]
+fn test_search_matches_live_bodies() {
+    let (history, _) = open("search");
+    let deleted = text(PEER, "Lunch was on the roof");
+    history.record_text(&text(ME, "Lunch at noon?"), "me", None);
+    history.record_text(&text(PEER, "sure, see you"), "peer", None);
+    history.record_text(&deleted, "peer", None);
+    history.record_delete(deleted.id);
+    history.record_text(&text(PEER, "no LUNCH for me"), "peer", None);
+
+    let entries = history.load().unwrap();
+    let found: Vec<&str> = history::search(&entries, "lunch").iter().map(|e| e.body.as_str()).collect();
+    assert_eq!(found, ["Lunch at noon?", "no LUNCH for me"]);
+    assert!(history::search(&entries, "dinner").is_empty());
+}
+
+#[test]
+fn test_scope_and_timestamps() {
+    assert_eq!("Room".parse::<HistoryScope>().unwrap(), HistoryScope::Room);
+    assert!("everyone".parse::<HistoryScope>().unwrap_err().contains("off, peer or room"));
+    assert_eq!(history::format_timestamp(0), "1970-01-01 00:00");
+    assert_eq!(history::format_timestamp(1_709_210_096_000), "2024-02-29 12:34");
+}
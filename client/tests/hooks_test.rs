@@ -0,0 +1,126 @@
+// Tests for event hooks and the config file that declares them
+
+use client::config::Config;
+use client::hooks::{is_mention, HookEvent, Hooks};
+use client::session::Event;
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+#[test]
+fn test_mention_matches_whole_words_only() {
+    let alice = words(&["alice"]);
+    assert!(is_mention("hi alice", &alice));
+    assert!(is_mention("@Alice: look", &alice));
+    assert!(!is_mention("malice", &alice));
+    assert!(!is_mention("alice_b", &alice));
+    assert!(!is_mention("hi there", &words(&[""])));
+}
+
+#[test]
+fn test_config_parses_hooks() {
+    let config = Config::parse(
+        r#"
+        [[hooks]]
+        on = "mention"
+        command = "true"
+        words = ["ops"]
+
+        [[hooks]]
+        on = "call_ended"
+        command = "true"
+        timeout = 2
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.hooks.len(), 2);
+    assert_eq!(config.hooks[0].on, HookEvent::Mention);
+    assert_eq!(config.hooks[0].timeout, 10);
+    assert_eq!(config.hooks[1].on, HookEvent::CallEnded);
+    assert_eq!(config.hooks[1].timeout, 2);
+
+    assert!(Config::parse("[[hooks]]\non = \"sneeze\"\ncommand = \"true\"").is_err());
+    assert!(Config::parse("hook = 1").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_gets_env_and_json() {
+    let out = std::env::temp_dir().join(format!("voicechat-hook-{}", std::process::id()));
+    let config = Config::parse(&format!(
+        "[[hooks]]\non = \"message\"\ncommand = \"echo $VOICECHAT_SENDER $VOICECHAT_BODY > {0}; cat >> {0}\"",
+        out.display()
+    ))
+    .unwrap();
+    let hooks = Hooks::new(config.hooks, "ROOM1");
+    hooks.fire(&Event::CallStarted, "bob");
+    hooks.fire(
+        &Event::Message {
+            id: "00000000000000ab".to_string(),
+            sender: "bob".to_string(),
+            key: "k".to_string(),
+            body: "hello".to_string(),
+            timestamp_ms: 0,
+            reply_to: None,
+        },
+        "bob",
+    );
+    hooks.wait();
+
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).ok();
+    let mut lines = written.lines();
+    assert_eq!(lines.next(), Some("bob hello"));
+    let json: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(json["type"], "message");
+    assert_eq!(json["body"], "hello");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_is_killed_after_timeout() {
+    let config = Config::parse("[[hooks]]\non = \"call_started\"\ncommand = \"sleep 30\"\ntimeout = 1").unwrap();
+    let hooks = Hooks::new(config.hooks, "ROOM1");
+    let started = std::time::Instant::now();
+    hooks.fire(&Event::CallStarted, "bob");
+    hooks.wait();
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_mention_hook_follows_nick_changes() {
+    let out = std::env::temp_dir().join(format!("voicechat-mention-{}", std::process::id()));
+    let config = Config::parse(&format!(
+        "[[hooks]]\non = \"mention\"\ncommand = \"echo $VOICECHAT_BODY >> {}\"\nwords = [\"ops\"]",
+        out.display()
+    ))
+    .unwrap();
+    let hooks = Hooks::new(config.hooks, "ROOM1");
+    let message = |body: &str| Event::Message {
+        id: "00000000000000ab".to_string(),
+        sender: "bob".to_string(),
+        key: "k".to_string(),
+        body: body.to_string(),
+        timestamp_ms: 0,
+        reply_to: None,
+    };
+
+    hooks.set_nick("zed");
+    for body in ["ping ops", "hi zed", "hi zoe", "zedd?"] {
+        hooks.fire(&message(body), "bob");
+    }
+    hooks.wait();
+    hooks.set_nick("zoe");
+    for body in ["hi zed", "hi zoe"] {
+        hooks.fire(&message(body), "bob");
+    }
+    hooks.wait();
+
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).ok();
+    let mut lines: Vec<&str> = written.lines().collect();
+    lines.sort();
+    assert_eq!(lines, ["hi zed", "hi zoe", "ping ops"]);
+}
@@ -0,0 +1,135 @@
+// Tests for tab completion and the encrypted input history
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Once};
+
+use client::input::{Completer, Completion, InputHistory};
+use client::status::StatusLine;
+
+/// The input history lives under $HOME; keep it out of the real one.
+/// Each test uses its own identity secret.
+fn test_home() -> PathBuf {
+    static INIT: Once = Once::new();
+    let home = std::env::temp_dir().join(format!("voicechat-input-{}", std::process::id()));
+    // SAFETY: every test calls this before any thread reads HOME
+    INIT.call_once(|| unsafe { std::env::set_var("HOME", &home) });
+    home
+}
+
+fn completer(peer: &str) -> Completer {
+    let mut status = StatusLine::new();
+    status.set_peer_name(peer.to_string());
+    Completer::new(vec!["msg", "me", "mute", "send", "export", "help"], Arc::new(Mutex::new(status)))
+}
+
+fn complete(line: &str) -> Completion {
+    completer("alice~3fa9c1d2").complete(line, line.len())
+}
+
+fn completion(start: usize, candidates: &[&str]) -> Completion {
+    Completion {
+        start,
+        candidates: candidates.iter().map(|c| c.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_command_names() {
+    assert_eq!(complete("/m"), completion(0, &["/me ", "/msg ", "/mute "]));
+    assert_eq!(complete("/MS"), completion(0, &["/msg "]));
+    assert_eq!(complete("/zzz"), completion(0, &[]));
+    // "//" sends a literal slash, nothing to complete
+    assert_eq!(complete("//m"), completion(0, &[]));
+}
+
+#[test]
+fn test_peer_name() {
+    // At the start of a line it addresses the peer
+    assert_eq!(complete("al"), completion(0, &["alice: "]));
+    assert_eq!(complete("hi AL"), completion(3, &["alice "]));
+    assert_eq!(complete("hi @a"), completion(3, &["@alice "]));
+    assert_eq!(complete("/msg thanks al"), completion(12, &["alice "]));
+    assert_eq!(complete("hi bo"), completion(3, &[]));
+    assert_eq!(complete("hi @"), completion(3, &[]));
+    assert_eq!(complete("hi "), completion(3, &[]));
+}
+
+#[test]
+fn test_file_paths() {
+    let dir = std::env::temp_dir().join(format!("voicechat-complete-{}", std::process::id()));
+    fs::create_dir_all(dir.join("notes")).unwrap();
+    for name in ["report final.pdf", "report.txt", ".hidden"] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+    let base = format!("{}/", dir.display());
+
+    // /send takes the rest of the line, spaces included
+    let line = format!("/send {}rep", base);
+    let found = complete(&line);
+    assert_eq!(found.start, 6);
+    assert_eq!(found.candidates, [format!("{}report final.pdf", base), format!("{}report.txt", base)]);
+    assert_eq!(found.common_prefix(), format!("{}report", base));
+
+    let line = format!("/export md {}n", base);
+    assert_eq!(complete(&line), Completion {
+        start: 11,
+        candidates: vec![format!("{}notes/", base)],
+    });
+    // Hidden files only once a dot is typed
+    assert_eq!(complete(&format!("/send {}.", base)).candidates, [format!("{}.hidden", base)]);
+    assert_eq!(complete(&format!("/send {}", base)).candidates.len(), 3);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_common_prefix() {
+    assert_eq!(completion(0, &[]).common_prefix(), "");
+    assert_eq!(completion(0, &["/msg "]).common_prefix(), "/msg ");
+    assert_eq!(completion(0, &["/me ", "/msg ", "/mute "]).common_prefix(), "/m");
+    assert_eq!(completion(0, &["report.txt", "report"]).common_prefix(), "report");
+    // Stops on a character boundary
+    assert_eq!(completion(0, &["café", "cafè"]).common_prefix(), "caf");
+    assert_eq!(completion(0, &["abc", "xyz"]).common_prefix(), "");
+}
+
+#[test]
+fn test_history_round_trip() {
+    let home = test_home();
+    let secret = [41; 32];
+    let mut history = InputHistory::open(&secret);
+    assert!(history.entries().is_empty());
+    history.add("hello there\n");
+    history.add("hello there");
+    history.add("   ");
+    history.add("/msg secret plans");
+    history.add("hello there");
+
+    let reopened = InputHistory::open(&secret);
+    assert_eq!(reopened.entries(), ["hello there", "/msg secret plans", "hello there"]);
+    assert_eq!(reopened.search("hello", 3), Some(2));
+    assert_eq!(reopened.search("hello", 2), Some(0));
+    assert_eq!(reopened.search("plans", 99), Some(1));
+    assert_eq!(reopened.search("bye", 3), None);
+
+    // Encrypted at rest and unreadable with another identity
+    for entry in fs::read_dir(home.join(".voicechat/input")).unwrap() {
+        let data = fs::read(entry.unwrap().path()).unwrap();
+        assert!(!data.windows(6).any(|w| w == b"secret"));
+    }
+    assert!(InputHistory::open(&[42; 32]).entries().is_empty());
+}
+
+#[test]
+fn test_history_keeps_the_newest_thousand() {
+    test_home();
+    let secret = [43; 32];
+    let mut history = InputHistory::open(&secret);
+    for i in 0..1005 {
+        history.add(&format!("line {}", i));
+    }
+    let reopened = InputHistory::open(&secret);
+    assert_eq!(reopened.entries().len(), 1000);
+    assert_eq!(reopened.entries()[0], "line 5");
+    assert_eq!(InputHistory::open(&secret).entries().len(), 1000);
+}
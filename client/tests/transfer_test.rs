@@ -0,0 +1,210 @@
+// Tests for file transfers between two clients joined by a loopback
+// secure stream
+
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use client::identity::Identity;
+use client::transfer::{self, Notify, Transfers};
+use vc_core::net::secure_stream::SecureStream;
+use vc_core::protocol::chat::ChatMessage;
+use vc_core::protocol::handshake;
+use vc_core::protocol::transfer::CHUNK_SIZE;
+
+/// One side of a transfer: its stream, its transfers and what it printed
+struct End {
+    stream: Arc<Mutex<SecureStream>>,
+    transfers: Arc<Mutex<Transfers>>,
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl End {
+    fn new(stream: SecureStream, download_dir: PathBuf) -> Self {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&lines);
+        let notify: Notify = Arc::new(move |line: &str| sink.lock().unwrap().push(line.to_string()));
+        Self {
+            stream: Arc::new(Mutex::new(stream)),
+            transfers: Arc::new(Mutex::new(Transfers::new(download_dir, notify))),
+            lines,
+        }
+    }
+
+    /// Next message from the peer; the lock is dropped between polls so
+    /// sender threads can write
+    fn recv(&self) -> ChatMessage {
+        loop {
+            match self.stream.lock().unwrap().recv() {
+                Err(e) if e.is_timeout() => continue,
+                data => return ChatMessage::from_bytes(&data.unwrap()).unwrap(),
+            }
+        }
+    }
+
+    fn send(&self, msg: &ChatMessage) {
+        self.stream.lock().unwrap().send(&msg.to_bytes().unwrap()).unwrap();
+    }
+
+    fn handle(&self, msg: ChatMessage) -> Option<String> {
+        transfer::handle(&self.transfers, &self.stream, msg)
+    }
+
+    /// Handle messages until one produces a line containing `text`
+    fn handle_until(&self, text: &str) -> String {
+        loop {
+            if let Some(line) = self.handle(self.recv())
+                && line.contains(text)
+            {
+                return line;
+            }
+        }
+    }
+
+    fn printed(&self, text: &str) -> bool {
+        self.lines.lock().unwrap().iter().any(|line| line.contains(text))
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voicechat-transfer-{}-{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Sender and receiver over loopback; the receiver saves into `dir/downloads`
+fn pair(dir: &Path) -> (End, End) {
+    let sender_key = Identity::load_or_create_at(&dir.join("sender.key")).public_key_bytes();
+    let receiver_key = Identity::load_or_create_at(&dir.join("receiver.key")).public_key_bytes();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let host = thread::spawn(move || {
+        let (tcp, _) = listener.accept().unwrap();
+        handshake::run_as_host(tcp, receiver_key, sender_key).unwrap()
+    });
+    let mut sender = handshake::run(TcpStream::connect(addr).unwrap(), sender_key, receiver_key).unwrap();
+    let mut receiver = host.join().unwrap();
+    sender.set_read_timeout(Duration::from_millis(20));
+    receiver.set_read_timeout(Duration::from_millis(20));
+
+    (End::new(sender, dir.join("sent")), End::new(receiver, dir.join("downloads")))
+}
+
+/// A file of `len` bytes that differ from chunk to chunk
+fn write_file(dir: &Path, name: &str, len: usize) -> (PathBuf, Vec<u8>) {
+    let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    let path = dir.join(name);
+    fs::write(&path, &data).unwrap();
+    (path, data)
+}
+
+/// Offer `path`, accept it on the receiver and return the offer
+fn offer_and_accept(sender: &End, receiver: &End, path: PathBuf) {
+    transfer::offer_file(&sender.transfers, &sender.stream, path).unwrap();
+    let offer = receiver.recv();
+    assert!(receiver.handle(offer).unwrap().contains("/accept"));
+    let (accept, line) = receiver.transfers.lock().unwrap().accept("").unwrap();
+    assert!(line.contains("Receiving"));
+    receiver.send(&accept);
+    sender.handle_until("accepted");
+}
+
+#[test]
+fn test_file_arrives_verified_under_a_free_name() {
+    let dir = temp_dir("whole");
+    let (sender, receiver) = pair(&dir);
+    let (path, data) = write_file(&dir, "notes.txt", 3 * CHUNK_SIZE + 100);
+    // Taken already, so the download gets a new name
+    fs::create_dir_all(dir.join("downloads")).unwrap();
+    fs::write(dir.join("downloads/notes.txt"), b"older").unwrap();
+
+    offer_and_accept(&sender, &receiver, path);
+    let first = receiver.recv();
+    let duplicate = ChatMessage::from_bytes(&first.to_bytes().unwrap()).unwrap();
+    receiver.handle(first);
+    // Chunks at an offset already written are ignored
+    assert_eq!(receiver.handle(duplicate), None);
+    let line = receiver.handle_until("Received");
+    assert!(line.contains("SHA-256 verified"));
+    assert!(sender.handle_until("delivered").contains("verified"));
+
+    let saved = dir.join("downloads/notes (1).txt");
+    assert_eq!(fs::read(&saved).unwrap(), data);
+    assert_eq!(fs::read(dir.join("downloads/notes.txt")).unwrap(), b"older");
+    let list = receiver.transfers.lock().unwrap().list();
+    assert!(list[0].contains("done") && list[0].contains("notes (1).txt"), "{:?}", list);
+    assert!(sender.printed("Sent notes.txt"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_interrupted_download_resumes_at_its_offset() {
+    let dir = temp_dir("resume");
+    let (sender, receiver) = pair(&dir);
+    let chunks = 6;
+    let (path, data) = write_file(&dir, "big.bin", chunks * CHUNK_SIZE);
+
+    offer_and_accept(&sender, &receiver, path);
+    // Two chunks make it, the rest is lost with the connection
+    for _ in 0..2 {
+        let chunk = receiver.recv();
+        receiver.handle(chunk);
+    }
+    for _ in 2..chunks {
+        receiver.recv();
+    }
+    assert!(receiver.transfers.lock().unwrap().list()[0].contains("in progress"));
+
+    let requests = receiver.transfers.lock().unwrap().resume_requests();
+    assert_eq!(requests.len(), 1);
+    match &requests[0] {
+        ChatMessage::FileAccept(accept) => assert_eq!(accept.offset, 2 * CHUNK_SIZE as u64),
+        other => panic!("expected an accept, got {:?}", other),
+    }
+    receiver.send(&requests[0]);
+    assert!(sender.handle_until("Resuming").contains("big.bin"));
+
+    receiver.handle_until("Received");
+    assert_eq!(fs::read(dir.join("downloads/big.bin")).unwrap(), data);
+    assert!(receiver.transfers.lock().unwrap().resume_requests().is_empty());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_corrupted_chunk_fails_the_hash_check() {
+    let dir = temp_dir("corrupt");
+    let (sender, receiver) = pair(&dir);
+    let (path, _) = write_file(&dir, "photo.jpg", 2 * CHUNK_SIZE);
+
+    offer_and_accept(&sender, &receiver, path);
+    let mut chunk = receiver.recv();
+    if let ChatMessage::FileChunk(chunk) = &mut chunk {
+        chunk.data[10] ^= 0xff;
+    }
+    receiver.handle(chunk);
+    let line = receiver.handle_until("failed");
+    assert!(line.contains("SHA-256 mismatch"));
+    assert!(sender.handle_until("could not verify").contains("photo.jpg"));
+
+    // Nothing is kept, not even the partial file
+    let left: Vec<_> = fs::read_dir(dir.join("downloads")).unwrap().collect();
+    assert!(left.is_empty(), "{:?}", left);
+    assert!(receiver.transfers.lock().unwrap().list()[0].contains("failed"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_accept_needs_a_matching_offer() {
+    let dir = temp_dir("accept");
+    let (_, receiver) = pair(&dir);
+    let mut transfers = receiver.transfers.lock().unwrap();
+    assert_eq!(transfers.accept("").unwrap_err(), "No pending file offers");
+    assert!(transfers.reject("abcd").unwrap_err().contains("abcd"));
+    drop(transfers);
+    fs::remove_dir_all(&dir).ok();
+}
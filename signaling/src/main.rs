@@ -1,6 +1,47 @@
 mod server;
 
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::process;
+use std::sync::Mutex;
+
+use tracing_subscriber::EnvFilter;
+
+/// Log filter when RUST_LOG is not set
+const DEFAULT_LOG: &str = "info";
+
 fn main(){
+    if let Err(e)=init_logging(){
+        eprintln!("{}",e);
+        process::exit(1);
+    }
     server::start_server("0.0.0.0:9000");
 
 }
+
+/// Log to stderr, or to SIGNALING_LOG_FILE if set. RUST_LOG picks the
+/// levels and SIGNALING_LOG_FORMAT=json switches to one JSON object per line.
+fn init_logging() -> Result<(), String> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG));
+    let json = std::env::var("SIGNALING_LOG_FORMAT").is_ok_and(|f| f == "json");
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let file = match std::env::var_os("SIGNALING_LOG_FILE") {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Cannot open log file {}: {}", path.to_string_lossy(), e))?,
+        ),
+        None => None,
+    };
+
+    match (file, json) {
+        (Some(file), true) => builder.json().with_writer(Mutex::new(file)).init(),
+        (Some(file), false) => builder.with_ansi(false).with_writer(Mutex::new(file)).init(),
+        (None, true) => builder.json().with_writer(std::io::stderr).init(),
+        (None, false) => builder.with_ansi(std::io::stderr().is_terminal()).with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
@@ -1,13 +1,22 @@
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Write, Read};
-use std::net::{TcpListener, TcpStream};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
 use base64::Engine;
+use tracing::{debug, info, trace, warn};
+
+/// How long a RESUME waits for the other peer, as long as the client
+/// waits for an answer
+const RESUME_WAIT: Duration = Duration::from_secs(30);
 
 struct Peer{
     stream:TcpStream,
     pubkey:Vec<u8>,
+    addr:SocketAddr,
+    /// A waiting RESUME, woken once it is paired or replaced
+    waiter:Option<Thread>,
 }
 
 type Rooms = Arc<Mutex<HashMap<String, Vec<Peer>>>>;
@@ -15,7 +24,7 @@ type Rooms = Arc<Mutex<HashMap<String, Vec<Peer>>>>;
 
 pub fn start_server(addr: &str) {
     let listener = TcpListener::bind(addr).expect("Failed to bind signaling server");
-    println!("Signaling server listening on {}", addr);
+    info!(%addr, "signaling server listening");
 
     let rooms: Rooms = Arc::new(Mutex::new(HashMap::new()));
 
@@ -28,7 +37,7 @@ pub fn start_server(addr: &str) {
                 });
             }
             Err(e) => {
-                eprintln!("Connection failed: {}", e);
+                warn!(error = %e, "connection failed");
             }
         }
     }
@@ -46,7 +55,7 @@ fn handle_client(stream: TcpStream, rooms: Rooms) {
         return;
     }
 
-    println!("Received from {}: {:?}", peer, line);
+    debug!(%peer, request = line.trim_end(), "received request");
 
     let parts: Vec<&str> = line.trim().split_whitespace().collect();
     if parts.len() < 2 {
@@ -77,9 +86,11 @@ fn handle_client(stream: TcpStream, rooms: Rooms) {
             room.push(Peer {
                 stream: writer.try_clone().unwrap(),
                 pubkey,
+                addr: peer,
+                waiter: None,
             });
             
-            println!("Room {} created by {}", code, peer);
+            info!(room = %code, %peer, "room created");
             
             // Don't send ROOM_CREATED yet - wait for second peer
             // Block to keep connection alive
@@ -121,77 +132,186 @@ fn handle_client(stream: TcpStream, rooms: Rooms) {
             room.push(Peer{
                 stream:writer.try_clone().unwrap(),
                 pubkey,
+                addr:peer,
+                waiter:None,
             });
 
-            println!("Room {} joined by {}",code,peer);
+            info!(room = %code, %peer, "room joined");
             let _=writeln!(writer, "ROOM_JOINED");
 
             //if second peer joined -> exchange pubkeys and start relaying
             if room.len()==2{
-                let pk1=base64::engine::general_purpose::STANDARD.encode(&room[0].pubkey);
-                let pk2=base64::engine::general_purpose::STANDARD.encode(&room[1].pubkey);
-
-                let _=writeln!(room[0].stream, "PEER_PUBKEY {} HOST",pk2);
-                let _=writeln!(room[1].stream, "PEER_PUBKEY {} CLIENT",pk1);
-
                 // Take ownership of streams from room
                 let peer1 = room.remove(0);
                 let peer2 = room.remove(0);
                 drop(rooms);
 
-                // Clone streams for relaying - each direction needs independent clones
-                let stream1_read = peer1.stream.try_clone().unwrap();
-                let stream1_write = peer1.stream.try_clone().unwrap();
-                let stream2_read = peer2.stream.try_clone().unwrap();
-                let stream2_write = peer2.stream.try_clone().unwrap();
-                
-                // Start relay threads
-                thread::spawn(move || relay_traffic(stream1_read, stream2_write, "1->2"));
-                thread::spawn(move || relay_traffic(stream2_read, stream1_write, "2->1"));
-                
-                // Keep original streams alive
-                thread::spawn(move || {
-                    let _keep = (peer1.stream, peer2.stream);
-                    std::thread::park();
-                });
-                
+                start_relay(peer1, peer2);
                 return; // Don't drop writer
             }
         }
 
+        // Both peers of a dropped session come back with RESUME and
+        // are paired again; the resumption handshake proves they
+        // belong together, the server only matches room codes
+        "RESUME"=>{
+            if parts.len()<3{
+                let _=writeln!(writer, "ERROR");
+                return;
+            }
+            let code=parts[1].to_string();
+            let pubkey_b64=parts[2];
+
+            let pubkey=match base64::engine::general_purpose::STANDARD.decode(pubkey_b64){
+                Ok(pk)=>pk,
+                Err(_)=>{
+                    let _=writeln!(writer, "ERROR");
+                    return;
+                }
+            };
+
+            // Kept apart from CREATE/JOIN rooms so a JOIN is never
+            // paired with a peer that is waiting to resume
+            let key=resume_key(&code);
+            let mut waiting=rooms.lock().unwrap();
+            let room=waiting.entry(key.clone()).or_default();
+
+            // A stale earlier attempt by the same peer is replaced and
+            // its waiting thread let go
+            for stale in room.iter().filter(|p| p.pubkey==pubkey){
+                if let Some(waiter)=&stale.waiter{
+                    waiter.unpark();
+                }
+            }
+            room.retain(|p| p.pubkey!=pubkey);
+            room.push(Peer{
+                stream:writer.try_clone().unwrap(),
+                pubkey,
+                addr:peer,
+                waiter:Some(thread::current()),
+            });
+
+            info!(room = %code, %peer, "room resume requested");
+
+            if room.len()==2{
+                let peer1 = room.remove(0);
+                let peer2 = room.remove(0);
+                waiting.remove(&key);
+                drop(waiting);
+
+                start_relay(peer1, peer2);
+                return;
+            }
+            drop(waiting);
+
+            // Wait for the other peer until the deadline; once paired
+            // or replaced our entry is gone
+            let deadline=Instant::now()+RESUME_WAIT;
+            loop{
+                let left=deadline.saturating_duration_since(Instant::now());
+                if left.is_zero(){
+                    break;
+                }
+                thread::park_timeout(left);
+                if !take_waiter(&rooms, &key, peer, false){
+                    return;
+                }
+            }
+            if take_waiter(&rooms, &key, peer, true){
+                info!(room = %code, %peer, "resume timed out");
+                let _=writeln!(writer, "RESUME_TIMEOUT");
+            }
+        }
+
         _ => {
             let _ = writeln!(writer, "ERROR");
         }
     }
 }
 
+fn resume_key(code: &str) -> String {
+    format!("resume:{}", code)
+}
+
+// Whether the peer at `addr` is still waiting in `key`; with `remove`
+// it stops waiting, and the room goes once it is empty
+fn take_waiter(rooms: &Rooms, key: &str, addr: SocketAddr, remove: bool) -> bool {
+    let mut rooms = rooms.lock().unwrap();
+    let Some(room) = rooms.get_mut(key) else {
+        return false;
+    };
+    let Some(i) = room.iter().position(|p| p.addr == addr) else {
+        return false;
+    };
+    if remove {
+        room.remove(i);
+        if room.is_empty() {
+            rooms.remove(key);
+        }
+    }
+    true
+}
+
+// Tell both peers about each other, then relay bytes between them.
+// The first peer takes the HOST role.
+fn start_relay(peer1: Peer, peer2: Peer) {
+    let pk1=base64::engine::general_purpose::STANDARD.encode(&peer1.pubkey);
+    let pk2=base64::engine::general_purpose::STANDARD.encode(&peer2.pubkey);
+
+    let mut stream1 = peer1.stream;
+    let mut stream2 = peer2.stream;
+    let _=writeln!(stream1, "PEER_PUBKEY {} HOST",pk2);
+    let _=writeln!(stream2, "PEER_PUBKEY {} CLIENT",pk1);
+    for waiter in [&peer1.waiter, &peer2.waiter].into_iter().flatten(){
+        waiter.unpark();
+    }
+
+    // Clone streams for relaying - each direction needs independent clones
+    let stream1_read = stream1.try_clone().unwrap();
+    let stream1_write = stream1.try_clone().unwrap();
+    let stream2_read = stream2.try_clone().unwrap();
+    let stream2_write = stream2.try_clone().unwrap();
+
+    // Start relay threads
+    thread::spawn(move || relay_traffic(stream1_read, stream2_write, "1->2"));
+    thread::spawn(move || relay_traffic(stream2_read, stream1_write, "2->1"));
+
+    // Keep original streams alive
+    thread::spawn(move || {
+        let _keep = (stream1, stream2);
+        std::thread::park();
+    });
+}
+
 fn relay_traffic(mut from: TcpStream, mut to: TcpStream, label: &str) {
     let mut buf = [0u8; 8192];
-    eprintln!("[RELAY {}] Starting relay loop", label);
+    debug!(direction = label, "relay started");
     loop {
         match from.read(&mut buf) {
             Ok(0) => {
-                eprintln!("[RELAY {}] Connection closed", label);
+                debug!(direction = label, "relay connection closed");
+                // Pass the half-close on so the other peer sees EOF too
+                let _ = to.shutdown(Shutdown::Write);
                 break;
             }
             Ok(n) => {
-                eprintln!("[RELAY {}] Read {} bytes, forwarding...", label, n);
                 if let Err(e) = to.write_all(&buf[..n]) {
-                    eprintln!("[RELAY {}] Failed to write: {}", label, e);
+                    debug!(direction = label, error = %e, "relay write failed");
                     break;
                 }
                 if let Err(e) = to.flush() {
-                    eprintln!("[RELAY {}] Failed to flush: {}", label, e);
+                    debug!(direction = label, error = %e, "relay flush failed");
                     break;
                 }
-                eprintln!("[RELAY {}] Successfully forwarded {} bytes", label, n);
+                trace!(direction = label, bytes = n, "relayed");
             }
             Err(e) => {
-                eprintln!("[RELAY {}] Read error: {}", label, e);
+                debug!(direction = label, error = %e, "relay read failed");
+                let _ = to.shutdown(Shutdown::Both);
                 break;
             }
         }
     }
-    eprintln!("[RELAY {}] Exiting relay loop", label);
+    debug!(direction = label, "relay stopped");
 }
 
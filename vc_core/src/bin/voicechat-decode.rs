@@ -0,0 +1,285 @@
+//! Decrypt captured SecureStream traffic with a VOICECHAT_KEYLOGFILE.
+//!
+//! Usage:
+//!   voicechat-decode --keylog <file> --stream <file>
+//!   voicechat-decode --keylog <file> --pcap <file> [--port <port>]
+//!
+//! A stream file holds the raw bytes of one TCP direction (e.g. saved
+//! from Wireshark's "Follow TCP Stream" as raw). A pcap is split into
+//! TCP flows and every flow is decoded separately.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+
+use ed25519_dalek::SigningKey;
+use vc_core::crypto::keylog::{self, KeyLogEntry};
+use vc_core::net::frame::Frame;
+use vc_core::protocol::chat::ChatMessage;
+use vc_core::state::secure_session::{SecureSession, SessionRole};
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let opt = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+            .cloned()
+    };
+
+    let Some(keylog_path) = opt("--keylog") else {
+        print_usage();
+        return Ok(());
+    };
+    let keys = keylog::parse_keylog(&fs::read_to_string(&keylog_path)?);
+    if keys.is_empty() {
+        anyhow::bail!("no SESSION_KEY lines in {}", keylog_path);
+    }
+    println!("Loaded {} session key(s) from {}", keys.len(), keylog_path);
+
+    if let Some(path) = opt("--stream") {
+        decode_stream(&fs::read(&path)?, &keys);
+    } else if let Some(path) = opt("--pcap") {
+        let port = opt("--port").map(|p| p.parse::<u16>()).transpose()?;
+        for (flow, bytes) in read_pcap_flows(&fs::read(&path)?, port)? {
+            println!("\n=== {} ({} bytes) ===", flow, bytes.len());
+            decode_stream(&bytes, &keys);
+        }
+    } else {
+        print_usage();
+    }
+    Ok(())
+}
+
+fn print_usage() {
+    println!("Usage:");
+    println!("  voicechat-decode --keylog <file> --stream <file>");
+    println!("  voicechat-decode --keylog <file> --pcap <file> [--port <port>]");
+}
+
+fn new_session(key: &[u8; 32]) -> SecureSession {
+    // Decryption never looks at the peer identity
+    let placeholder = SigningKey::from_bytes(&[0u8; 32]).verifying_key();
+    SecureSession::new(SessionRole::Client, *key, placeholder)
+}
+
+/// Walk LEN || CIPHERTEXT frames. Until a frame decrypts, the decoder
+/// slides forward byte by byte to skip handshake traffic.
+fn decode_stream(bytes: &[u8], keys: &[KeyLogEntry]) {
+    let mut session: Option<SecureSession> = None;
+    let mut offset = 0;
+    let mut frames = 0;
+
+    while offset + 2 <= bytes.len() {
+        let len = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]) as usize;
+        let end = offset + 2 + len;
+        if len < 8 || end > bytes.len() {
+            offset += 1;
+            continue;
+        }
+        let record = &bytes[offset + 2..end];
+
+        let plaintext = match session.as_mut() {
+            Some(s) => s.decrypt(record).ok(),
+            None => keys.iter().find_map(|entry| {
+                let mut s = new_session(&entry.key);
+                let plaintext = s.decrypt(record).ok()?;
+                println!(
+                    "[offset {}] synced on session {}",
+                    offset,
+                    keylog::to_hex(&entry.session_id)
+                );
+                session = Some(s);
+                Some(plaintext)
+            }),
+        };
+
+        let Some(plaintext) = plaintext else {
+            if session.is_some() {
+                println!("[offset {}] frame does not decrypt, resyncing", offset);
+            }
+            offset += 1;
+            continue;
+        };
+
+        let ctr = u64::from_be_bytes(record[..8].try_into().unwrap());
+        print_frame(frames, ctr, &plaintext);
+        frames += 1;
+        offset = end;
+    }
+
+    if session.is_none() {
+        println!("No frame could be decrypted with the given key log");
+    }
+}
+
+fn print_frame(index: usize, ctr: u64, plaintext: &[u8]) {
+    match Frame::decode(plaintext) {
+        Some(Frame::Data(data)) => match ChatMessage::from_bytes(&data) {
+            Ok(msg) => println!("#{} ctr={} DATA {:?}", index, ctr, msg),
+            Err(_) => println!("#{} ctr={} DATA {} bytes (not a ChatMessage)", index, ctr, data.len()),
+        },
+        Some(Frame::Close(reason)) => println!("#{} ctr={} CLOSE ({})", index, ctr, reason),
+        Some(Frame::Ping(id)) => println!("#{} ctr={} PING id={}", index, ctr, id),
+        Some(Frame::Pong(id)) => println!("#{} ctr={} PONG id={}", index, ctr, id),
+        Some(Frame::Cover) => println!("#{} ctr={} COVER", index, ctr),
+        None => println!("#{} ctr={} unknown frame {} bytes", index, ctr, plaintext.len()),
+    }
+}
+
+/* ================= PCAP ================= */
+
+#[derive(Default)]
+struct Flow {
+    // TCP payloads keyed by sequence number, reassembled in order
+    segments: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Flow {
+    fn reassemble(self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut next: Option<u32> = None;
+        for (seq, data) in self.segments {
+            let start = match next {
+                None => 0,
+                Some(n) if seq >= n => {
+                    if seq > n {
+                        eprintln!("[PCAP] gap of {} bytes in flow", seq - n);
+                    }
+                    0
+                }
+                // Overlapping retransmission: keep only the new tail
+                Some(n) => (n - seq) as usize,
+            };
+            if start < data.len() {
+                out.extend_from_slice(&data[start..]);
+                next = Some(seq.wrapping_add(data.len() as u32));
+            }
+        }
+        out
+    }
+}
+
+/// Split a classic (non-pcapng) capture into TCP flows, one per direction
+fn read_pcap_flows(buf: &[u8], port: Option<u16>) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    if buf.len() < 24 {
+        anyhow::bail!("file too short for a pcap header");
+    }
+    let magic = u32::from_le_bytes(buf[0..4].try_into()?);
+    let le = match magic {
+        0xa1b2c3d4 | 0xa1b23c4d => true,
+        0xd4c3b2a1 | 0x4d3cb2a1 => false,
+        0x0a0d0d0a => anyhow::bail!("pcapng is not supported, save the capture as pcap"),
+        _ => anyhow::bail!("not a pcap file"),
+    };
+    let rd32 = |b: &[u8]| {
+        let a: [u8; 4] = b[..4].try_into().unwrap();
+        if le { u32::from_le_bytes(a) } else { u32::from_be_bytes(a) }
+    };
+    let linktype = rd32(&buf[20..24]);
+
+    let mut flows: HashMap<String, Flow> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+    let mut off = 24;
+
+    while off + 16 <= buf.len() {
+        let incl = rd32(&buf[off + 8..off + 12]) as usize;
+        let start = off + 16;
+        off = start + incl;
+        if off > buf.len() {
+            break;
+        }
+
+        let Some(ip) = strip_link_layer(&buf[start..off], linktype) else {
+            continue;
+        };
+        let Some((src, dst, seq, payload)) = parse_tcp(ip) else {
+            continue;
+        };
+        if payload.is_empty() {
+            continue;
+        }
+        if let Some(p) = port
+            && src.1 != p
+            && dst.1 != p
+        {
+            continue;
+        }
+
+        let name = format!("{}:{} -> {}:{}", src.0, src.1, dst.0, dst.1);
+        if !flows.contains_key(&name) {
+            order.push(name.clone());
+        }
+        flows
+            .entry(name)
+            .or_default()
+            .segments
+            .entry(seq)
+            .or_insert_with(|| payload.to_vec());
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|name| {
+            let flow = flows.remove(&name).unwrap_or_default();
+            (name, flow.reassemble())
+        })
+        .collect())
+}
+
+fn strip_link_layer(frame: &[u8], linktype: u32) -> Option<&[u8]> {
+    match linktype {
+        // Ethernet, skipping one optional VLAN tag
+        1 => {
+            let ethertype = u16::from_be_bytes(frame.get(12..14)?.try_into().ok()?);
+            if ethertype == 0x8100 {
+                frame.get(18..)
+            } else {
+                frame.get(14..)
+            }
+        }
+        // BSD loopback / null
+        0 => frame.get(4..),
+        // Raw IP
+        101 => Some(frame),
+        // Linux cooked capture
+        113 => frame.get(16..),
+        _ => None,
+    }
+}
+
+type Endpoint = (String, u16);
+
+fn parse_tcp(ip: &[u8]) -> Option<(Endpoint, Endpoint, u32, &[u8])> {
+    let version = ip.first()? >> 4;
+    let (src, dst, tcp) = match version {
+        4 => {
+            let ihl = ((ip[0] & 0x0f) as usize) * 4;
+            if *ip.get(9)? != 6 {
+                return None;
+            }
+            let total = u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?) as usize;
+            let src = std::net::Ipv4Addr::from(<[u8; 4]>::try_from(ip.get(12..16)?).ok()?);
+            let dst = std::net::Ipv4Addr::from(<[u8; 4]>::try_from(ip.get(16..20)?).ok()?);
+            (src.to_string(), dst.to_string(), ip.get(ihl..total.min(ip.len()))?)
+        }
+        6 => {
+            // Extension headers are not followed
+            if *ip.get(6)? != 6 {
+                return None;
+            }
+            let plen = u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?) as usize;
+            let src = std::net::Ipv6Addr::from(<[u8; 16]>::try_from(ip.get(8..24)?).ok()?);
+            let dst = std::net::Ipv6Addr::from(<[u8; 16]>::try_from(ip.get(24..40)?).ok()?);
+            (src.to_string(), dst.to_string(), ip.get(40..(40 + plen).min(ip.len()))?)
+        }
+        _ => return None,
+    };
+
+    let sport = u16::from_be_bytes(tcp.get(0..2)?.try_into().ok()?);
+    let dport = u16::from_be_bytes(tcp.get(2..4)?.try_into().ok()?);
+    let seq = u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?);
+    let data_off = ((tcp.get(12)? >> 4) as usize) * 4;
+
+    Some(((src, sport), (dst, dport), seq, tcp.get(data_off..)?))
+}
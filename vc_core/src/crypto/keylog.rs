@@ -0,0 +1,119 @@
+//! Opt-in session key logging for debugging, in the spirit of SSLKEYLOGFILE.
+//!
+//! Keys are only written when vc_core is built with the `keylog` feature
+//! AND `VOICECHAT_KEYLOGFILE` points at a file. Anyone holding that file
+//! can read every frame of the logged sessions.
+
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+pub const KEYLOG_ENV: &str = "VOICECHAT_KEYLOGFILE";
+
+/// One `SESSION_KEY <session id> <key>` line of a key log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyLogEntry {
+    pub session_id: [u8; 8],
+    pub key: [u8; 32],
+}
+
+/// Public identifier of a session key, safe to print in logs
+pub fn session_id(session_key: &[u8; 32]) -> [u8; 8] {
+    let digest = Sha256::new()
+        .chain_update(b"voicechat session id")
+        .chain_update(session_key)
+        .finalize();
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&digest[..8]);
+    id
+}
+
+fn keylog_path() -> Option<PathBuf> {
+    std::env::var_os(KEYLOG_ENV)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Print a loud warning when key logging is requested.
+/// Call once at startup, before any session is created.
+pub fn warn_if_enabled() {
+    let Some(path) = keylog_path() else {
+        return;
+    };
+
+    if cfg!(feature = "keylog") {
+        eprintln!("==================================================================");
+        eprintln!("  WARNING: {} is set", KEYLOG_ENV);
+        eprintln!("  Session keys are being written to {}", path.display());
+        eprintln!("  Anyone with this file can decrypt your chat. Debugging only!");
+        eprintln!("==================================================================");
+    } else {
+        tracing::warn!("{} is set but key logging was not compiled in (enable the `keylog` feature)", KEYLOG_ENV);
+    }
+}
+
+/// Append a session key to the key log, if enabled
+#[cfg(feature = "keylog")]
+pub fn log_session_key(session_key: &[u8; 32]) {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    let Some(path) = keylog_path() else {
+        return;
+    };
+
+    let line = format_entry(&KeyLogEntry {
+        session_id: session_id(session_key),
+        key: *session_key,
+    });
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    // Session keys decrypt captured traffic, keep them from other users
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&path).and_then(|mut f| writeln!(f, "{}", line));
+
+    if let Err(e) = written {
+        tracing::error!(path = %path.display(), error = %e, "could not write the key log");
+    }
+}
+
+/// Key logging is compiled out
+#[cfg(not(feature = "keylog"))]
+pub fn log_session_key(_session_key: &[u8; 32]) {}
+
+pub fn format_entry(entry: &KeyLogEntry) -> String {
+    format!("SESSION_KEY {} {}", to_hex(&entry.session_id), to_hex(&entry.key))
+}
+
+/// Parse a key log, skipping blank lines, `#` comments and unknown lines
+pub fn parse_keylog(text: &str) -> Vec<KeyLogEntry> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next()? != "SESSION_KEY" {
+                return None;
+            }
+            let session_id = from_hex(parts.next()?)?.try_into().ok()?;
+            let key = from_hex(parts.next()?)?.try_into().ok()?;
+            Some(KeyLogEntry { session_id, key })
+        })
+        .collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
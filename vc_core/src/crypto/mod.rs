@@ -1,3 +1,5 @@
+pub mod keylog;
+pub mod storage;
 use rand::rngs::OsRng;
 use rand::RngCore;
 use hkdf::Hkdf;
@@ -0,0 +1,72 @@
+//! Encryption for files kept on disk, keyed from the identity secret.
+//!
+//! Each purpose ("history", ...) gets its own key, so a record from one
+//! store never opens in another.
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+const NONCE_LEN: usize = 12;
+
+pub struct StorageCipher {
+    cipher: ChaCha20Poly1305,
+    name_key: [u8; 32],
+}
+
+impl StorageCipher {
+    pub fn from_identity(identity_secret: &[u8; 32], purpose: &str) -> Self {
+        let hk = Hkdf::<Sha256>::new(Some(b"voicechat storage"), identity_secret);
+        let mut key = [0u8; 32];
+        let mut name_key = [0u8; 32];
+        hk.expand(format!("{} key", purpose).as_bytes(), &mut key)
+            .expect("HKDF expand failed");
+        hk.expand(format!("{} names", purpose).as_bytes(), &mut name_key)
+            .expect("HKDF expand failed");
+
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            name_key,
+        }
+    }
+
+    /// NONCE || CIPHERTEXT with a random nonce; `aad` is authenticated
+    /// but not stored
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .expect("encryption failure");
+
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ciphertext);
+        out
+    }
+
+    pub fn open(&self, record: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        if record.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = record.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .ok()
+    }
+
+    /// File name for a store scope that does not reveal the scope
+    /// (a peer key or room code) to someone browsing the directory
+    pub fn file_name(&self, scope: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.name_key)
+            .chain_update(scope.as_bytes())
+            .finalize();
+        digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+/// Inner frame carried inside every encrypted SecureStream record.
+///
+/// Layout (before encryption):
+/// [u8 kind]
+/// [u8 body...]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameKind {
+    Data = 0,
+    Close = 1,
+    Ping = 2,
+    Pong = 3,
+    Cover = 4,
+}
+
+impl FrameKind {
+    pub fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(FrameKind::Data),
+            1 => Some(FrameKind::Close),
+            2 => Some(FrameKind::Ping),
+            3 => Some(FrameKind::Pong),
+            4 => Some(FrameKind::Cover),
+            _ => None,
+        }
+    }
+}
+
+/// Why a session was closed, sent inside an authenticated close frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    UserLeft,
+    RekeyFailure,
+    ProtocolError,
+    Unknown(u8),
+}
+
+impl CloseReason {
+    pub fn code(self) -> u8 {
+        match self {
+            CloseReason::UserLeft => 0,
+            CloseReason::RekeyFailure => 1,
+            CloseReason::ProtocolError => 2,
+            CloseReason::Unknown(c) => c,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => CloseReason::UserLeft,
+            1 => CloseReason::RekeyFailure,
+            2 => CloseReason::ProtocolError,
+            c => CloseReason::Unknown(c),
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseReason::UserLeft => write!(f, "user left"),
+            CloseReason::RekeyFailure => write!(f, "rekey failure"),
+            CloseReason::ProtocolError => write!(f, "protocol error"),
+            CloseReason::Unknown(c) => write!(f, "unknown reason {}", c),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    Close(CloseReason),
+    /// Keepalive probe carrying a ping id
+    Ping(u64),
+    /// Answer to a ping, echoing its id
+    Pong(u64),
+    /// Dummy frame sent while idle, dropped by the receiver
+    Cover,
+}
+
+impl Frame {
+    pub fn kind(&self) -> FrameKind {
+        match self {
+            Frame::Data(_) => FrameKind::Data,
+            Frame::Close(_) => FrameKind::Close,
+            Frame::Ping(_) => FrameKind::Ping,
+            Frame::Pong(_) => FrameKind::Pong,
+            Frame::Cover => FrameKind::Cover,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![self.kind() as u8];
+        match self {
+            Frame::Data(data) => buf.extend_from_slice(data),
+            Frame::Close(reason) => buf.push(reason.code()),
+            Frame::Ping(id) | Frame::Pong(id) => buf.extend_from_slice(&id.to_be_bytes()),
+            Frame::Cover => {}
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let (&kind, body) = buf.split_first()?;
+
+        match FrameKind::from_u8(kind)? {
+            FrameKind::Data => Some(Frame::Data(body.to_vec())),
+            FrameKind::Close => {
+                let &code = body.first()?;
+                Some(Frame::Close(CloseReason::from_code(code)))
+            }
+            FrameKind::Ping => Some(Frame::Ping(u64::from_be_bytes(body.try_into().ok()?))),
+            FrameKind::Pong => Some(Frame::Pong(u64::from_be_bytes(body.try_into().ok()?))),
+            FrameKind::Cover => Some(Frame::Cover),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+/// Heartbeat settings for a SecureStream
+#[derive(Debug, Clone, Copy)]
+pub struct KeepaliveConfig {
+    /// How often to send a ping
+    pub interval: Duration,
+    /// Give up on the peer after hearing nothing for this long
+    pub dead_peer_timeout: Duration,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            dead_peer_timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+/// Round-trip statistics from answered pings
+#[derive(Debug, Clone, Copy)]
+pub struct RttStats {
+    pub latest: Duration,
+    pub min: Duration,
+    /// Smoothed RTT (RFC 6298 SRTT)
+    pub smoothed: Duration,
+    /// Mean RTT deviation (RFC 6298 RTTVAR)
+    pub jitter: Duration,
+    pub samples: u32,
+}
+
+impl RttStats {
+    fn first(rtt: Duration) -> Self {
+        Self {
+            latest: rtt,
+            min: rtt,
+            smoothed: rtt,
+            jitter: rtt / 2,
+            samples: 1,
+        }
+    }
+
+    fn update(&mut self, rtt: Duration) {
+        let deviation = self.smoothed.abs_diff(rtt);
+        // RTTVAR = 3/4 RTTVAR + 1/4 |SRTT - R|, SRTT = 7/8 SRTT + 1/8 R
+        self.jitter = (self.jitter * 3 + deviation) / 4;
+        self.smoothed = (self.smoothed * 7 + rtt) / 8;
+        self.latest = rtt;
+        self.min = self.min.min(rtt);
+        self.samples += 1;
+    }
+}
+
+/// Ping bookkeeping kept by SecureStream
+pub struct Keepalive {
+    config: KeepaliveConfig,
+    next_ping_id: u64,
+    outstanding: Option<(u64, Instant)>,
+    last_ping: Instant,
+    last_heard: Instant,
+    stats: Option<RttStats>,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            next_ping_id: 0,
+            outstanding: None,
+            last_ping: now,
+            last_heard: now,
+            stats: None,
+        }
+    }
+
+    pub fn config(&self) -> KeepaliveConfig {
+        self.config
+    }
+
+    /// Any authenticated frame proves the peer is alive
+    pub fn heard(&mut self) {
+        self.last_heard = Instant::now();
+    }
+
+    pub fn peer_dead(&self) -> bool {
+        self.last_heard.elapsed() >= self.config.dead_peer_timeout
+    }
+
+    /// Id of the next ping to send, if one is due
+    pub fn ping_due(&mut self) -> Option<u64> {
+        if self.last_ping.elapsed() < self.config.interval {
+            return None;
+        }
+        let id = self.next_ping_id;
+        self.next_ping_id += 1;
+        self.last_ping = Instant::now();
+        // An unanswered older ping is simply forgotten
+        self.outstanding = Some((id, self.last_ping));
+        Some(id)
+    }
+
+    pub fn pong_received(&mut self, id: u64) {
+        let Some((expected, sent_at)) = self.outstanding else {
+            return;
+        };
+        if expected != id {
+            return;
+        }
+        self.outstanding = None;
+
+        let rtt = sent_at.elapsed();
+        match self.stats.as_mut() {
+            Some(stats) => stats.update(rtt),
+            None => self.stats = Some(RttStats::first(rtt)),
+        }
+    }
+
+    pub fn stats(&self) -> Option<RttStats> {
+        self.stats
+    }
+}
@@ -1,3 +1,5 @@
 pub mod client_handshake;
 pub mod host_handshek;
 pub mod secure_stream;
+pub mod frame;
+pub mod keepalive;
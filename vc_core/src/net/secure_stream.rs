@@ -1,17 +1,47 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
 
+use ed25519_dalek::VerifyingKey;
+use tracing::{debug, trace, warn};
+
+use crate::net::frame::{CloseReason, Frame};
+use crate::net::keepalive::{Keepalive, KeepaliveConfig, RttStats};
+use crate::protocol::resume::{Resumed, ResumptionTicket};
+use crate::state::padding::{PaddingPolicy, MAX_PADDED_LEN};
 use crate::state::secure_session::{SecureSession, SecureSessionError};
 #[derive(Debug)]
 pub enum SecureStreamError {
     Io(std::io::Error),
     Crypto(SecureSessionError),
     FrameTooLarge,
+    MalformedFrame,
+    /// TCP connection ended without a close frame
     UnexpectedEof,
+    /// Session ended by an authenticated close frame
+    Closed(CloseReason),
+    /// Nothing heard from the peer within the keepalive timeout
+    PeerTimeout,
+}
+
+impl SecureStreamError {
+    /// True when a read simply timed out and the stream is still usable
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            SecureStreamError::Io(e) => {
+                matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl From<std::io::Error> for SecureStreamError {
     fn from(e: std::io::Error) -> Self {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            return SecureStreamError::UnexpectedEof;
+        }
         SecureStreamError::Io(e)
     }
 }
@@ -22,73 +52,317 @@ impl From<SecureSessionError> for SecureStreamError {
     }
 }
 
+/// Sent data frames kept for retransmission after a resume
+const OUTBOX_LIMIT: usize = 256;
+
+/// How long recv() waits for data before returning a timeout error
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(500);
+
 pub struct SecureStream {
     stream: TcpStream,
     session: SecureSession,
+    // Bytes of a partially received frame, kept across read timeouts
+    rbuf: Vec<u8>,
+    local_close: Option<CloseReason>,
+    peer_close: Option<CloseReason>,
+    keepalive: Option<Keepalive>,
+    // Send a cover frame after this long without outgoing traffic
+    cover_interval: Option<Duration>,
+    last_sent: Instant,
+    // Data frames handed to send() and delivered by recv(), across resumes
+    app_sent: u64,
+    app_received: u64,
+    // Most recent sent data frames, the oldest is number app_sent - len
+    outbox: VecDeque<Vec<u8>>,
+    read_timeout: Duration,
 }
 
 impl SecureStream {
     pub fn new(stream: TcpStream, session: SecureSession) -> Self {
-        // Use blocking mode with timeouts to prevent deadlocks
-        // Timeouts are generous for internet connections with high latency
-        stream.set_nonblocking(false).ok();
-        stream.set_read_timeout(Some(std::time::Duration::from_millis(500))).ok();
-        stream.set_write_timeout(Some(std::time::Duration::from_secs(10))).ok();
-        // Enable TCP keepalive to detect dead connections
-        stream.set_nodelay(true).ok(); // Disable Nagle for lower latency
-        Self { stream, session }
+        configure_socket(&stream, DEFAULT_READ_TIMEOUT);
+        Self {
+            stream,
+            session,
+            rbuf: Vec::new(),
+            local_close: None,
+            peer_close: None,
+            keepalive: None,
+            cover_interval: None,
+            last_sent: Instant::now(),
+            app_sent: 0,
+            app_received: 0,
+            outbox: VecDeque::new(),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
     }
 
     pub fn try_clone(&self) -> Result<TcpStream, std::io::Error> {
         self.stream.try_clone()
     }
 
-    /// Send one encrypted frame
+    /// Send one encrypted frame.
+    /// The frame is queued for retransmission first, so it is delivered
+    /// after a resume even if this write fails. A frame too large to
+    /// send is rejected before it is queued.
     pub fn send(&mut self, plaintext: &[u8]) -> Result<(), SecureStreamError> {
-        eprintln!("[SecureStream] Encrypting {} bytes", plaintext.len());
-        let encrypted = self.session.encrypt(plaintext);
-        eprintln!("[SecureStream] Encrypted to {} bytes", encrypted.len());
+        if let Some(reason) = self.local_close.or(self.peer_close) {
+            return Err(SecureStreamError::Closed(reason));
+        }
+        let encoded = Frame::Data(plaintext.to_vec()).encode();
+        if !fits(encoded.len()) {
+            return Err(SecureStreamError::FrameTooLarge);
+        }
+
+        self.app_sent += 1;
+        self.outbox.push_back(plaintext.to_vec());
+        if self.outbox.len() > OUTBOX_LIMIT {
+            self.outbox.pop_front();
+        }
+
+        self.write_encoded(&encoded)
+    }
+
+    /// Receive one encrypted frame.
+    /// Keepalive pings and pongs are handled here and never returned.
+    pub fn recv(&mut self) -> Result<Vec<u8>, SecureStreamError> {
+        if let Some(reason) = self.peer_close {
+            return Err(SecureStreamError::Closed(reason));
+        }
+
+        loop {
+            self.poll_timers()?;
+
+            let frame = self.read_frame()?;
+            if let Some(keepalive) = self.keepalive.as_mut() {
+                keepalive.heard();
+            }
+
+            match Frame::decode(&frame).ok_or(SecureStreamError::MalformedFrame)? {
+                Frame::Data(data) => {
+                    self.app_received += 1;
+                    return Ok(data);
+                }
+                Frame::Close(reason) => {
+                    self.peer_close = Some(reason);
+                    // Answer with our own close so the peer sees a clean shutdown
+                    if self.local_close.is_none() {
+                        self.close(reason).ok();
+                    }
+                    return Err(SecureStreamError::Closed(reason));
+                }
+                Frame::Ping(id) => {
+                    if self.local_close.is_none() {
+                        self.write_frame(&Frame::Pong(id))?;
+                    }
+                }
+                Frame::Pong(id) => {
+                    if let Some(keepalive) = self.keepalive.as_mut() {
+                        keepalive.pong_received(id);
+                    }
+                }
+                Frame::Cover => {}
+            }
+        }
+    }
+
+    /// Enable encrypted heartbeats. Pings go out from recv(), so the
+    /// stream must be polled at least as often as `config.interval`.
+    pub fn set_keepalive(&mut self, config: KeepaliveConfig) {
+        self.keepalive = Some(Keepalive::new(config));
+    }
+
+    /// Pad outgoing frames to hide message lengths from the relay
+    pub fn set_padding(&mut self, policy: PaddingPolicy) {
+        self.session.set_padding(policy);
+    }
+
+    /// Shorter timeouts make recv() give up a shared stream sooner,
+    /// letting other threads send in between
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = timeout;
+        self.stream.set_read_timeout(Some(timeout)).ok();
+    }
+
+    /// Send a dummy frame whenever nothing was sent for `idle`.
+    /// Like pings, cover frames are sent from recv().
+    pub fn set_cover_traffic(&mut self, idle: Option<Duration>) {
+        self.cover_interval = idle;
+    }
+
+    /// Run due keepalive and cover traffic work, and fail if the peer
+    /// has gone silent
+    pub fn poll_timers(&mut self) -> Result<(), SecureStreamError> {
+        if self.is_closed() {
+            return Ok(());
+        }
+
+        if let Some(keepalive) = self.keepalive.as_mut() {
+            if keepalive.peer_dead() {
+                return Err(SecureStreamError::PeerTimeout);
+            }
+            if let Some(id) = keepalive.ping_due() {
+                self.write_frame(&Frame::Ping(id))?;
+            }
+        }
+
+        if let Some(idle) = self.cover_interval
+            && self.last_sent.elapsed() >= idle
+        {
+            self.write_frame(&Frame::Cover)?;
+        }
+        Ok(())
+    }
+
+    /// RTT and jitter from answered pings, once at least one came back
+    pub fn rtt_stats(&self) -> Option<RttStats> {
+        self.keepalive.as_ref().and_then(|k| k.stats())
+    }
+
+    /// Send an authenticated close frame and half-close the write side.
+    /// Frames can still be received until the peer closes too.
+    pub fn close(&mut self, reason: CloseReason) -> Result<(), SecureStreamError> {
+        if self.local_close.is_some() {
+            return Ok(());
+        }
+        let result = self.write_frame(&Frame::Close(reason));
+        self.local_close = Some(reason);
+        self.stream.shutdown(Shutdown::Write).ok();
+        result
+    }
+
+    pub fn peer_identity(&self) -> &VerifyingKey {
+        self.session.peer_identity()
+    }
+
+    /// Close reason received from the peer, if any
+    pub fn peer_close_reason(&self) -> Option<CloseReason> {
+        self.peer_close
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.local_close.is_some() || self.peer_close.is_some()
+    }
+
+    /// Ticket for resuming the current session after a drop
+    pub fn resumption_ticket(&self) -> ResumptionTicket {
+        ResumptionTicket::from_session(&self.session)
+    }
+
+    /// Data frames received so far, reported to the peer when resuming
+    pub fn app_received(&self) -> u64 {
+        self.app_received
+    }
+
+    /// Continue on a new connection after a resume handshake and resend
+    /// every data frame the peer had not received yet.
+    pub fn resume(&mut self, resumed: Resumed) -> Result<(), SecureStreamError> {
+        let Resumed {
+            stream,
+            mut session,
+            peer_received,
+        } = resumed;
+
+        configure_socket(&stream, self.read_timeout);
+        session.set_padding(self.session.padding());
+        self.stream = stream;
+        self.session = session;
+        self.rbuf.clear();
+        self.local_close = None;
+        self.peer_close = None;
+        if let Some(keepalive) = self.keepalive.as_mut() {
+            *keepalive = Keepalive::new(keepalive.config());
+        }
+
+        let first_queued = self.app_sent - self.outbox.len() as u64;
+        if peer_received < first_queued {
+            warn!(lost = first_queued - peer_received, "frames fell out of the outbox and cannot be resent");
+        }
+
+        let skip = peer_received.saturating_sub(first_queued) as usize;
+        let pending: Vec<Vec<u8>> = self.outbox.iter().skip(skip).cloned().collect();
+        debug!(frames = pending.len(), "resending frames after resume");
+        for data in pending {
+            self.write_frame(&Frame::Data(data))?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> TcpStream {
+        self.stream
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<(), SecureStreamError> {
+        self.write_encoded(&frame.encode())
+    }
 
-        if encrypted.len() > u16::MAX as usize {
+    fn write_encoded(&mut self, plaintext: &[u8]) -> Result<(), SecureStreamError> {
+        // Checked before encrypting so a rejected frame uses no counter
+        if !fits(plaintext.len()) {
             return Err(SecureStreamError::FrameTooLarge);
         }
+        let encrypted = self.session.encrypt(plaintext);
+        trace!(plaintext = plaintext.len(), encrypted = encrypted.len(), "encrypted frame");
 
         let len = encrypted.len() as u16;
         let len_bytes = len.to_be_bytes();
 
         // LEN || ENCRYPTED_DATA
-        eprintln!("[SecureStream] Writing {} byte frame (2 byte len + {} byte data)", len + 2, len);
         self.stream.write_all(&len_bytes)?;
         self.stream.write_all(&encrypted)?;
         self.stream.flush()?;
-        eprintln!("[SecureStream] Frame sent successfully");
+        self.last_sent = Instant::now();
+        trace!(bytes = len + 2, "frame sent");
 
         Ok(())
     }
 
-    /// Receive one encrypted frame
-    pub fn recv(&mut self) -> Result<Vec<u8>, SecureStreamError> {
+    /// Read and decrypt one frame. A timeout leaves any partial frame
+    /// buffered so the next call continues where this one stopped.
+    fn read_frame(&mut self) -> Result<Vec<u8>, SecureStreamError> {
         // Read LEN (silently - no debug spam)
-        let mut len_buf = [0u8; 2];
-        self.stream.read_exact(&mut len_buf)?;
-        let len = u16::from_be_bytes(len_buf) as usize;
+        self.fill_to(2)?;
+        let len = u16::from_be_bytes([self.rbuf[0], self.rbuf[1]]) as usize;
 
         if len == 0 {
-            return Err(SecureStreamError::UnexpectedEof);
+            return Err(SecureStreamError::MalformedFrame);
         }
 
         // Read ENCRYPTED_DATA
-        let mut enc_buf = vec![0u8; len];
-        self.stream.read_exact(&mut enc_buf)?;
-        eprintln!("[SecureStream] Received {} byte frame, decrypting...", len);
+        self.fill_to(2 + len)?;
+        let enc_buf: Vec<u8> = self.rbuf.drain(..2 + len).skip(2).collect();
 
         let plaintext = self.session.decrypt(&enc_buf)?;
-        eprintln!("[SecureStream] Decrypted to {} bytes", plaintext.len());
+        trace!(encrypted = len, plaintext = plaintext.len(), "decrypted frame");
         Ok(plaintext)
     }
 
-    pub fn into_inner(self) -> TcpStream {
-        self.stream
+    fn fill_to(&mut self, want: usize) -> Result<(), SecureStreamError> {
+        let mut chunk = [0u8; 4096];
+        while self.rbuf.len() < want {
+            let max = (want - self.rbuf.len()).min(chunk.len());
+            let n = self.stream.read(&mut chunk[..max])?;
+            if n == 0 {
+                // Peer's TCP went away without an authenticated close
+                return Err(SecureStreamError::UnexpectedEof);
+            }
+            self.rbuf.extend_from_slice(&chunk[..n]);
+        }
+        Ok(())
     }
+}
+
+/// Whether an encoded frame still fits a u16-length frame once the
+/// padding marker, counter and tag are added
+fn fits(encoded_len: usize) -> bool {
+    encoded_len < MAX_PADDED_LEN
+}
 
+fn configure_socket(stream: &TcpStream, read_timeout: Duration) {
+    // Use blocking mode with timeouts to prevent deadlocks
+    // Timeouts are generous for internet connections with high latency
+    stream.set_nonblocking(false).ok();
+    stream.set_read_timeout(Some(read_timeout)).ok();
+    stream.set_write_timeout(Some(Duration::from_secs(10))).ok();
+    // Enable TCP keepalive to detect dead connections
+    stream.set_nodelay(true).ok(); // Disable Nagle for lower latency
 }
@@ -1,18 +1,306 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ciborium::Value;
 use serde::{Deserialize,Serialize};
 
-#[derive(Debug,Serialize,Deserialize)]
+use crate::protocol::presence::PresenceStatus;
+use crate::protocol::transfer::TransferId;
+
+/// Random per-message id, shown to users as a short hex prefix
+pub type MessageId = u64;
+
+/// Hex digits used when showing a message id
+pub const SHORT_ID_LEN: usize = 6;
+
+/// Wire format version of the envelope. Only bumped for changes old
+/// clients cannot skip over; new fields and new message types keep it.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Every chat message travels as a CBOR map `{v: version, t: type, b: body}`.
+/// Bodies are CBOR maps keyed by field name, so fields can be added
+/// without breaking older peers, which ignore keys they do not know.
+#[derive(Serialize,Deserialize)]
+struct Envelope{
+    v:u32,
+    t:String,
+    b:Value,
+}
+
+/// Why a data frame could not be turned into a ChatMessage
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum EnvelopeError{
+    /// Not a well-formed envelope, or a body that does not fit its type
+    Malformed(String),
+    UnsupportedVersion(u32),
+    /// Sent by a newer client; safe to skip
+    UnknownType(String),
+}
+
+impl std::fmt::Display for EnvelopeError{
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>)->std::fmt::Result{
+        match self{
+            EnvelopeError::Malformed(e)=>write!(f,"malformed message: {}",e),
+            EnvelopeError::UnsupportedVersion(v)=>write!(f,"unsupported envelope version {} (expected {})",v,ENVELOPE_VERSION),
+            EnvelopeError::UnknownType(t)=>write!(f,"unknown message type '{}'",t),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError{}
+
+impl EnvelopeError{
+    /// True when the peer is simply newer than us and the session can go on
+    pub fn is_skippable(&self)->bool{
+        matches!(self,EnvelopeError::UnknownType(_)|EnvelopeError::UnsupportedVersion(_))
+    }
+}
+
+fn malformed(e:impl std::fmt::Display)->EnvelopeError{
+    EnvelopeError::Malformed(e.to_string())
+}
+
+#[derive(Debug)]
 pub enum ChatMessage{
     Text(ChatText),
     System(SystemMessage),
+    Edit(ChatEdit),
+    Delete(ChatDelete),
+    Reaction(ChatReaction),
+    Receipt(ChatReceipt),
+    Typing(ChatTyping),
+    Presence(ChatPresence),
+    FileOffer(FileOffer),
+    FileAccept(FileAccept),
+    FileReject(FileReject),
+    FileChunk(FileChunk),
+    FileComplete(FileComplete),
+    Nick(ChatNick),
+    Timer(ChatTimer),
 }
 
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub struct ChatText{
     pub sender_id:String,
     pub body:String,
+    pub id:MessageId,
+    /// Sender's clock, milliseconds since the Unix epoch
+    pub sent_at:u64,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub reply_to:Option<MessageId>,
+    /// Disappearing message: seconds until both sides drop it
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub expires_in:Option<u64>,
 }
 
 #[derive(Debug,Serialize,Deserialize)]
 pub struct SystemMessage{
     pub body:String,
 }
+
+/// Replace the body of one of the sender's own messages
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ChatEdit{
+    pub id:MessageId,
+    pub body:String,
+    pub edited_at:u64,
+}
+
+/// Retract one of the sender's own messages
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ChatDelete{
+    pub id:MessageId,
+}
+
+/// Add or remove a reaction on any message
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ChatReaction{
+    pub id:MessageId,
+    pub emoji:String,
+    pub remove:bool,
+}
+
+/// How far a message got on the receiving side
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize)]
+pub enum ReceiptStatus{
+    Delivered,
+    Read,
+}
+
+/// Acknowledge text messages from the peer
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ChatReceipt{
+    pub ids:Vec<MessageId>,
+    pub status:ReceiptStatus,
+}
+
+/// The sender changed the disappearing-message timer of the
+/// conversation; the most recent change wins on both sides
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ChatTimer{
+    /// None turns the timer off
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub ttl_secs:Option<u64>,
+    pub set_at:u64,
+}
+
+/// The sender started or stopped typing
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ChatTyping{
+    pub active:bool,
+}
+
+/// The sender's availability, with optional free text like "in a meeting"
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ChatPresence{
+    pub status:PresenceStatus,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub text:Option<String>,
+}
+
+/// Propose sending a file; nothing is sent before the peer accepts
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct FileOffer{
+    pub id:TransferId,
+    /// Sender's file name, sanitized again by the receiver
+    pub name:String,
+    pub size:u64,
+    #[serde(with="serde_bytes")]
+    pub sha256:[u8;32],
+}
+
+/// Start or, after a reconnect, continue sending from `offset`
+#[derive(Debug,Serialize,Deserialize)]
+pub struct FileAccept{
+    pub id:TransferId,
+    pub offset:u64,
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub struct FileReject{
+    pub id:TransferId,
+}
+
+#[derive(Serialize,Deserialize)]
+pub struct FileChunk{
+    pub id:TransferId,
+    pub offset:u64,
+    #[serde(with="serde_bytes")]
+    pub data:Vec<u8>,
+}
+
+impl std::fmt::Debug for FileChunk{
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>)->std::fmt::Result{
+        write!(f,"FileChunk {{ id: {}, offset: {}, {} bytes }}",self.id,self.offset,self.data.len())
+    }
+}
+
+/// Receiver's verdict once all bytes arrived and the hash was checked
+#[derive(Debug,Serialize,Deserialize)]
+pub struct FileComplete{
+    pub id:TransferId,
+    pub ok:bool,
+}
+
+/// The sender's display name, signed with its identity key
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ChatNick{
+    pub name:String,
+    #[serde(with="serde_bytes")]
+    pub signature:Vec<u8>,
+}
+
+impl ChatText{
+    /// New message with a fresh id, stamped with the current time
+    pub fn new(sender_id:String,body:String,reply_to:Option<MessageId>)->Self{
+        Self{
+            sender_id,
+            body,
+            id:new_message_id(),
+            sent_at:now_millis(),
+            reply_to,
+            expires_in:None,
+        }
+    }
+}
+
+/// Maps every variant to its type tag on the wire. Tags are part of the
+/// protocol: never rename one, add a new tag instead.
+macro_rules! message_types{
+    ($($variant:ident=>$tag:literal),* $(,)?)=>{
+        impl ChatMessage{
+            pub fn type_tag(&self)->&'static str{
+                match self{
+                    $(ChatMessage::$variant(_)=>$tag,)*
+                }
+            }
+
+            fn body(&self)->Result<Value,EnvelopeError>{
+                match self{
+                    $(ChatMessage::$variant(m)=>Value::serialized(m).map_err(malformed),)*
+                }
+            }
+
+            fn from_body(tag:&str,body:Value)->Result<Self,EnvelopeError>{
+                match tag{
+                    $($tag=>Ok(ChatMessage::$variant(body.deserialized().map_err(malformed)?)),)*
+                    other=>Err(EnvelopeError::UnknownType(other.to_string())),
+                }
+            }
+        }
+    };
+}
+
+message_types!{
+    Text=>"text",
+    System=>"system",
+    Edit=>"edit",
+    Delete=>"delete",
+    Reaction=>"reaction",
+    Receipt=>"receipt",
+    Typing=>"typing",
+    Presence=>"presence",
+    FileOffer=>"file_offer",
+    FileAccept=>"file_accept",
+    FileReject=>"file_reject",
+    FileChunk=>"file_chunk",
+    FileComplete=>"file_complete",
+    Nick=>"nick",
+    Timer=>"timer",
+}
+
+impl ChatMessage{
+    pub fn to_bytes(&self)->Result<Vec<u8>,EnvelopeError>{
+        let envelope=Envelope{
+            v:ENVELOPE_VERSION,
+            t:self.type_tag().to_string(),
+            b:self.body()?,
+        };
+        let mut out=Vec::new();
+        ciborium::ser::into_writer(&envelope,&mut out).map_err(malformed)?;
+        Ok(out)
+    }
+
+    pub fn from_bytes(data:&[u8])->Result<Self,EnvelopeError>{
+        let envelope:Envelope=ciborium::de::from_reader(data).map_err(malformed)?;
+        if envelope.v!=ENVELOPE_VERSION{
+            return Err(EnvelopeError::UnsupportedVersion(envelope.v));
+        }
+        Self::from_body(&envelope.t,envelope.b)
+    }
+}
+
+pub fn new_message_id()->MessageId{
+    rand::random()
+}
+
+/// Fixed-width hex form of an id; users may type any unique prefix
+pub fn short_id(id:MessageId)->String{
+    format!("{:016x}",id)[..SHORT_ID_LEN].to_string()
+}
+
+pub fn now_millis()->u64{
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// Longest timer either side may set
+pub const MAX_TTL: Duration = Duration::from_secs(4 * 7 * 86_400);
+
+/// (suffix, seconds) from the largest unit down
+const UNITS: [(&str, u64); 5] = [("w", 7 * 86_400), ("d", 86_400), ("h", 3600), ("m", 60), ("s", 1)];
+
+/// "30s", "5m", "2h", "1d", "1w" or a bare number of seconds;
+/// "off" or "0" turn the timer off
+pub fn parse_ttl(s: &str) -> Result<Option<Duration>, String> {
+    let s = s.trim().to_lowercase();
+    if matches!(s.as_str(), "off" | "0") {
+        return Ok(None);
+    }
+
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}', use e.g. 30s, 5m, 1h, 1d or off", s))?;
+    let unit_secs = match unit {
+        "" => 1,
+        unit => UNITS
+            .iter()
+            .find(|(suffix, _)| *suffix == unit)
+            .map(|&(_, secs)| secs)
+            .ok_or_else(|| format!("unknown unit '{}', use s, m, h, d or w", unit))?,
+    };
+
+    let ttl = Duration::from_secs(number.saturating_mul(unit_secs));
+    if ttl.is_zero() {
+        return Ok(None);
+    }
+    if ttl > MAX_TTL {
+        return Err(format!("timer too long, the maximum is {}", format_ttl(MAX_TTL)));
+    }
+    Ok(Some(ttl))
+}
+
+/// Largest unit that divides the duration evenly: "90s", "5m", "1d"
+pub fn format_ttl(ttl: Duration) -> String {
+    let secs = ttl.as_secs();
+    let (suffix, unit) = UNITS
+        .iter()
+        .find(|&&(_, unit)| secs >= unit && secs.is_multiple_of(unit))
+        .copied()
+        .unwrap_or(("s", 1));
+    format!("{}{}", secs / unit, suffix)
+}
+
+/// Time left in its largest unit, rounded up: 4m59s shows as "5m"
+pub fn format_remaining(remaining: Duration) -> String {
+    let secs = remaining.as_secs().max(1);
+    let (suffix, unit) = UNITS
+        .iter()
+        .find(|&&(_, unit)| secs >= unit)
+        .copied()
+        .unwrap_or(("s", 1));
+    format!("{}{}", secs.div_ceil(unit), suffix)
+}
+
+/// When a message expires, `ttl_secs` after `start` (milliseconds
+/// since the epoch)
+pub fn expires_at(start: u64, ttl_secs: u64) -> u64 {
+    start.saturating_add(ttl_secs.saturating_mul(1000))
+}
@@ -4,6 +4,7 @@ use serde::{Serialize, Deserialize};
 use serde_bytes;
 use std::net::TcpStream;
 use std::io::{Read,Write};
+use tracing::{debug, trace};
 
 use crate::state::secure_session::{SecureSession,SessionRole};
 use crate::net::secure_stream::SecureStream;
@@ -56,7 +57,7 @@ pub fn run(
     my_pubkey:[u8;32],
     peer_pubkey:[u8;32],
 )->anyhow::Result<SecureStream>{
-    eprintln!("[CLIENT] Starting handshake as initiator...");
+    debug!("starting handshake as initiator");
     //1.create ephermal handshake state 
     let hs=Handshake::new();
 
@@ -68,19 +69,17 @@ pub fn run(
     };
 
     let msg=bincode::serialize(&hello)?;
-    eprintln!("[CLIENT] Sending ClientHello ({} bytes)...", msg.len());
+    trace!(bytes = msg.len(), "sending ClientHello");
     stream.write_all(&msg)?;
     stream.flush()?;
-    eprintln!("[CLIENT] ClientHello sent, waiting for HostChallenge...");
 
     //3.recive HostChallenge 
     
     let mut buf=vec![0u8;1024];
     let n=stream.read(&mut buf)?;
-    eprintln!("[CLIENT] Received {} bytes", n);
+    trace!(bytes = n, "received HostChallenge");
 
     let challenge:HostChallenge=bincode::deserialize(&buf[..n])?;
-    eprintln!("[CLIENT] HostChallenge decoded successfully");
 
     //4.Verify the host identity 
 
@@ -95,7 +94,7 @@ pub fn run(
         .map_err(|e| anyhow::anyhow!("Invalid peer public key: {}", e))?;
     let session=SecureSession::new(SessionRole::Client, shared, peer_verifying_key);
 
-    eprintln!("[CLIENT] Handshake complete!");
+    debug!("handshake complete");
     Ok(SecureStream::new(stream,session))
 }
 
@@ -104,17 +103,15 @@ pub fn run_as_host(
     my_pubkey: [u8; 32],
     peer_pubkey: [u8; 32],
 ) -> anyhow::Result<SecureStream> {
-    eprintln!("[HOST] Starting handshake as responder...");
+    debug!("starting handshake as responder");
     // 1. Create ephemeral handshake state
     let hs = Handshake::new();
 
     // 2. Receive ClientHello
-    eprintln!("[HOST] Waiting for ClientHello...");
     let mut buf = vec![0u8; 1024];
     let n = stream.read(&mut buf)?;
-    eprintln!("[HOST] Received {} bytes", n);
+    trace!(bytes = n, "received ClientHello");
     let hello: ClientHello = bincode::deserialize(&buf[..n])?;
-    eprintln!("[HOST] ClientHello decoded successfully");
 
     // 3. Send HostChallenge
     let challenge = HostChallenge {
@@ -125,10 +122,9 @@ pub fn run_as_host(
     };
 
     let msg = bincode::serialize(&challenge)?;
-    eprintln!("[HOST] Sending HostChallenge ({} bytes)...", msg.len());
+    trace!(bytes = msg.len(), "sending HostChallenge");
     stream.write_all(&msg)?;
     stream.flush()?;
-    eprintln!("[HOST] HostChallenge sent");
 
     // 4. Derive shared secret
     let peer_ephemeral = x25519_dalek::PublicKey::from(hello.client_ephemeral_pub);
@@ -139,6 +135,6 @@ pub fn run_as_host(
         .map_err(|e| anyhow::anyhow!("Invalid peer public key: {}", e))?;
     let session = SecureSession::new(SessionRole::Host, shared, peer_verifying_key);
 
-    eprintln!("[HOST] Handshake complete!");
+    debug!("handshake complete");
     Ok(SecureStream::new(stream, session))
 }
@@ -1,3 +1,8 @@
 pub mod handshake;
 pub mod chat;
+pub mod resume;
+pub mod presence;
+pub mod transfer;
+pub mod nick;
+pub mod expiry;
 // ...existing code...
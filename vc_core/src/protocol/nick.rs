@@ -0,0 +1,71 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::protocol::chat::ChatNick;
+
+/// Longest display name, in characters
+pub const MAX_NICK_LEN: usize = 32;
+
+/// Bytes of the key hash shown after a display name
+pub const FINGERPRINT_BYTES: usize = 4;
+
+/// Separates a display name from its fingerprint, never allowed in names
+pub const FINGERPRINT_SEPARATOR: char = '~';
+
+/// Short hex digest of an identity key, e.g. "3fa9c1d2"
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"voicechat fingerprint")
+        .chain_update(public_key)
+        .finalize();
+    digest[..FINGERPRINT_BYTES]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// "alice~3fa9c1d2": two peers may pick the same name, but not the
+/// same fingerprint
+pub fn display_name(name: &str, public_key: &[u8]) -> String {
+    format!("{}{}{}", name, FINGERPRINT_SEPARATOR, fingerprint(public_key))
+}
+
+/// Trim and check a requested display name
+pub fn clean_nick(name: &str) -> Result<String, String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err("Display name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_NICK_LEN {
+        return Err(format!("Display name is longer than {} characters", MAX_NICK_LEN));
+    }
+    if name.chars().any(|c| c.is_control() || c == FINGERPRINT_SEPARATOR) {
+        return Err(format!(
+            "Display name cannot contain '{}' or control characters",
+            FINGERPRINT_SEPARATOR
+        ));
+    }
+    Ok(name)
+}
+
+/// Bytes signed by the identity key, binding the name to that key
+pub fn signing_payload(public_key: &[u8; 32], name: &str) -> Vec<u8> {
+    let mut payload = b"voicechat nick\0".to_vec();
+    payload.extend_from_slice(public_key);
+    payload.extend_from_slice(name.as_bytes());
+    payload
+}
+
+/// Check that `nick` was signed by `public_key` and holds a valid name
+pub fn verify_nick(public_key: &[u8; 32], nick: &ChatNick) -> bool {
+    let (Ok(key), Ok(signature)) = (
+        VerifyingKey::from_bytes(public_key),
+        Signature::from_slice(&nick.signature),
+    ) else {
+        return false;
+    };
+    clean_nick(&nick.name).is_ok_and(|name| name == nick.name)
+        && key
+            .verify_strict(&signing_payload(public_key, &nick.name), &signature)
+            .is_ok()
+}
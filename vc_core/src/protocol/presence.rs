@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Longest status text a peer may attach to its presence
+pub const MAX_STATUS_TEXT: usize = 64;
+
+/// Resend "typing" at most this often while the user keeps typing
+pub const TYPING_REFRESH: Duration = Duration::from_secs(3);
+
+/// Treat a peer as no longer typing after this long without a refresh
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Send "stopped typing" after this long without a keystroke
+pub const TYPING_IDLE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresenceStatus {
+    #[default]
+    Online,
+    Away,
+    Busy,
+    DoNotDisturb,
+}
+
+impl fmt::Display for PresenceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceStatus::Online => write!(f, "online"),
+            PresenceStatus::Away => write!(f, "away"),
+            PresenceStatus::Busy => write!(f, "busy"),
+            PresenceStatus::DoNotDisturb => write!(f, "do not disturb"),
+        }
+    }
+}
+
+impl FromStr for PresenceStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "online" | "back" => Ok(PresenceStatus::Online),
+            "away" => Ok(PresenceStatus::Away),
+            "busy" => Ok(PresenceStatus::Busy),
+            "dnd" | "do-not-disturb" => Ok(PresenceStatus::DoNotDisturb),
+            other => Err(format!("unknown status '{}', use online, away, busy or dnd", other)),
+        }
+    }
+}
+
+/// Token bucket: `capacity` events at once, refilled over `period`
+pub struct RateLimiter {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refill_per_sec: capacity as f64 / period.as_secs_f64(),
+            last: Instant::now(),
+        }
+    }
+
+    /// Take one token if available
+    pub fn allow(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until the next event would be allowed
+    pub fn retry_after(&mut self) -> Duration {
+        self.refill();
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec)
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last = now;
+    }
+}
+
+/// Decides when a frontend that sees keystrokes should send typing
+/// notices. Each method returns Some(active) when a notice is due.
+#[derive(Default)]
+pub struct TypingNotifier {
+    last_sent: Option<Instant>,
+    last_key: Option<Instant>,
+}
+
+impl TypingNotifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The input line changed
+    pub fn keystroke(&mut self) -> Option<bool> {
+        let now = Instant::now();
+        self.last_key = Some(now);
+        match self.last_sent {
+            Some(sent) if now.duration_since(sent) < TYPING_REFRESH => None,
+            _ => {
+                self.last_sent = Some(now);
+                Some(true)
+            }
+        }
+    }
+
+    /// The line was sent or cleared
+    pub fn submitted(&mut self) -> Option<bool> {
+        self.last_key = None;
+        self.last_sent.take().map(|_| false)
+    }
+
+    /// Call periodically to stop after the user goes quiet
+    pub fn poll(&mut self) -> Option<bool> {
+        match self.last_key {
+            Some(key) if key.elapsed() >= TYPING_IDLE => self.submitted(),
+            _ => None,
+        }
+    }
+}
+
+/// Cut status text to MAX_STATUS_TEXT characters on a char boundary
+pub fn clamp_status_text(text: &str) -> String {
+    text.trim().chars().take(MAX_STATUS_TEXT).collect()
+}
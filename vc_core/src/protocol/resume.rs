@@ -0,0 +1,202 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use ed25519_dalek::VerifyingKey;
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::debug;
+
+use crate::crypto::random_nonce;
+use crate::state::secure_session::{SecureSession, SessionRole};
+
+/// How long a ticket can be used after the session it came from
+pub const TICKET_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// Give up on a resume handshake that stalls this long
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything needed to resume an encrypted session without a full
+/// handshake. Both peers derive the same ticket from their session.
+#[derive(Clone)]
+pub struct ResumptionTicket {
+    pub ticket_id: [u8; 16],
+    psk: [u8; 32],
+    pub peer_identity: VerifyingKey,
+    pub issued_at: Instant,
+}
+
+impl ResumptionTicket {
+    pub fn from_session(session: &SecureSession) -> Self {
+        let hk = Hkdf::<Sha256>::new(None, session.resumption_secret());
+
+        let mut ticket_id = [0u8; 16];
+        hk.expand(b"voicechat ticket id", &mut ticket_id)
+            .expect("HKDF expand failed");
+        let mut psk = [0u8; 32];
+        hk.expand(b"voicechat ticket psk", &mut psk)
+            .expect("HKDF expand failed");
+
+        Self {
+            ticket_id,
+            psk,
+            peer_identity: *session.peer_identity(),
+            issued_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.issued_at.elapsed() >= TICKET_LIFETIME
+    }
+}
+
+/// Client -> Host: which ticket to resume and how much was received
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResumeHello {
+    pub ticket_id: [u8; 16],
+    pub nonce_c: [u8; 32],
+    pub received: u64,
+}
+
+/// Host -> Client: host nonce, host receive count and key confirmation
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResumeAccept {
+    pub nonce_h: [u8; 32],
+    pub received: u64,
+    pub confirm_h: [u8; 32],
+}
+
+/// Client -> Host: client key confirmation
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResumeFinish {
+    pub confirm_c: [u8; 32],
+}
+
+/// Result of a successful resume handshake
+pub struct Resumed {
+    pub stream: TcpStream,
+    pub session: SecureSession,
+    /// Data frames the peer had received before the drop
+    pub peer_received: u64,
+}
+
+struct ResumeKeys {
+    session_key: [u8; 32],
+    confirm_h: [u8; 32],
+    confirm_c: [u8; 32],
+}
+
+fn derive_resume_keys(
+    ticket: &ResumptionTicket,
+    nonce_c: &[u8; 32],
+    nonce_h: &[u8; 32],
+) -> ResumeKeys {
+    let hk = Hkdf::<Sha256>::new(Some(&ticket.ticket_id), &ticket.psk);
+    let mut okm = [0u8; 96];
+
+    let info = [b"voicechat resume".as_slice(), nonce_c, nonce_h].concat();
+    hk.expand(&info, &mut okm).expect("HKDF expand failed");
+
+    let mut keys = ResumeKeys {
+        session_key: [0u8; 32],
+        confirm_h: [0u8; 32],
+        confirm_c: [0u8; 32],
+    };
+    keys.session_key.copy_from_slice(&okm[..32]);
+    keys.confirm_h.copy_from_slice(&okm[32..64]);
+    keys.confirm_c.copy_from_slice(&okm[64..]);
+    keys
+}
+
+/// Compare without bailing out on the first differing byte
+fn confirm_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn write_msg<T: Serialize>(stream: &mut TcpStream, msg: &T) -> anyhow::Result<()> {
+    let data = bincode::serialize(msg)?;
+    stream.write_all(&(data.len() as u16).to_be_bytes())?;
+    stream.write_all(&data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_msg<T: for<'de> Deserialize<'de>>(stream: &mut TcpStream) -> anyhow::Result<T> {
+    let mut len_buf = [0u8; 2];
+    stream.read_exact(&mut len_buf)?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len_buf) as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(bincode::deserialize(&buf)?)
+}
+
+/// Resume as the side the signaling server called CLIENT
+pub fn resume_as_client(
+    mut stream: TcpStream,
+    ticket: &ResumptionTicket,
+    received: u64,
+) -> anyhow::Result<Resumed> {
+    debug!("resuming session as client");
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    let nonce_c = random_nonce();
+    write_msg(&mut stream, &ResumeHello {
+        ticket_id: ticket.ticket_id,
+        nonce_c,
+        received,
+    })?;
+
+    let accept: ResumeAccept = read_msg(&mut stream)?;
+    let keys = derive_resume_keys(ticket, &nonce_c, &accept.nonce_h);
+    if !confirm_eq(&keys.confirm_h, &accept.confirm_h) {
+        anyhow::bail!("Host could not prove knowledge of the resumption ticket");
+    }
+
+    write_msg(&mut stream, &ResumeFinish {
+        confirm_c: keys.confirm_c,
+    })?;
+
+    debug!("session resumed");
+    let session = SecureSession::new(SessionRole::Client, keys.session_key, ticket.peer_identity);
+    Ok(Resumed {
+        stream,
+        session,
+        peer_received: accept.received,
+    })
+}
+
+/// Resume as the side the signaling server called HOST
+pub fn resume_as_host(
+    mut stream: TcpStream,
+    ticket: &ResumptionTicket,
+    received: u64,
+) -> anyhow::Result<Resumed> {
+    debug!("resuming session as host");
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    let hello: ResumeHello = read_msg(&mut stream)?;
+    if hello.ticket_id != ticket.ticket_id {
+        anyhow::bail!("Peer presented an unknown resumption ticket");
+    }
+
+    let nonce_h = random_nonce();
+    let keys = derive_resume_keys(ticket, &hello.nonce_c, &nonce_h);
+    write_msg(&mut stream, &ResumeAccept {
+        nonce_h,
+        received,
+        confirm_h: keys.confirm_h,
+    })?;
+
+    let finish: ResumeFinish = read_msg(&mut stream)?;
+    if !confirm_eq(&keys.confirm_c, &finish.confirm_c) {
+        anyhow::bail!("Client could not prove knowledge of the resumption ticket");
+    }
+
+    debug!("session resumed");
+    let session = SecureSession::new(SessionRole::Host, keys.session_key, ticket.peer_identity);
+    Ok(Resumed {
+        stream,
+        session,
+        peer_received: hello.received,
+    })
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Random per-transfer id, shown like a message id
+pub type TransferId = u64;
+
+/// File bytes per FileChunk; well under the frame size limit even
+/// with padding
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Longest file name kept from an offer, in bytes
+pub const MAX_FILENAME_LEN: usize = 200;
+
+/// Turn a peer-supplied name into a plain file name that cannot
+/// escape the download directory or hide itself
+pub fn sanitize_filename(name: &str) -> String {
+    // Only the last path component, whichever separator was used
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+
+    let mut clean: String = base
+        .chars()
+        .map(|c| match c {
+            c if c.is_control() => '_',
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+
+    clean = clean.trim().trim_start_matches('.').trim_end_matches(['.', ' ']).to_string();
+
+    while clean.len() > MAX_FILENAME_LEN {
+        clean.pop();
+    }
+
+    if clean.is_empty() {
+        "download".to_string()
+    } else {
+        clean
+    }
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Human readable size, e.g. "4.2 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+    format!("{:.1} {}", value, unit)
+}
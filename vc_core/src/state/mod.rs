@@ -1,2 +1,3 @@
 pub mod machine;
 pub mod secure_session;
+pub mod padding;
@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+/// Marker byte that separates a payload from its zero padding
+const PAD_MARKER: u8 = 0x80;
+/// Smallest bucket used by `PaddingPolicy::Buckets`
+const MIN_BUCKET: usize = 64;
+/// Largest padded plaintext that still fits a u16-length frame
+/// (8 byte counter + 16 byte tag)
+pub const MAX_PADDED_LEN: usize = u16::MAX as usize - 24;
+
+/// How much padding SecureSession adds before encrypting.
+///
+/// Every plaintext gets a 0x80 marker followed by zeros, so the receiver
+/// strips padding the same way whatever policy the sender picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingPolicy {
+    /// Only the marker byte, ciphertext length follows plaintext length
+    #[default]
+    None,
+    /// Round up to the next power of two, starting at 64 bytes
+    Buckets,
+    /// Round up to a multiple of a fixed size, e.g. the path MTU
+    Fixed(usize),
+}
+
+impl PaddingPolicy {
+    fn target_len(self, len: usize) -> usize {
+        let target = match self {
+            PaddingPolicy::None => len,
+            PaddingPolicy::Buckets => len.next_power_of_two().max(MIN_BUCKET),
+            PaddingPolicy::Fixed(0) => len,
+            PaddingPolicy::Fixed(size) => len.div_ceil(size) * size,
+        };
+        // Never pad a frame past what the wire format can carry
+        target.min(MAX_PADDED_LEN).max(len)
+    }
+}
+
+impl FromStr for PaddingPolicy {
+    type Err = String;
+
+    /// Parses `none`, `buckets` or `mtu:<bytes>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(PaddingPolicy::None),
+            "buckets" => Ok(PaddingPolicy::Buckets),
+            other => other
+                .strip_prefix("mtu:")
+                .and_then(|n| n.parse().ok())
+                .filter(|&n: &usize| n > 0)
+                .map(PaddingPolicy::Fixed)
+                .ok_or_else(|| format!("invalid padding policy: {}", s)),
+        }
+    }
+}
+
+/// Append the marker and zero padding required by `policy`
+pub fn pad(plaintext: &[u8], policy: PaddingPolicy) -> Vec<u8> {
+    let target = policy.target_len(plaintext.len() + 1);
+
+    let mut out = Vec::with_capacity(target);
+    out.extend_from_slice(plaintext);
+    out.push(PAD_MARKER);
+    out.resize(target, 0);
+    out
+}
+
+/// Strip trailing zeros and the marker, None if the marker is missing
+pub fn unpad(padded: &[u8]) -> Option<&[u8]> {
+    let end = padded.iter().rposition(|&b| b != 0)?;
+    if padded[end] != PAD_MARKER {
+        return None;
+    }
+    Some(&padded[..end])
+}
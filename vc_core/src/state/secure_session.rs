@@ -3,6 +3,11 @@ use chacha20poly1305::{
     ChaCha20Poly1305, Key, Nonce,
 };
 use ed25519_dalek::VerifyingKey;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::crypto::keylog;
+use crate::state::padding::{self, PaddingPolicy};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionRole {
@@ -23,6 +28,8 @@ pub struct SecureSession {
     cipher: ChaCha20Poly1305,
     send_ctr: u64,
     recv_ctr: u64,
+    padding: PaddingPolicy,
+    resumption_secret: [u8; 32],
 }
 
 impl SecureSession {
@@ -31,30 +38,60 @@ impl SecureSession {
         session_key: [u8; 32],
         peer_identity: VerifyingKey,
     ) -> Self {
+        keylog::log_session_key(&session_key);
+
         let key = Key::from_slice(&session_key);
         let cipher = ChaCha20Poly1305::new(key);
 
+        // Separate secret for resumption tickets so the traffic key
+        // itself never leaves the session
+        let mut resumption_secret = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &session_key)
+            .expand(b"voicechat resumption", &mut resumption_secret)
+            .expect("HKDF expand failed");
+
         Self {
             role,
             peer_identity,
             cipher,
             send_ctr: 0,
             recv_ctr: 0,
+            padding: PaddingPolicy::None,
+            resumption_secret,
         }
     }
 
+    /// Choose how outgoing plaintexts are padded to hide their length
+    pub fn set_padding(&mut self, policy: PaddingPolicy) {
+        self.padding = policy;
+    }
+
+    pub fn padding(&self) -> PaddingPolicy {
+        self.padding
+    }
+
+    pub fn role(&self) -> SessionRole {
+        self.role
+    }
+
+    /// Secret both peers share for resuming this session later
+    pub fn resumption_secret(&self) -> &[u8; 32] {
+        &self.resumption_secret
+    }
+
     pub fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
         let ctr = self.send_ctr;
         self.send_ctr += 1;
 
         let nonce = nonce_from_ctr(ctr);
+        let padded = padding::pad(plaintext, self.padding);
 
         let ciphertext = self
             .cipher
             .encrypt(
                 &nonce,
                 Payload {
-                    msg: plaintext,
+                    msg: &padded,
                     aad: &ctr.to_be_bytes(),
                 },
             )
@@ -92,7 +129,9 @@ impl SecureSession {
             .map_err(|_| SecureSessionError::DecryptionFailed)?;
 
         self.recv_ctr = ctr + 1;
-        Ok(plaintext)
+        let plaintext = padding::unpad(&plaintext)
+            .ok_or(SecureSessionError::MalformedPacket)?;
+        Ok(plaintext.to_vec())
     }
 
     pub fn peer_identity(&self) -> &VerifyingKey {
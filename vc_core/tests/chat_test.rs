@@ -0,0 +1,42 @@
+use vc_core::protocol::chat::{
+    short_id, ChatMessage, ChatReceipt, ChatText, ReceiptStatus, SHORT_ID_LEN,
+};
+
+#[test]
+fn text_round_trips_with_id_and_reply() {
+    let parent = ChatText::new("alice".to_string(), "hi".to_string(), None);
+    let reply = ChatText::new("bob".to_string(), "hello".to_string(), Some(parent.id));
+
+    let bytes = ChatMessage::Text(reply.clone()).to_bytes().unwrap();
+    match ChatMessage::from_bytes(&bytes).unwrap() {
+        ChatMessage::Text(txt) => {
+            assert_eq!(txt.id, reply.id);
+            assert_eq!(txt.sent_at, reply.sent_at);
+            assert_eq!(txt.reply_to, Some(parent.id));
+            assert_eq!(txt.body, "hello");
+        }
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+#[test]
+fn receipt_round_trips() {
+    let msg = ChatMessage::Receipt(ChatReceipt {
+        ids: vec![1, 2, 3],
+        status: ReceiptStatus::Read,
+    });
+
+    match ChatMessage::from_bytes(&msg.to_bytes().unwrap()).unwrap() {
+        ChatMessage::Receipt(receipt) => {
+            assert_eq!(receipt.ids, vec![1, 2, 3]);
+            assert_eq!(receipt.status, ReceiptStatus::Read);
+        }
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+#[test]
+fn short_ids_are_fixed_width() {
+    assert_eq!(short_id(0), "000000");
+    assert_eq!(short_id(u64::MAX).len(), SHORT_ID_LEN);
+}
@@ -0,0 +1,192 @@
+//! Pins the chat wire format. If one of these fails, the change breaks
+//! compatibility with released clients. Regenerate the files with
+//! `UPDATE_GOLDEN=1 cargo test -p vc_core --test envelope_test` only
+//! when that is intended.
+
+use std::fs;
+use std::path::PathBuf;
+
+use ciborium::Value;
+use vc_core::protocol::chat::{
+    ChatMessage, ChatNick, ChatPresence, ChatReceipt, ChatText, ChatTimer, EnvelopeError, FileChunk,
+    FileOffer, ReceiptStatus, ENVELOPE_VERSION,
+};
+use vc_core::protocol::presence::PresenceStatus;
+
+fn samples() -> Vec<(&'static str, ChatMessage)> {
+    vec![
+        (
+            "text",
+            ChatMessage::Text(ChatText {
+                sender_id: "alice".to_string(),
+                body: "hello".to_string(),
+                id: 0x0123_4567_89ab_cdef,
+                sent_at: 1_700_000_000_000,
+                reply_to: None,
+                expires_in: None,
+            }),
+        ),
+        (
+            "text_reply",
+            ChatMessage::Text(ChatText {
+                sender_id: "bob".to_string(),
+                body: "hi!".to_string(),
+                id: 2,
+                sent_at: 1_700_000_001_000,
+                reply_to: Some(0x0123_4567_89ab_cdef),
+                expires_in: None,
+            }),
+        ),
+        (
+            "text_expiring",
+            ChatMessage::Text(ChatText {
+                sender_id: "alice".to_string(),
+                body: "burn after reading".to_string(),
+                id: 3,
+                sent_at: 1_700_000_002_000,
+                reply_to: None,
+                expires_in: Some(300),
+            }),
+        ),
+        (
+            "timer",
+            ChatMessage::Timer(ChatTimer {
+                ttl_secs: Some(300),
+                set_at: 1_700_000_002_000,
+            }),
+        ),
+        (
+            "receipt",
+            ChatMessage::Receipt(ChatReceipt {
+                ids: vec![1, 2],
+                status: ReceiptStatus::Read,
+            }),
+        ),
+        (
+            "presence",
+            ChatMessage::Presence(ChatPresence {
+                status: PresenceStatus::Away,
+                text: Some("lunch".to_string()),
+            }),
+        ),
+        (
+            "file_offer",
+            ChatMessage::FileOffer(FileOffer {
+                id: 7,
+                name: "notes.txt".to_string(),
+                size: 3,
+                sha256: [0xab; 32],
+            }),
+        ),
+        (
+            "file_chunk",
+            ChatMessage::FileChunk(FileChunk {
+                id: 7,
+                offset: 0,
+                data: b"abc".to_vec(),
+            }),
+        ),
+        (
+            "nick",
+            ChatMessage::Nick(ChatNick {
+                name: "alice".to_string(),
+                signature: vec![0x11; 64],
+            }),
+        ),
+    ]
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.cbor", name))
+}
+
+fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    ciborium::ser::into_writer(value, &mut out).unwrap();
+    out
+}
+
+fn envelope(version: u32, tag: &str, body: Vec<(&str, Value)>) -> Vec<u8> {
+    let body = body
+        .into_iter()
+        .map(|(k, v)| (Value::Text(k.to_string()), v))
+        .collect();
+    encode(&Value::Map(vec![
+        (Value::Text("v".into()), Value::Integer(version.into())),
+        (Value::Text("t".into()), Value::Text(tag.into())),
+        (Value::Text("b".into()), Value::Map(body)),
+    ]))
+}
+
+#[test]
+fn encoding_matches_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    for (name, msg) in samples() {
+        let bytes = msg.to_bytes().unwrap();
+        let path = golden_path(name);
+        if update {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &bytes).unwrap();
+            continue;
+        }
+        let golden = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(bytes, golden, "{} no longer encodes like {}", name, path.display());
+    }
+}
+
+#[test]
+fn golden_files_decode() {
+    for (name, msg) in samples() {
+        let golden = fs::read(golden_path(name)).unwrap();
+        let decoded = ChatMessage::from_bytes(&golden).unwrap();
+        assert_eq!(decoded.type_tag(), msg.type_tag());
+        // Decoding then encoding must reproduce the same bytes
+        assert_eq!(decoded.to_bytes().unwrap(), golden, "{}", name);
+    }
+}
+
+#[test]
+fn unknown_type_is_reported_as_skippable() {
+    let data = envelope(ENVELOPE_VERSION, "poll", vec![("question", Value::Text("lunch?".into()))]);
+
+    let err = ChatMessage::from_bytes(&data).unwrap_err();
+    assert_eq!(err, EnvelopeError::UnknownType("poll".to_string()));
+    assert!(err.is_skippable());
+}
+
+#[test]
+fn unknown_fields_are_ignored() {
+    let data = envelope(
+        ENVELOPE_VERSION,
+        "typing",
+        vec![
+            ("active", Value::Bool(true)),
+            ("added_later", Value::Integer(5.into())),
+        ],
+    );
+
+    match ChatMessage::from_bytes(&data).unwrap() {
+        ChatMessage::Typing(typing) => assert!(typing.active),
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+#[test]
+fn other_versions_and_garbage_are_rejected() {
+    let data = envelope(ENVELOPE_VERSION + 1, "typing", vec![("active", Value::Bool(true))]);
+    assert_eq!(
+        ChatMessage::from_bytes(&data).unwrap_err(),
+        EnvelopeError::UnsupportedVersion(ENVELOPE_VERSION + 1)
+    );
+
+    let err = ChatMessage::from_bytes(&[0xff, 0x00, 0x13]).unwrap_err();
+    assert!(matches!(err, EnvelopeError::Malformed(_)));
+    assert!(!err.is_skippable());
+
+    // Known type, body missing a required field
+    let data = envelope(ENVELOPE_VERSION, "typing", vec![]);
+    assert!(matches!(ChatMessage::from_bytes(&data), Err(EnvelopeError::Malformed(_))));
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use vc_core::protocol::expiry::{expires_at, format_remaining, format_ttl, parse_ttl, MAX_TTL};
+
+#[test]
+fn ttl_parses_units_and_off() {
+    assert_eq!(parse_ttl("30s").unwrap(), Some(Duration::from_secs(30)));
+    assert_eq!(parse_ttl("5M").unwrap(), Some(Duration::from_secs(300)));
+    assert_eq!(parse_ttl("2h").unwrap(), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_ttl("90").unwrap(), Some(Duration::from_secs(90)));
+    assert_eq!(parse_ttl("off").unwrap(), None);
+    assert_eq!(parse_ttl("0m").unwrap(), None);
+
+    assert!(parse_ttl("5x").is_err());
+    assert!(parse_ttl("m").is_err());
+    assert!(parse_ttl("5w").is_err());
+    assert_eq!(parse_ttl("4w").unwrap(), Some(MAX_TTL));
+}
+
+#[test]
+fn ttl_formats_with_largest_even_unit() {
+    assert_eq!(format_ttl(Duration::from_secs(90)), "90s");
+    assert_eq!(format_ttl(Duration::from_secs(300)), "5m");
+    assert_eq!(format_ttl(Duration::from_secs(86_400)), "1d");
+    assert_eq!(format_remaining(Duration::from_secs(299)), "5m");
+    assert_eq!(format_remaining(Duration::from_millis(10)), "1s");
+    assert_eq!(expires_at(1_000, 5), 6_000);
+}
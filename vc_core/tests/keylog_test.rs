@@ -0,0 +1,88 @@
+use std::process::Command;
+
+use ed25519_dalek::SigningKey;
+
+use vc_core::crypto::keylog::{self, KeyLogEntry};
+use vc_core::net::frame::{CloseReason, Frame};
+use vc_core::protocol::chat::{ChatMessage, SystemMessage};
+use vc_core::state::secure_session::{SecureSession, SessionRole};
+
+#[test]
+fn keylog_lines_round_trip() {
+    let key = [0x5au8; 32];
+    let entry = KeyLogEntry {
+        session_id: keylog::session_id(&key),
+        key,
+    };
+
+    let text = format!("# comment\n\n{}\nGARBAGE line\n", keylog::format_entry(&entry));
+    assert_eq!(keylog::parse_keylog(&text), vec![entry]);
+}
+
+#[test]
+fn decoder_prints_frames_from_raw_stream() {
+    let key = [0x11u8; 32];
+    let peer = SigningKey::from_bytes(&[4u8; 32]).verifying_key();
+    let mut session = SecureSession::new(SessionRole::Client, key, peer);
+
+    // Handshake bytes come before the first frame in a real capture
+    let mut capture = b"not a frame, just handshake noise".to_vec();
+    let msg = ChatMessage::System(SystemMessage {
+        body: "decode me".to_string(),
+    });
+    for frame in [
+        Frame::Data(msg.to_bytes().unwrap()),
+        Frame::Ping(7),
+        Frame::Close(CloseReason::UserLeft),
+    ] {
+        let record = session.encrypt(&frame.encode());
+        capture.extend_from_slice(&(record.len() as u16).to_be_bytes());
+        capture.extend_from_slice(&record);
+    }
+
+    let dir = std::env::temp_dir().join(format!("vc_keylog_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let keylog_path = dir.join("keys.log");
+    let stream_path = dir.join("stream.bin");
+    let entry = KeyLogEntry {
+        session_id: keylog::session_id(&key),
+        key,
+    };
+    std::fs::write(&keylog_path, keylog::format_entry(&entry)).unwrap();
+    std::fs::write(&stream_path, &capture).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_voicechat-decode"))
+        .arg("--keylog")
+        .arg(&keylog_path)
+        .arg("--stream")
+        .arg(&stream_path)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("decode me"), "{}", stdout);
+    assert!(stdout.contains("PING id=7"), "{}", stdout);
+    assert!(stdout.contains("CLOSE (user left)"), "{}", stdout);
+}
+
+#[cfg(all(unix, feature = "keylog"))]
+#[test]
+fn key_log_is_private_to_its_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("vc_keylog_mode_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keys.log");
+    // SAFETY: no other test in this binary reads the key log variable
+    unsafe { std::env::set_var(keylog::KEYLOG_ENV, &path) };
+    keylog::log_session_key(&[0x22u8; 32]);
+    // SAFETY: as above
+    unsafe { std::env::remove_var(keylog::KEYLOG_ENV) };
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(keylog::parse_keylog(&text).len(), 1);
+}
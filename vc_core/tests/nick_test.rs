@@ -0,0 +1,41 @@
+use ed25519_dalek::{Signer, SigningKey};
+
+use vc_core::protocol::chat::ChatNick;
+use vc_core::protocol::nick::{clean_nick, display_name, fingerprint, signing_payload, verify_nick};
+
+fn signed_nick(key: &SigningKey, name: &str) -> ChatNick {
+    let payload = signing_payload(key.verifying_key().as_bytes(), name);
+    ChatNick {
+        name: name.to_string(),
+        signature: key.sign(&payload).to_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn nick_is_bound_to_signing_key() {
+    let alice = SigningKey::from_bytes(&[1u8; 32]);
+    let mallory = SigningKey::from_bytes(&[2u8; 32]);
+    let nick = signed_nick(&alice, "alice");
+
+    assert!(verify_nick(alice.verifying_key().as_bytes(), &nick));
+    // Replaying alice's announcement from another key fails
+    assert!(!verify_nick(mallory.verifying_key().as_bytes(), &nick));
+
+    let renamed = ChatNick {
+        name: "bob".to_string(),
+        ..nick
+    };
+    assert!(!verify_nick(alice.verifying_key().as_bytes(), &renamed));
+}
+
+#[test]
+fn names_cannot_fake_a_fingerprint() {
+    assert!(clean_nick("alice~1234abcd").is_err());
+    assert!(clean_nick("   ").is_err());
+    assert!(clean_nick(&"x".repeat(33)).is_err());
+    assert_eq!(clean_nick("  alice \t smith ").unwrap(), "alice smith");
+
+    let key = [9u8; 32];
+    assert_eq!(fingerprint(&key).len(), 8);
+    assert_eq!(display_name("alice", &key), format!("alice~{}", fingerprint(&key)));
+}
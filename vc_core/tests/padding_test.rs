@@ -0,0 +1,56 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use vc_core::state::padding::{pad, unpad, PaddingPolicy};
+use vc_core::state::secure_session::{SecureSession, SessionRole};
+
+fn session_pair() -> (SecureSession, SecureSession) {
+    let peer_key = VerifyingKey::from(&SigningKey::from_bytes(&[3u8; 32]));
+    let session_key = [42u8; 32];
+    (
+        SecureSession::new(SessionRole::Client, session_key, peer_key),
+        SecureSession::new(SessionRole::Host, session_key, peer_key),
+    )
+}
+
+#[test]
+fn padding_round_trips_for_every_policy() {
+    let policies = [
+        PaddingPolicy::None,
+        PaddingPolicy::Buckets,
+        PaddingPolicy::Fixed(1200),
+    ];
+
+    for policy in policies {
+        for len in [0usize, 1, 63, 64, 200, 1199, 1200, 5000] {
+            // Trailing zeros in the payload must survive unpadding
+            let mut msg = vec![0xAB; len];
+            if len > 1 {
+                msg[len - 1] = 0;
+            }
+            let padded = pad(&msg, policy);
+            assert_eq!(unpad(&padded).unwrap(), msg.as_slice(), "{:?} len {}", policy, len);
+        }
+    }
+}
+
+#[test]
+fn buckets_hide_exact_lengths() {
+    let (mut sender, mut receiver) = session_pair();
+    sender.set_padding(PaddingPolicy::Buckets);
+
+    let short = sender.encrypt(b"hi");
+    let longer = sender.encrypt(b"hello there, how are you?");
+    assert_eq!(short.len(), longer.len());
+
+    // Receiver does not need to know the sender's policy
+    assert_eq!(receiver.decrypt(&short).unwrap(), b"hi");
+    assert_eq!(receiver.decrypt(&longer).unwrap(), b"hello there, how are you?");
+}
+
+#[test]
+fn fixed_mtu_pads_to_multiples() {
+    assert_eq!(pad(b"abc", PaddingPolicy::Fixed(1200)).len(), 1200);
+    assert_eq!(pad(&[1u8; 1200], PaddingPolicy::Fixed(1200)).len(), 2400);
+    assert_eq!("mtu:1200".parse::<PaddingPolicy>().unwrap(), PaddingPolicy::Fixed(1200));
+    assert!("mtu:0".parse::<PaddingPolicy>().is_err());
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use vc_core::protocol::presence::{clamp_status_text, PresenceStatus, RateLimiter, TypingNotifier, MAX_STATUS_TEXT};
+
+#[test]
+fn rate_limiter_allows_burst_then_refills() {
+    let mut limiter = RateLimiter::new(2, Duration::from_millis(100));
+    assert!(limiter.allow());
+    assert!(limiter.allow());
+    assert!(!limiter.allow());
+    assert!(limiter.retry_after() > Duration::ZERO);
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(limiter.allow());
+}
+
+#[test]
+fn typing_notifier_sends_start_once_and_stop_on_submit() {
+    let mut typing = TypingNotifier::new();
+    assert_eq!(typing.keystroke(), Some(true));
+    assert_eq!(typing.keystroke(), None);
+    assert_eq!(typing.poll(), None);
+    assert_eq!(typing.submitted(), Some(false));
+    // Nothing to stop when no start was sent
+    assert_eq!(typing.submitted(), None);
+}
+
+#[test]
+fn presence_parses_and_clamps_text() {
+    assert_eq!("DND".parse::<PresenceStatus>().unwrap(), PresenceStatus::DoNotDisturb);
+    assert_eq!("back".parse::<PresenceStatus>().unwrap(), PresenceStatus::Online);
+    assert!("sleeping".parse::<PresenceStatus>().is_err());
+
+    let long = "é".repeat(MAX_STATUS_TEXT * 2);
+    assert_eq!(clamp_status_text(&long).chars().count(), MAX_STATUS_TEXT);
+}
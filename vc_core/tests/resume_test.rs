@@ -0,0 +1,118 @@
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use vc_core::net::secure_stream::{SecureStream, SecureStreamError};
+use vc_core::protocol::resume::{resume_as_client, resume_as_host};
+use vc_core::state::secure_session::{SecureSession, SessionRole};
+
+fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (host, _) = listener.accept().unwrap();
+    (client, host)
+}
+
+fn stream_pair() -> (SecureStream, SecureStream) {
+    let client_id = VerifyingKey::from(&SigningKey::from_bytes(&[1u8; 32]));
+    let host_id = VerifyingKey::from(&SigningKey::from_bytes(&[2u8; 32]));
+    let (client_tcp, host_tcp) = tcp_pair();
+
+    (
+        SecureStream::new(client_tcp, SecureSession::new(SessionRole::Client, [9u8; 32], host_id)),
+        SecureStream::new(host_tcp, SecureSession::new(SessionRole::Host, [9u8; 32], client_id)),
+    )
+}
+
+fn recv_blocking(stream: &mut SecureStream) -> Result<Vec<u8>, SecureStreamError> {
+    loop {
+        match stream.recv() {
+            Err(e) if e.is_timeout() => continue,
+            other => return other,
+        }
+    }
+}
+
+#[test]
+fn resume_resends_undelivered_frames() {
+    let (mut client, mut host) = stream_pair();
+
+    client.send(b"one").unwrap();
+    assert_eq!(recv_blocking(&mut host).unwrap(), b"one");
+
+    // Sent but never read by the host before the network drops
+    client.send(b"two").unwrap();
+    client.send(b"three").unwrap();
+
+    let client_ticket = client.resumption_ticket();
+    let host_ticket = host.resumption_ticket();
+    assert_eq!(client_ticket.ticket_id, host_ticket.ticket_id);
+
+    let (client_tcp, host_tcp) = tcp_pair();
+    let host_received = host.app_received();
+    let host_thread = thread::spawn(move || {
+        let resumed = resume_as_host(host_tcp, &host_ticket, host_received).unwrap();
+        host.resume(resumed).unwrap();
+        host
+    });
+
+    let resumed = resume_as_client(client_tcp, &client_ticket, client.app_received()).unwrap();
+    assert_eq!(resumed.peer_received, 1);
+    client.resume(resumed).unwrap();
+
+    let mut host = host_thread.join().unwrap();
+    assert_eq!(recv_blocking(&mut host).unwrap(), b"two");
+    assert_eq!(recv_blocking(&mut host).unwrap(), b"three");
+
+    // The resumed session carries on normally in both directions
+    host.send(b"welcome back").unwrap();
+    assert_eq!(recv_blocking(&mut client).unwrap(), b"welcome back");
+}
+
+#[test]
+fn resume_rejects_foreign_ticket() {
+    let (client, _) = stream_pair();
+    let (_, other_host) = {
+        let client_id = VerifyingKey::from(&SigningKey::from_bytes(&[1u8; 32]));
+        let (a, b) = tcp_pair();
+        (a, SecureStream::new(b, SecureSession::new(SessionRole::Host, [5u8; 32], client_id)))
+    };
+
+    let client_ticket = client.resumption_ticket();
+    let host_ticket = other_host.resumption_ticket();
+
+    let (client_tcp, host_tcp) = tcp_pair();
+    let host_thread = thread::spawn(move || resume_as_host(host_tcp, &host_ticket, 0).is_err());
+
+    assert!(resume_as_client(client_tcp, &client_ticket, 0).is_err());
+    assert!(host_thread.join().unwrap());
+}
+
+#[test]
+fn oversized_frame_is_not_queued_for_resume() {
+    let (mut client, mut host) = stream_pair();
+
+    assert!(matches!(
+        client.send(&vec![0u8; u16::MAX as usize]),
+        Err(SecureStreamError::FrameTooLarge)
+    ));
+    client.send(b"after").unwrap();
+
+    let client_ticket = client.resumption_ticket();
+    let host_ticket = host.resumption_ticket();
+    let (client_tcp, host_tcp) = tcp_pair();
+    let host_thread = thread::spawn(move || {
+        let resumed = resume_as_host(host_tcp, &host_ticket, 0).unwrap();
+        host.resume(resumed).unwrap();
+        host
+    });
+
+    // The host read nothing, so everything queued is resent
+    let resumed = resume_as_client(client_tcp, &client_ticket, 0).unwrap();
+    assert_eq!(resumed.peer_received, 0);
+    client.resume(resumed).unwrap();
+
+    let mut host = host_thread.join().unwrap();
+    assert_eq!(recv_blocking(&mut host).unwrap(), b"after");
+}
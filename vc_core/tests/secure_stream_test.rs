@@ -0,0 +1,130 @@
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use vc_core::net::frame::CloseReason;
+use vc_core::net::keepalive::KeepaliveConfig;
+use vc_core::net::secure_stream::{SecureStream, SecureStreamError};
+use vc_core::state::secure_session::{SecureSession, SessionRole};
+
+// Two SecureStreams joined over loopback TCP with the same session key
+fn stream_pair() -> (SecureStream, SecureStream) {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let peer_key = VerifyingKey::from(&SigningKey::from_bytes(&secret));
+    let session_key = [7u8; 32];
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client_tcp = TcpStream::connect(addr).unwrap();
+    let (host_tcp, _) = listener.accept().unwrap();
+
+    let client = SecureStream::new(
+        client_tcp,
+        SecureSession::new(SessionRole::Client, session_key, peer_key),
+    );
+    let host = SecureStream::new(
+        host_tcp,
+        SecureSession::new(SessionRole::Host, session_key, peer_key),
+    );
+    (client, host)
+}
+
+// Keep calling recv until something other than a read timeout happens
+fn recv_blocking(stream: &mut SecureStream) -> Result<Vec<u8>, SecureStreamError> {
+    loop {
+        match stream.recv() {
+            Err(e) if e.is_timeout() => continue,
+            other => return other,
+        }
+    }
+}
+
+#[test]
+fn close_frame_carries_reason() {
+    let (mut client, mut host) = stream_pair();
+
+    client.send(b"bye soon").unwrap();
+    client.close(CloseReason::UserLeft).unwrap();
+
+    assert_eq!(recv_blocking(&mut host).unwrap(), b"bye soon");
+    match recv_blocking(&mut host) {
+        Err(SecureStreamError::Closed(CloseReason::UserLeft)) => {}
+        other => panic!("expected close frame, got {:?}", other),
+    }
+
+    // Host answered with its own close, so the client sees a clean end too
+    match recv_blocking(&mut client) {
+        Err(SecureStreamError::Closed(CloseReason::UserLeft)) => {}
+        other => panic!("expected close reply, got {:?}", other),
+    }
+
+    assert!(matches!(
+        client.send(b"too late"),
+        Err(SecureStreamError::Closed(_))
+    ));
+}
+
+#[test]
+fn dropped_connection_is_unexpected_eof() {
+    let (client, mut host) = stream_pair();
+
+    drop(client);
+
+    match recv_blocking(&mut host) {
+        Err(SecureStreamError::UnexpectedEof) => {}
+        other => panic!("expected connection loss, got {:?}", other),
+    }
+}
+
+#[test]
+fn keepalive_pings_measure_rtt() {
+    let (mut client, mut host) = stream_pair();
+
+    client.set_keepalive(KeepaliveConfig {
+        interval: Duration::from_millis(50),
+        dead_peer_timeout: Duration::from_secs(5),
+    });
+
+    // Host only needs to keep reading for pongs to go back
+    let host_thread = thread::spawn(move || loop {
+        match host.recv() {
+            Err(e) if e.is_timeout() => continue,
+            Err(SecureStreamError::Closed(_)) => break,
+            other => panic!("unexpected host result {:?}", other),
+        }
+    });
+
+    for _ in 0..3 {
+        match client.recv() {
+            Err(e) if e.is_timeout() => {}
+            other => panic!("pings must not surface from recv, got {:?}", other),
+        }
+    }
+
+    let rtt = client.rtt_stats().expect("no RTT sample recorded");
+    assert!(rtt.samples >= 1);
+    assert!(rtt.min <= rtt.smoothed + rtt.jitter * 4);
+
+    client.close(CloseReason::UserLeft).unwrap();
+    host_thread.join().unwrap();
+}
+
+#[test]
+fn silent_peer_times_out() {
+    let (mut client, _host) = stream_pair();
+
+    client.set_keepalive(KeepaliveConfig {
+        interval: Duration::from_secs(60),
+        dead_peer_timeout: Duration::from_millis(100),
+    });
+
+    match recv_blocking(&mut client) {
+        Err(SecureStreamError::PeerTimeout) => {}
+        other => panic!("expected dead peer, got {:?}", other),
+    }
+}
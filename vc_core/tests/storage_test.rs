@@ -0,0 +1,23 @@
+use vc_core::crypto::storage::StorageCipher;
+
+#[test]
+fn records_only_open_with_same_identity_purpose_and_aad() {
+    let mine = StorageCipher::from_identity(&[1u8; 32], "history");
+    let record = mine.seal(b"hello", b"log-a");
+
+    assert_eq!(mine.open(&record, b"log-a").unwrap(), b"hello");
+    assert!(mine.open(&record, b"log-b").is_none());
+    assert!(StorageCipher::from_identity(&[2u8; 32], "history").open(&record, b"log-a").is_none());
+    assert!(StorageCipher::from_identity(&[1u8; 32], "other").open(&record, b"log-a").is_none());
+}
+
+#[test]
+fn file_names_hide_scope() {
+    let cipher = StorageCipher::from_identity(&[1u8; 32], "history");
+    let name = cipher.file_name("room ABC-123");
+
+    assert_eq!(name, cipher.file_name("room ABC-123"));
+    assert_ne!(name, cipher.file_name("room ABC-124"));
+    assert!(!name.contains("ABC"));
+    assert_ne!(name, StorageCipher::from_identity(&[2u8; 32], "history").file_name("room ABC-123"));
+}
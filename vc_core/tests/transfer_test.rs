@@ -0,0 +1,44 @@
+use sha2::{Digest, Sha256};
+
+use vc_core::protocol::chat::{ChatMessage, FileChunk};
+use vc_core::protocol::transfer::{format_size, sanitize_filename, sha256_file, CHUNK_SIZE, MAX_FILENAME_LEN};
+
+#[test]
+fn filenames_cannot_escape_download_dir() {
+    assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+    assert_eq!(sanitize_filename("C:\\Users\\me\\report.pdf"), "report.pdf");
+    assert_eq!(sanitize_filename(".."), "download");
+    assert_eq!(sanitize_filename(".bashrc"), "bashrc");
+    assert_eq!(sanitize_filename("a\nb\u{7}.txt"), "a_b_.txt");
+    assert_eq!(sanitize_filename("   "), "download");
+    assert!(sanitize_filename(&"x".repeat(1000)).len() <= MAX_FILENAME_LEN);
+}
+
+#[test]
+fn file_hash_matches_sha256() {
+    let path = std::env::temp_dir().join(format!("vc_transfer_test_{}", std::process::id()));
+    let data = vec![7u8; 200_000];
+    std::fs::write(&path, &data).unwrap();
+
+    let hash = sha256_file(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(hash, <[u8; 32]>::from(Sha256::digest(&data)));
+}
+
+#[test]
+fn full_chunk_fits_in_a_frame() {
+    let chunk = ChatMessage::FileChunk(FileChunk {
+        id: u64::MAX,
+        offset: u64::MAX,
+        data: vec![0xff; CHUNK_SIZE],
+    });
+    // SecureStream frames carry at most u16::MAX bytes of ciphertext
+    assert!(chunk.to_bytes().unwrap().len() < u16::MAX as usize / 2);
+}
+
+#[test]
+fn sizes_are_human_readable() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+}